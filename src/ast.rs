@@ -0,0 +1,187 @@
+use std::fmt::{Display, Formatter};
+
+use num_rational::BigRational;
+
+use crate::error::Span;
+use crate::tokens::val::number::fmt_exact;
+use crate::tokens::{Associativity, BinOperator, UnOperator};
+
+/// A single parsed statement.
+/// Produced by `SyntCalc::parse()` and evaluated with `SyntCalc::eval()`
+#[derive(Clone, Debug)]
+pub enum Ast {
+    /// Plain expression, e.g. `1 + sin(pi)`
+    Expr(Node),
+    /// Variable declaration, e.g. `x = 2m`
+    VarDef { name: String, value: Node },
+    /// Function declaration, e.g. `f(x, y) = x^2 + y`
+    FnDef { name: String, args: Vec<String>, body: Node },
+    /// Expression printed in the given units, e.g. `3.2 km/h to m/s`
+    Convert { value: Node, target: Node },
+}
+
+/// A node of an expression tree
+#[derive(Clone, Debug)]
+pub struct Node {
+    pub kind: NodeKind,
+    /// Part of the input the node was parsed from
+    pub span: Span,
+}
+
+impl Node {
+    pub fn new(kind: NodeKind, span: Span) -> Self {
+        Node { kind, span }
+    }
+
+    /// Moves the spans of the node and its children by `offset` bytes,
+    /// for statements parsed out of a longer program
+    pub(crate) fn shift(&mut self, offset: usize) {
+        self.span = Span::new(self.span.start + offset, self.span.end + offset);
+        match &mut self.kind {
+            NodeKind::Number { .. } | NodeKind::Bool(_) | NodeKind::Var(_) | NodeKind::Arg(_) => {}
+            NodeKind::Call { args: nodes, .. } | NodeKind::Array(nodes) => {
+                nodes.iter_mut().for_each(|node| node.shift(offset));
+            }
+            NodeKind::UnOp { operand, .. } => operand.shift(offset),
+            NodeKind::BinOp { lhs, rhs, .. } | NodeKind::Interval { lo: lhs, hi: rhs } => {
+                lhs.shift(offset);
+                rhs.shift(offset);
+            }
+            NodeKind::Ternary { cond, if_true, if_false } => {
+                cond.shift(offset);
+                if_true.shift(offset);
+                if_false.shift(offset);
+            }
+        }
+    }
+}
+
+impl Ast {
+    /// Moves the spans of the statement by `offset` bytes, see [`Node::shift`]
+    pub(crate) fn shift(&mut self, offset: usize) {
+        match self {
+            Ast::Expr(node) | Ast::VarDef { value: node, .. } | Ast::FnDef { body: node, .. } => node.shift(offset),
+            Ast::Convert { value, target } => {
+                value.shift(offset);
+                target.shift(offset);
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum NodeKind {
+    /// Number literal, optionally immediately followed by a unit or a variable (e.g. `2km`, `3 x`)
+    /// The value is exact; it becomes a number of the current backend when evaluated.
+    /// `uncertainty` is set for `9.81 ± 0.02` and `9.81(2)`, the unit applies to the value with it
+    Number { value: BigRational, uncertainty: Option<BigRational>, unit: Option<String> },
+    /// `true` or `false`
+    Bool(bool),
+    Var(String),
+    /// Argument slot of a compiled user function body
+    Arg(usize),
+    Call { name: String, args: Vec<Node> },
+    /// `[a, b, c]`, the elements of a matrix are its rows
+    Array(Vec<Node>),
+    /// `[lo .. hi]`, the same as `interval(lo, hi)`
+    Interval { lo: Box<Node>, hi: Box<Node> },
+    UnOp { op: UnOperator, operand: Box<Node> },
+    BinOp { op: BinOperator, lhs: Box<Node>, rhs: Box<Node> },
+    /// `(cond ? if_true : if_false)`, also written as `if(cond, if_true, if_false)`.
+    /// Only the chosen branch is evaluated
+    Ternary {
+        cond: Box<Node>,
+        if_true: Box<Node>,
+        if_false: Box<Node>,
+    },
+}
+
+/// Prints a node, wrapping it in parentheses if it is an operation
+/// that would otherwise bind looser than its parent
+fn fmt_operand(f: &mut Formatter<'_>, node: &Node, parent: &BinOperator, is_rhs: bool) -> std::fmt::Result {
+    let needs_parens = match &node.kind {
+        NodeKind::BinOp { op, .. } => {
+            op.get_precedence() < parent.get_precedence()
+                || op.get_precedence() == parent.get_precedence()
+                    && (parent.get_associativity() == Associativity::Left) == is_rhs
+        }
+        // `(!a) == b` is not `!a == b`, which is `!(a == b)`
+        NodeKind::UnOp { op, .. } => op.get_precedence() < parent.get_precedence(),
+        _ => false,
+    };
+    if needs_parens {
+        write!(f, "({})", node)
+    } else {
+        write!(f, "{}", node)
+    }
+}
+
+impl Display for Node {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.kind)
+    }
+}
+
+impl Display for NodeKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NodeKind::Number { value, uncertainty, unit } => {
+                write!(f, "{}", fmt_exact(value))?;
+                if let Some(uncertainty) = uncertainty {
+                    write!(f, " ± {}", fmt_exact(uncertainty))?;
+                }
+                if let Some(unit) = unit {
+                    write!(f, "{}", unit)?;
+                }
+                Ok(())
+            }
+            NodeKind::Bool(b) => write!(f, "{}", b),
+            NodeKind::Var(name) => write!(f, "{}", name),
+            NodeKind::Arg(index) => write!(f, "${}", index),
+            NodeKind::Call { name, args } => {
+                write!(f, "{}(", name)?;
+                for (i, arg) in args.iter().enumerate() {
+                    if i != 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", arg)?;
+                }
+                write!(f, ")")
+            }
+            NodeKind::Array(elements) => {
+                write!(f, "[")?;
+                for (i, element) in elements.iter().enumerate() {
+                    if i != 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", element)?;
+                }
+                write!(f, "]")
+            }
+            NodeKind::Interval { lo, hi } => write!(f, "[{} .. {}]", lo, hi),
+            NodeKind::UnOp { op, operand } => match operand.kind {
+                NodeKind::BinOp { .. } | NodeKind::UnOp { .. } => write!(f, "{}({})", op, operand),
+                _ => write!(f, "{}{}", op, operand),
+            },
+            NodeKind::BinOp { op, lhs, rhs } => {
+                fmt_operand(f, lhs, op, false)?;
+                write!(f, " {} ", op)?;
+                fmt_operand(f, rhs, op, true)
+            }
+            NodeKind::Ternary { cond, if_true, if_false } => {
+                write!(f, "({} ? {} : {})", cond, if_true, if_false)
+            }
+        }
+    }
+}
+
+impl Display for Ast {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Ast::Expr(node) => write!(f, "{}", node),
+            Ast::VarDef { name, value } => write!(f, "{} = {}", name, value),
+            Ast::FnDef { name, args, body } => write!(f, "{}({}) = {}", name, args.join(", "), body),
+            Ast::Convert { value, target } => write!(f, "{} to {}", value, target),
+        }
+    }
+}
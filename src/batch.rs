@@ -0,0 +1,169 @@
+//! Non-interactive mode of the `syntcalc` binary: programs from `-e` arguments,
+//! files and a piped stdin are evaluated statement by statement
+
+use std::io::Read;
+use std::path::PathBuf;
+
+use syntcalc::SyntCalc;
+
+use crate::repl::print_error;
+
+pub const USAGE: &str = "\
+Usage: syntcalc [--keep-going] [-e EXPR]... [FILE]...
+
+Evaluates the statements of each -e argument and each file in order, printing the results.
+Statements are separated by newlines or ';', comments start with '#' or '//'.
+A file named - is stdin. With no arguments the interactive shell starts,
+unless stdin is not a terminal, in which case it is read as a file.
+
+Options:
+  -e, --eval EXPR   evaluate a statement
+  -k, --keep-going  do not stop at the first error, still exit with 1 at the end
+  -h, --help        print this help";
+
+/// Where statements are read from
+#[derive(Debug, PartialEq)]
+pub enum Input {
+    Expr(String),
+    File(PathBuf),
+    Stdin,
+}
+
+/// Parsed command line arguments
+#[derive(Debug, Default, PartialEq)]
+pub struct Args {
+    pub inputs: Vec<Input>,
+    pub keep_going: bool,
+    pub help: bool,
+}
+
+impl Args {
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        let mut ret = Args::default();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-e" | "--eval" => {
+                    let expr = args.next().ok_or_else(|| format!("{} needs an expression", arg))?;
+                    ret.inputs.push(Input::Expr(expr));
+                }
+                "-k" | "--keep-going" => ret.keep_going = true,
+                "-h" | "--help" => ret.help = true,
+                "-" => ret.inputs.push(Input::Stdin),
+                _ if arg.starts_with('-') => return Err(format!("Unknown option: '{}'", arg)),
+                _ => ret.inputs.push(Input::File(PathBuf::from(arg))),
+            }
+        }
+        Ok(ret)
+    }
+}
+
+/// Start of the first line that is `exit()`, which ends a program
+fn exit_line(text: &str) -> Option<usize> {
+    let mut start = 0;
+    for line in text.split_inclusive('\n') {
+        if line.trim() == "exit()" {
+            return Some(start);
+        }
+        start += line.len();
+    }
+    None
+}
+
+/// Evaluates statements and prints their results
+pub struct Batch<'a> {
+    sc: &'a mut SyntCalc,
+    keep_going: bool,
+    failed: bool,
+}
+
+impl<'a> Batch<'a> {
+    pub fn new(sc: &'a mut SyntCalc, keep_going: bool) -> Self {
+        Batch { sc, keep_going, failed: false }
+    }
+
+    /// Evaluates the inputs in order. Stops at the first error unless `keep_going` is set,
+    /// or at `exit()`. Returns whether there were no errors
+    pub fn run(mut self, inputs: &[Input]) -> bool {
+        for input in inputs {
+            let go_on = match input {
+                Input::Expr(expr) => self.eval_source(None, expr),
+                Input::File(path) => match std::fs::read_to_string(path) {
+                    Ok(text) => self.eval_source(Some(&path.display().to_string()), &text),
+                    Err(e) => self.fail(&format!("Can not read {}: {}", path.display(), e)),
+                },
+                Input::Stdin => {
+                    let mut text = String::new();
+                    match std::io::stdin().read_to_string(&mut text) {
+                        Ok(_) => self.eval_source(Some("<stdin>"), &text),
+                        Err(e) => self.fail(&format!("Can not read stdin: {}", e)),
+                    }
+                }
+            };
+            if !go_on {
+                break;
+            }
+        }
+        !self.failed
+    }
+
+    /// Evaluates a program; errors are prefixed with `name:line:`.
+    /// Returns whether to go on with the next input
+    fn eval_source(&mut self, name: Option<&str>, text: &str) -> bool {
+        let end = exit_line(text);
+        let program = &text[..end.unwrap_or(text.len())];
+        for result in self.sc.eval_statements(program) {
+            match result {
+                Ok(Some(val)) => println!("{}", val),
+                Ok(None) => {}
+                Err(e) => {
+                    if let Some(name) = name {
+                        let line = program[..e.span().start.min(program.len())].matches('\n').count() + 1;
+                        eprintln!("{}:{}:", name, line);
+                    }
+                    print_error(&e, program);
+                    self.failed = true;
+                    if !self.keep_going {
+                        return false;
+                    }
+                }
+            }
+        }
+        end.is_none()
+    }
+
+    fn fail(&mut self, message: &str) -> bool {
+        eprintln!("{}", message);
+        self.failed = true;
+        self.keep_going
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn batch() {
+        let args = |args: &[&str]| Args::parse(args.iter().map(|a| a.to_string()));
+        assert_eq!(args(&["-k", "-e", "1+2", "calc.txt", "-"]).unwrap(), Args {
+            inputs: vec![Input::Expr("1+2".to_string()), Input::File(PathBuf::from("calc.txt")), Input::Stdin],
+            keep_going: true,
+            help: false,
+        });
+        assert!(args(&["-e"]).is_err());
+        assert!(args(&["--frobnicate"]).is_err());
+
+        let mut sc = SyntCalc::default();
+        let exprs = |exprs: &[&str]| exprs.iter().map(|e| Input::Expr(e.to_string())).collect::<Vec<_>>();
+        assert!(Batch::new(&mut sc, false).run(&exprs(&["x = 2", "", "x + 1"])));
+        assert!(!Batch::new(&mut sc, false).run(&exprs(&["y", "z = 3"])));
+        assert!(sc.eval_statement("z").is_err());
+        assert!(!Batch::new(&mut sc, true).run(&exprs(&["y", "z = 3"])));
+        assert!(sc.eval_statement("z").is_ok());
+        assert!(Batch::new(&mut sc, false).run(&exprs(&["exit()", "y"])));
+        assert!(Batch::new(&mut sc, false).run(&exprs(&["w = 1; w + 1 # two\nexit()\ny"])));
+        assert!(sc.eval_statement("w").is_ok());
+        assert!(!Batch::new(&mut sc, false).run(&[Input::File(PathBuf::from("/nonexistent"))]));
+    }
+}
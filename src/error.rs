@@ -0,0 +1,183 @@
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+
+use crate::tokens::suggest::did_you_mean;
+use crate::tokens::val::{ValComputeError, ValComputeErrorType};
+
+/// Byte range of the input a node or an error refers to
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Span { start, end }
+    }
+}
+
+impl From<pest::Span<'_>> for Span {
+    fn from(span: pest::Span<'_>) -> Self {
+        Span::new(span.start(), span.end())
+    }
+}
+
+/// Any error produced while parsing or evaluating a statement.
+/// Every variant carries the span of the input it was caused by
+#[derive(Clone, Debug, PartialEq)]
+pub enum CalcError {
+    Syntax { message: String, span: Span },
+    /// `suggestions` holds the closest known names, best first
+    UnknownVariable { name: String, suggestions: Vec<String>, span: Span },
+    UnknownFunction { name: String, suggestions: Vec<String>, span: Span },
+    ArityMismatch { name: String, expected: usize, found: usize, span: Span },
+    /// E. g. adding meters to seconds
+    DimensionMismatch { message: String, span: Span },
+    /// Math error like taking a logarithm of a negative number
+    Domain { message: String, span: Span },
+    DivisionByZero { message: String, span: Span },
+    /// Arrays of shapes that do not fit the operation
+    ShapeMismatch { message: String, span: Span },
+    /// Boolean where a number is expected, or the other way around
+    TypeMismatch { message: String, span: Span },
+    /// Evaluation error that fits none of the other variants
+    Other { message: String, span: Span },
+    /// Nested subexpressions and user function calls exceeded `EvalLimits::max_depth`
+    DepthLimitExceeded { limit: usize, span: Span },
+    /// Brackets, calls and operators nested deeper than `EvalLimits::max_nesting`
+    NestingLimitExceeded { limit: usize, span: Span },
+    /// Evaluation took more than `EvalLimits::max_steps` steps
+    StepLimitExceeded { limit: u64, span: Span },
+    /// Evaluation took longer than `EvalLimits::timeout`
+    TimeLimitExceeded { span: Span },
+}
+
+impl CalcError {
+    pub fn span(&self) -> Span {
+        use CalcError::*;
+        match self {
+            Syntax { span, .. }
+            | UnknownVariable { span, .. }
+            | UnknownFunction { span, .. }
+            | ArityMismatch { span, .. }
+            | DimensionMismatch { span, .. }
+            | Domain { span, .. }
+            | DivisionByZero { span, .. }
+            | ShapeMismatch { span, .. }
+            | TypeMismatch { span, .. }
+            | Other { span, .. }
+            | DepthLimitExceeded { span, .. }
+            | NestingLimitExceeded { span, .. }
+            | StepLimitExceeded { span, .. }
+            | TimeLimitExceeded { span } => *span,
+        }
+    }
+
+    /// Moves the error to another span,
+    /// e. g. from inside a function body to the call of that function
+    pub(crate) fn at(mut self, new_span: Span) -> Self {
+        use CalcError::*;
+        match &mut self {
+            Syntax { span, .. }
+            | UnknownVariable { span, .. }
+            | UnknownFunction { span, .. }
+            | ArityMismatch { span, .. }
+            | DimensionMismatch { span, .. }
+            | Domain { span, .. }
+            | DivisionByZero { span, .. }
+            | ShapeMismatch { span, .. }
+            | TypeMismatch { span, .. }
+            | Other { span, .. }
+            | DepthLimitExceeded { span, .. }
+            | NestingLimitExceeded { span, .. }
+            | StepLimitExceeded { span, .. }
+            | TimeLimitExceeded { span } => *span = new_span,
+        }
+        self
+    }
+
+    pub(crate) fn from_val_error(e: ValComputeError, span: Span) -> Self {
+        let message = e.get_desc().to_string();
+        match e.get_type() {
+            ValComputeErrorType::DivisionByZero => CalcError::DivisionByZero { message, span },
+            ValComputeErrorType::IncompatibleUnits => CalcError::DimensionMismatch { message, span },
+            ValComputeErrorType::Shape => CalcError::ShapeMismatch { message, span },
+            ValComputeErrorType::Type => CalcError::TypeMismatch { message, span },
+            ValComputeErrorType::Domain => CalcError::Domain { message, span },
+            ValComputeErrorType::Other => CalcError::Other { message, span },
+        }
+    }
+
+    /// Formats the error with the offending part of `source` underlined
+    pub fn render(&self, source: &str) -> String {
+        let span = self.span();
+        let line_start = source[..span.start.min(source.len())].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = source[line_start..].find('\n').map_or(source.len(), |i| line_start + i);
+        let line = &source[line_start..line_end];
+        let offset = source[line_start..span.start.min(line_end)].chars().count();
+        let width = source[span.start.min(line_end)..span.end.clamp(span.start, line_end)].chars().count().max(1);
+        format!("{}\n{}{}\n{}", line, " ".repeat(offset), "^".repeat(width), self)
+    }
+}
+
+impl Display for CalcError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        use CalcError::*;
+        match self {
+            Syntax { message, .. } => write!(f, "Syntax error: {}", message),
+            UnknownVariable { name, suggestions, .. } => {
+                write!(f, "Variable {} not found{}", name, did_you_mean(suggestions))
+            }
+            UnknownFunction { name, suggestions, .. } => {
+                write!(f, "No such function: {}{}", name, did_you_mean(suggestions))
+            }
+            ArityMismatch { name, expected, found, .. } => write!(
+                f,
+                "Function {} takes {} argument(s), but {} were given",
+                name, expected, found
+            ),
+            DimensionMismatch { message, .. }
+            | Domain { message, .. }
+            | DivisionByZero { message, .. }
+            | ShapeMismatch { message, .. }
+            | TypeMismatch { message, .. }
+            | Other { message, .. } => {
+                write!(f, "{}", message)
+            }
+            DepthLimitExceeded { limit, .. } => write!(f, "Maximum evaluation depth of {} exceeded", limit),
+            NestingLimitExceeded { limit, .. } => write!(f, "Expression is nested deeper than {} levels", limit),
+            StepLimitExceeded { limit, .. } => write!(f, "Evaluation exceeded the limit of {} steps", limit),
+            TimeLimitExceeded { .. } => write!(f, "Evaluation exceeded the time limit"),
+        }
+    }
+}
+
+impl Error for CalcError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_underlines_span() {
+        let err = CalcError::UnknownVariable {
+            name: "foo".to_string(),
+            suggestions: vec!["fo".to_string()],
+            span: Span::new(10, 13),
+        };
+        assert_eq!(
+            err.render("x = 1\n1 + foo * 2"),
+            "1 + foo * 2\n    ^^^\nVariable foo not found; did you mean `fo`?"
+        );
+    }
+
+    #[test]
+    fn val_errors_keep_their_kind() {
+        let span = Span::new(1, 2);
+        let other = ValComputeError::new("oops".to_string(), ValComputeErrorType::Other);
+        assert_eq!(CalcError::from_val_error(other, span), CalcError::Other { message: "oops".to_string(), span });
+        let domain = ValComputeError::new("ln of 0".to_string(), ValComputeErrorType::Domain);
+        assert!(matches!(CalcError::from_val_error(domain, span), CalcError::Domain { .. }));
+    }
+}
@@ -1,6 +1,11 @@
+pub mod ast;
+pub mod error;
 pub mod parse;
+mod program;
+mod session;
 pub mod tokens;
 pub use parse::*;
+pub use error::{CalcError, Span};
 
 // #[cfg(test)]
 // mod test{
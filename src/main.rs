@@ -1,18 +1,36 @@
+mod batch;
+mod repl;
+
+use std::io::IsTerminal;
+
+use batch::{Args, Batch, Input, USAGE};
 use syntcalc as sc;
-use std::io;
+
 fn main() {
+    let args = match Args::parse(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(message) => {
+            eprintln!("{}\n\n{}", message, USAGE);
+            std::process::exit(2);
+        }
+    };
+    if args.help {
+        println!("{}", USAGE);
+        return;
+    }
     let mut parser = sc::SyntCalc::default();
-    let mut string = String::new();
-    loop {
-        io::stdin()
-            .read_line(&mut string)
-            .expect("Failed to read line");
-        if string.trim() == "exit()" {break;}
-
-        match parser.eval_str(&string) {
-            Ok(val) => println!("{}", val),
-            Err(e) => println!("{}", e),
+    if args.inputs.is_empty() && std::io::stdin().is_terminal() {
+        if let Err(e) = repl::run(&mut parser) {
+            eprintln!("{}", e);
+            std::process::exit(1);
         }
-        string.clear();
+        return;
+    }
+    let inputs = match args.inputs.is_empty() {
+        true => vec![Input::Stdin],
+        false => args.inputs,
+    };
+    if !Batch::new(&mut parser, args.keep_going).run(&inputs) {
+        std::process::exit(1);
     }
 }
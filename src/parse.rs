@@ -1,40 +1,108 @@
-use crate::tokens::val::{ValComputeError, ValComputeErrorType};
+use crate::ast::{Ast, Node, NodeKind};
+use crate::error::{CalcError, Span};
+use crate::program;
+use crate::tokens::val::base_units;
+use crate::tokens::val::number::{parse_literal, Number};
+use num_rational::BigRational;
 pub use crate::tokens::val::ValOpts;
 
-use super::tokens::{Val, BinOperator, UnOperator, Function};
+use super::tokens::{Val, BinOperator, BinOps, UnOperator, UnOps, Function};
 use super::tokens::token_builder::Builder;
-use pest::{self, Parser};
+use pest::{self, iterators::Pair, Parser};
 use pest_derive::Parser;
 use std::collections::VecDeque;
-use std::error::Error;
+use std::ops::Range;
 use std::fmt::{Display, Formatter};
-use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-#[derive(Debug)]
-pub struct ParseError{
-    desc: String,
+/// Limits that keep evaluation of hostile or runaway input from exhausting the stack or hanging.
+/// Steps and time are counted per evaluated statement.
+#[derive(Debug, Clone, Copy)]
+pub struct EvalLimits{
+    /// Maximum depth of the evaluation: every nested subexpression and every user function call
+    /// is a level, so deep expressions in recursive functions use it up together with the calls.
+    /// The default is safe for the 2MiB stack of a spawned thread in debug builds;
+    /// raising it may require a bigger stack
+    pub max_depth: usize,
+    /// Maximum number of evaluated expression nodes, none by default.
+    /// Tail calls do not deepen the call stack, so only this or `timeout` stops `f(x) = f(x)`
+    pub max_steps: Option<u64>,
+    pub timeout: Option<Duration>,
+    /// Maximum nesting of an expression: brackets, calls and chained operators (`1+1+1`) all nest.
+    /// Checked while parsing, as parsing and evaluation recurse as deep as the expression;
+    /// the default fits the 2 MiB stack of a spawned thread in debug builds
+    pub max_nesting: usize,
 }
 
-impl Display for ParseError{
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.desc)
+impl Default for EvalLimits{
+    fn default() -> Self {
+        EvalLimits{max_depth: 384, max_steps: None, timeout: None, max_nesting: 128}
+    }
+}
+
+/// Result of evaluating an expression in tail position of a function body
+enum Tail{
+    Done(Val),
+    /// Call of a user function that is yet to be evaluated
+    Call(Function, Vec<Val>),
+}
+
+/// Resources used up by the statement being evaluated
+struct EvalState{
+    depth: usize,
+    steps: u64,
+    deadline: Option<Instant>,
+}
+
+impl EvalState{
+    fn new(limits: &EvalLimits) -> Self{
+        EvalState{depth: 0, steps: 0, deadline: limits.timeout.map(|t| Instant::now() + t)}
+    }
+
+    fn step(&mut self, limits: &EvalLimits, span: Span) -> Result<(), CalcError>{
+        self.steps += 1;
+        if let Some(limit) = limits.max_steps.filter(|&max| self.steps > max) {
+            return Err(CalcError::StepLimitExceeded{limit, span});
+        }
+        // checking the clock on every step is needlessly slow
+        if self.steps.is_multiple_of(64) && self.deadline.is_some_and(|deadline| Instant::now() > deadline) {
+            return Err(CalcError::TimeLimitExceeded{span});
+        }
+        Ok(())
+    }
+
+    /// Takes a step into a node one level deeper
+    fn visit(&mut self, limits: &EvalLimits, span: Span) -> Result<(), CalcError>{
+        self.step(limits, span)?;
+        self.enter(limits, span)
+    }
+
+    /// Goes one level deeper into the evaluation, see `EvalLimits::max_depth`
+    fn enter(&mut self, limits: &EvalLimits, span: Span) -> Result<(), CalcError>{
+        if self.depth >= limits.max_depth {
+            return Err(CalcError::DepthLimitExceeded{limit: limits.max_depth, span});
+        }
+        self.depth += 1;
+        Ok(())
     }
 }
-impl Error for ParseError{}
 
 #[derive(Clone)]
 enum Expr  {
-    Val(Val),
-    UnOp(UnOperator),
-    BinOp(BinOperator),
+    /// Node with its height, the number of levels of the tree below and including it
+    Node(Node, usize),
+    /// Unary operators are prefixes, so their position is needed to get the node's span
+    UnOp(UnOperator, Span),
+    /// The span of a binary operator is only used to report malformed expressions
+    BinOp(BinOperator, Span),
 }
 
 impl Display for Expr {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         let str = match self{
-            Expr::Val(val) => format!("Val: {}", val), 
-            Expr::BinOp(op) => format!("BinOperator: {}", op),
-            Expr::UnOp(op) => format!("UnOperator: {}", op),
+            Expr::Node(node, _) => format!("Node: {}", node), 
+            Expr::BinOp(op, _) => format!("BinOperator: {}", op),
+            Expr::UnOp(op, _) => format!("UnOperator: {}", op),
         };
         write!(f, "{}", str)
     }
@@ -48,176 +116,654 @@ impl std::fmt::Debug for Expr {
 
 #[derive(Debug, Copy, Clone)]
 enum Op{
-    Un(UnOperator),
-    Bin(BinOperator),
+    Un(UnOperator, Span),
+    Bin(BinOperator, Span),
 }
 impl Op {
     fn get_precedence(&self) -> u32{
         match self{
-            Op::Un(op) => op.get_precedence(),
-            Op::Bin(op) => op.get_precedence(),
+            Op::Un(op, _) => op.get_precedence(),
+            Op::Bin(op, _) => op.get_precedence(),
         }
     }
     fn as_expr(self) -> Expr{
         match self{
-            Op::Un(op) => Expr::UnOp(op),
-            Op::Bin(op) => Expr::BinOp(op),
+            Op::Un(op, span) => Expr::UnOp(op, span),
+            Op::Bin(op, span) => Expr::BinOp(op, span),
         }
     }
 }
 
+/// Error of [`SyntCalc::eval_str`], the [`CalcError`] it comes from is its source
+#[derive(Debug)]
+pub struct ParseError{
+    desc: String,
+    error: CalcError,
+}
+
+impl ParseError {
+    pub fn error(&self) -> &CalcError{
+        &self.error
+    }
+}
+
+impl From<CalcError> for ParseError {
+    fn from(error: CalcError) -> Self{
+        Self{desc: error.to_string(), error}
+    }
+}
+
+impl Display for ParseError{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.desc)
+    }
+}
+impl std::error::Error for ParseError{
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)>{
+        Some(&self.error)
+    }
+}
+
 #[derive(Clone, Default)]
 pub struct SyntCalc {
     pub token_builder: Builder,
+    pub limits: EvalLimits,
 }
 
 /// Main class for synthcalc crate.
-/// Used to parse expressions and evaluate them with eval_str() function.
+/// Used to parse expressions and evaluate them with eval_statement() function.
+/// Parsing and evaluation can also be done separately with parse() and eval(),
+/// so that the same [`Ast`] can be evaluated many times.
 impl  SyntCalc {
     pub fn new () -> Self{
         Self{
             token_builder: Builder::new(), //ValOpts::default()),
+            limits: EvalLimits::default(),
         }
     }
+
+    /// Parses and evaluates an expression or a variable declaration.
+    /// Function declarations are still made but give an error, as they produce no value;
+    /// use [`SyntCalc::eval_statement`] for statements that may not have one
     pub fn eval_str(&mut self, expr: &str) -> Result<Val, ParseError>{
-        let parsed = match MathParser::parse(Rule::file, expr){
+        let val = self.eval_statement(expr)?.ok_or_else(|| CalcError::Other{
+            message: "Statement has no value".to_string(),
+            span: Span::new(0, expr.len()),
+        })?;
+        Ok(val)
+    }
+
+    /// Parses and evaluates a statement.
+    /// Returns `None` for statements that do not produce a value (function declarations)
+    pub fn eval_statement(&mut self, expr: &str) -> Result<Option<Val>, CalcError>{
+        let ast = self.parse(expr)?;
+        self.eval(&ast)
+    }
+
+    /// Parses a statement into an [`Ast`] without evaluating it
+    pub fn parse(&self, expr: &str) -> Result<Ast, CalcError>{
+        self.check_brackets(expr)?;
+        let mut parsed = match MathParser::parse(Rule::file, expr){
             Ok(parsed) => parsed,
-            Err(e) => return Err(ParseError{desc: format!("Parse error:\n{}", e)}),
+            Err(e) => {
+                let span = match e.location {
+                    pest::error::InputLocation::Pos(pos) => Span::new(pos, pos),
+                    pest::error::InputLocation::Span((start, end)) => Span::new(start, end),
+                };
+                return Err(CalcError::Syntax{message: e.variant.message().to_string(), span});
+            },
         };
-        self.eval_parsed(parsed)
+        let statement = parsed.next().unwrap().into_inner().next().unwrap();
+        self.build_statement(statement)
     }
 
-    fn eval_parsed(&mut self, parsed: pest::iterators::Pairs<Rule>) -> Result<Val, ParseError> {
-        let mut val_op_sequence = Vec::new();
-        for pair in parsed {
-            // println!("pair: {}", pair);
-            // println!("val_op_sequence: {:#?}\n", val_op_sequence);
-            match pair.as_rule() {
-                Rule::file => val_op_sequence.push(
-                    Expr::Val(self.eval_parsed(pair.into_inner())?)),
-                Rule::number => val_op_sequence.push(
-                    Expr::Val(self.token_builder.val_from_str(pair.as_str().trim()).unwrap())),
+    /// Parses a program: statements separated by newlines or `;`, with comments
+    /// from `#` or `//` to the end of the line.
+    /// A statement goes on at the next line while a bracket is open.
+    /// Returns a result for every statement, spans point into the whole program
+    pub fn parse_program(&self, program: &str) -> Vec<Result<Ast, CalcError>>{
+        let stripped = program::strip_comments(program);
+        program::split_statements(&stripped).into_iter()
+            .map(|range| self.parse_part(&stripped, range))
+            .collect()
+    }
 
-                Rule::infix => val_op_sequence.push(
-                    Expr::BinOp(BinOperator::match_str(pair.as_str()).unwrap())),
-                Rule::prefix => val_op_sequence.push(
-                    Expr::UnOp(UnOperator::match_str(pair.as_str()).unwrap())),
+    /// Parses and evaluates the statements of a program in order, see [`SyntCalc::parse_program`].
+    /// Stops at the first error; statements before it stay in effect
+    pub fn eval_program(&mut self, program: &str) -> Result<Vec<Option<Val>>, CalcError>{
+        self.eval_statements(program).collect()
+    }
 
-                Rule::func => {
-                    let func = self.token_builder.function_from_str(pair.clone().into_inner().next().unwrap().as_str()).map_err(|e| ParseError{desc: e.to_string()})?;
-                    let val = func.compute(self.get_args_from_func_pair(&pair).unwrap()).map_err(|e| ParseError{desc: e.to_string()})?;
-                    val_op_sequence.push(Expr::Val(val));
-                },
-                Rule::expr => val_op_sequence.push(
-                    Expr::Val(self.eval_parsed(pair.into_inner())?)),
-                Rule::var => val_op_sequence.push(
-                    Expr::Val(match self.token_builder.get_var_val(pair.as_str()){
-                        Ok(val) => val,
-                        Err(e) => return Err(ParseError{desc: e.to_string()}),
-                    })),
-                Rule::ternary => {
-                    let mut inner = pair.into_inner();
-                    let lhs = self.eval_parsed(inner.next().unwrap().into_inner())?;
-                    let cond_type = inner.next().unwrap();
-                    let rhs = self.eval_parsed(inner.next().unwrap().into_inner())?;
-                    let if_true = inner.next().unwrap();
-                    let if_false = inner.next().unwrap();
-                    let flag = match cond_type.as_rule(){
-                        Rule::greater => lhs > rhs,
-                        Rule::less => lhs < rhs,
-                        Rule::equal => lhs == rhs,
-                        Rule::greaterEqual => lhs >= rhs,
-                        Rule::lessEqual => lhs <= rhs,
-                        Rule::notEqual => lhs != rhs,
-                        _ => unreachable!(),
-                    };
-                    if flag{
-                        val_op_sequence.push(Expr::Val(self.eval_parsed(if_true.into_inner())?));
-                    }else{
-                        val_op_sequence.push(Expr::Val(self.eval_parsed(if_false.into_inner())?));
+    /// Evaluates the statements of a program one by one as the iterator is advanced.
+    /// Unlike [`SyntCalc::eval_program`], it can go on after an error
+    pub fn eval_statements(&mut self, program: &str) -> impl Iterator<Item = Result<Option<Val>, CalcError>> + '_{
+        let stripped = program::strip_comments(program);
+        let ranges = program::split_statements(&stripped);
+        // each statement is parsed only after the previous ones are evaluated,
+        // as parsing depends on the names defined so far
+        ranges.into_iter().map(move |range| {
+            let ast = self.parse_part(&stripped, range)?;
+            self.eval(&ast)
+        })
+    }
+
+    /// Parses a statement that is a part of a longer program, keeping the spans relative to the program
+    fn parse_part(&self, program: &str, range: Range<usize>) -> Result<Ast, CalcError>{
+        let offset = range.start;
+        match self.parse(&program[range]) {
+            Ok(mut ast) => {
+                ast.shift(offset);
+                Ok(ast)
+            },
+            Err(e) => {
+                let span = e.span();
+                Err(e.at(Span::new(span.start + offset, span.end + offset)))
+            },
+        }
+    }
+
+    /// Evaluates a parsed statement.
+    /// Declarations are added to the calculator's state;
+    /// variable declarations return the assigned value.
+    pub fn eval(&mut self, ast: &Ast) -> Result<Option<Val>, CalcError>{
+        match ast {
+            Ast::Expr(node) => self.eval_node(node).map(Some),
+            Ast::VarDef { name, value } => {
+                let val = self.eval_node(value)?;
+                self.token_builder.val_alias.borrow_mut().define(name.clone(), val.clone());
+                Ok(Some(val))
+            },
+            Ast::FnDef { name, args, body } => {
+                let func = Function::compiled(Self::compile_body(body, args), args.len() as u32)
+                    .with_definition(ast.clone());
+                self.token_builder.func_alias.borrow_mut().define(name.clone(), func);
+                Ok(None)
+            },
+            Ast::Convert { value, target } => {
+                let val = self.eval_node(value)?;
+                let affine = match &target.kind {
+                    NodeKind::Var(name) => self.token_builder.val_alias.borrow().get_affine(name),
+                    _ => None,
+                };
+                let (target_val, offset) = match affine {
+                    Some((scale, zero)) => (Val::from_number(scale, base_units::K, self.token_builder.val_opts.clone()).absolute(), zero),
+                    None => (self.eval_node(target)?, Number::exact_from_f64(0.)),
+                };
+                // `km / h` is printed as `km/h`
+                let name = target.to_string().replace(' ', "");
+                val.convert_to(&target_val, offset, &name)
+                    .map(Some)
+                    .map_err(|e| CalcError::from_val_error(e, target.span))
+            },
+        }
+    }
+
+    /// Replaces references to the arguments with their slots.
+    /// Other names are left as they are and are resolved at call time
+    fn compile_body(node: &Node, args: &[String]) -> Node {
+        let slot = |name: &str| args.iter().position(|arg| arg == name);
+        let compile = |node: &Node| Box::new(Self::compile_body(node, args));
+        let kind = match &node.kind {
+            NodeKind::Var(name) => match slot(name) {
+                Some(i) => NodeKind::Arg(i),
+                None => node.kind.clone(),
+            },
+            NodeKind::Number { value, uncertainty, unit: Some(unit) } => match slot(unit) {
+                Some(i) => {
+                    let unit_span = Span::new(node.span.end - unit.len(), node.span.end);
+                    let number = NodeKind::Number { value: value.clone(), uncertainty: uncertainty.clone(), unit: None };
+                    NodeKind::BinOp {
+                        op: BinOperator::match_str("*").unwrap(),
+                        lhs: Box::new(Node::new(number, node.span)),
+                        rhs: Box::new(Node::new(NodeKind::Arg(i), unit_span)),
                     }
                 },
-                Rule::add_var => {
-                    let mut inner = pair.into_inner();
-                    let name = inner.next().unwrap().as_str().to_string();
-                    let val = self.eval_parsed(inner.next().unwrap().into_inner())?;
-                    self.token_builder.val_alias.borrow_mut().add_alias(name, val.clone());
-                },
-                Rule::add_function => {
-                    let inner = pair.into_inner();
-                    let mut names = Vec::new();
-                    let mut body = None;
-                    for pair in inner {
-                        match pair.as_rule() {
-                            Rule::name => {
-                                names.push(pair);
-                            },
-                            Rule::expr => {
-                                body = Some(pair);
-                            },
-                            _ => unreachable!(),
-                        }
-                    }
+                None => node.kind.clone(),
+            },
+            NodeKind::Number { .. } | NodeKind::Bool(_) | NodeKind::Arg(_) => node.kind.clone(),
+            NodeKind::Call { name, args: call_args } => NodeKind::Call {
+                name: name.clone(),
+                args: call_args.iter().map(|arg| Self::compile_body(arg, args)).collect(),
+            },
+            NodeKind::Array(elements) => NodeKind::Array(elements.iter().map(|e| Self::compile_body(e, args)).collect()),
+            NodeKind::Interval { lo, hi } => NodeKind::Interval { lo: compile(lo), hi: compile(hi) },
+            NodeKind::UnOp { op, operand } => NodeKind::UnOp { op: *op, operand: compile(operand) },
+            NodeKind::BinOp { op, lhs, rhs } => NodeKind::BinOp { op: *op, lhs: compile(lhs), rhs: compile(rhs) },
+            NodeKind::Ternary { cond, if_true, if_false } => NodeKind::Ternary {
+                cond: compile(cond),
+                if_true: compile(if_true),
+                if_false: compile(if_false),
+            },
+        };
+        Node::new(kind, node.span)
+    }
 
-                    let body = body.unwrap().as_str().to_string();
-                    let fn_name = names[0].as_str().to_string();
-                    let argc = names.len() - 1;
-                    let sc = self.clone();
-
-                    let names: Vec<String> = names.iter().skip(1)
-                        .map(|name| name.as_str().to_string())
-                        .collect();
-
-                    let lambda = move |vals: Vec<Val>|{
-                        let arg_names = names.clone();
-                        let body = body.clone();
-                        let mut sc = sc.clone();
-                        for i in 0..arg_names.len() {
-                            sc.token_builder.local_val_alias.add_alias(arg_names[i].clone(), vals[i].clone())
-                        }
-                        sc.eval_str(&body).map_err(|e| ValComputeError::new(e.desc, ValComputeErrorType::Other))
-                    };
+    fn eval_node(&self, node: &Node) -> Result<Val, CalcError>{
+        self.eval_in_frame(node, &[], &mut EvalState::new(&self.limits))
+    }
+
+    /// Evaluates a node; `frame` holds the values of argument slots.
+    /// Bulky cases live in separate functions to keep this recursive frame small
+    fn eval_in_frame(&self, node: &Node, frame: &[Val], state: &mut EvalState) -> Result<Val, CalcError>{
+        state.visit(&self.limits, node.span)?;
+        let ret = match &node.kind {
+            NodeKind::Call { name, args } => self.eval_call(name, args, node.span, frame, state),
+            NodeKind::Array(elements) => self.eval_array(elements, node.span, frame, state),
+            NodeKind::Interval { lo, hi } => self.eval_interval(lo, hi, node.span, frame, state),
+            NodeKind::UnOp { op, operand } => self.eval_un_op(op, operand, node.span, frame, state),
+            NodeKind::BinOp { op, lhs, rhs } => self.eval_bin_op(op, lhs, rhs, node.span, frame, state),
+            NodeKind::Ternary { cond, if_true, if_false } => self.eval_ternary(cond, if_true, if_false, frame, state),
+            _ => self.eval_leaf(node, frame),
+        };
+        state.depth -= 1;
+        ret
+    }
 
-                    let func = Function::new(Arc::new(lambda), argc as u32);
+    /// Evaluates a node without subexpressions
+    fn eval_leaf(&self, node: &Node, frame: &[Val]) -> Result<Val, CalcError>{
+        match &node.kind {
+            NodeKind::Number { value, uncertainty, unit } => self.eval_number(value, uncertainty.as_ref(), unit.as_deref(), node.span),
+            NodeKind::Bool(b) => Ok(Val::boolean(*b, self.token_builder.val_opts.clone())),
+            NodeKind::Var(name) => self.get_var(name, node.span),
+            NodeKind::Arg(index) => Ok(frame[*index].clone()),
+            _ => unreachable!("only leaves are evaluated here"),
+        }
+    }
+
+    fn eval_ternary(&self, cond: &Node, if_true: &Node, if_false: &Node, frame: &[Val], state: &mut EvalState) -> Result<Val, CalcError>{
+        let branch = self.choose_branch(cond, if_true, if_false, frame, state)?;
+        self.eval_in_frame(branch, frame, state)
+    }
+
+    fn eval_un_op(&self, op: &UnOperator, operand: &Node, span: Span, frame: &[Val], state: &mut EvalState) -> Result<Val, CalcError>{
+        if let Some(temperature) = self.negative_temperature(op, operand) {
+            return temperature;
+        }
+        let operand = self.eval_in_frame(operand, frame, state)?;
+        Self::apply_un_op(op, operand, span)
+    }
+
+    /// `-20degC` is twenty degrees below zero, not the negated absolute temperature of 20 °C
+    fn negative_temperature(&self, op: &UnOperator, operand: &Node) -> Option<Result<Val, CalcError>>{
+        let (UnOps::Neg, NodeKind::Number { value, uncertainty, unit: Some(unit) }) = (op.get_op_type(), &operand.kind) else {
+            return None;
+        };
+        self.token_builder.val_alias.borrow().get_affine(unit)?;
+        Some(self.eval_number(&-value, uncertainty.as_ref(), Some(unit), operand.span))
+    }
+
+    // operators are applied apart from the evaluation of their operands, which keeps its recursive frames small
+    fn apply_un_op(op: &UnOperator, operand: Val, span: Span) -> Result<Val, CalcError>{
+        op.compute(operand).map_err(|e| CalcError::from_val_error(e, span))
+    }
 
-                    self.token_builder.func_alias.borrow_mut().add_alias(fn_name, func);
+    /// `x != 0 && 1/x > 1` does not divide by zero
+    fn short_circuits(op: &BinOperator, lhs: &Val) -> bool{
+        matches!((op.get_op_type(), lhs.as_bool()), (BinOps::And, Some(false)) | (BinOps::Or, Some(true)))
+    }
+
+    fn apply_bin_op(op: &BinOperator, lhs: Val, rhs: Val, span: Span) -> Result<Val, CalcError>{
+        op.compute(lhs, rhs).map_err(|e| CalcError::from_val_error(e, span))
+    }
+
+    fn eval_array(&self, elements: &[Node], span: Span, frame: &[Val], state: &mut EvalState) -> Result<Val, CalcError>{
+        let mut vals = Vec::with_capacity(elements.len());
+        for element in elements {
+            vals.push(self.eval_in_frame(element, frame, state)?);
+        }
+        Val::array(vals, self.token_builder.val_opts.clone()).map_err(|e| CalcError::from_val_error(e, span))
+    }
+
+    fn eval_interval(&self, lo: &Node, hi: &Node, span: Span, frame: &[Val], state: &mut EvalState) -> Result<Val, CalcError>{
+        let lo = self.eval_in_frame(lo, frame, state)?;
+        let hi = self.eval_in_frame(hi, frame, state)?;
+        lo.interval(&hi).map_err(|e| CalcError::from_val_error(e, span))
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn eval_bin_op(&self, op: &BinOperator, lhs: &Node, rhs: &Node, span: Span, frame: &[Val], state: &mut EvalState) -> Result<Val, CalcError>{
+        let lhs = self.eval_in_frame(lhs, frame, state)?;
+        if Self::short_circuits(op, &lhs) {
+            return Ok(lhs);
+        }
+        let rhs = self.eval_in_frame(rhs, frame, state)?;
+        Self::apply_bin_op(op, lhs, rhs, span)
+    }
+
+    /// Evaluates the condition of a ternary operator and returns the branch to be evaluated
+    fn choose_branch<'a>(&self, cond: &Node, if_true: &'a Node, if_false: &'a Node,
+                         frame: &[Val], state: &mut EvalState) -> Result<&'a Node, CalcError>{
+        let val = self.eval_in_frame(cond, frame, state)?;
+        match val.as_bool() {
+            Some(true) => Ok(if_true),
+            Some(false) => Ok(if_false),
+            None => Err(CalcError::TypeMismatch{
+                message: format!("Condition should be a boolean, not {}", val),
+                span: cond.span,
+            }),
+        }
+    }
+
+    fn get_var(&self, name: &str, span: Span) -> Result<Val, CalcError>{
+        if self.token_builder.val_alias.borrow().get_affine(name).is_some() {
+            return Err(CalcError::Syntax{
+                message: format!("{0} is a temperature scale, it can only follow a number or be converted to; \
+                                  use delta_{0} for temperature differences", name.replace('°', "deg")),
+                span,
+            });
+        }
+        self.token_builder.get_var_val(name)
+            .map_err(|_| CalcError::UnknownVariable{
+                name: name.to_string(),
+                suggestions: self.token_builder.val_alias.borrow().suggest(name),
+                span,
+            })
+    }
+
+    /// Evaluates a literal. The unit applies to the value with its uncertainty,
+    /// so the uncertainty of `20.0(5) degC` is a temperature difference
+    fn eval_number(&self, value: &BigRational, uncertainty: Option<&BigRational>, unit: Option<&str>, span: Span) -> Result<Val, CalcError>{
+        let opts = &self.token_builder.val_opts;
+        let mut magn = opts.borrow().number(value.clone());
+        if let Some(uncertainty) = uncertainty {
+            magn = magn.plus_minus(&opts.borrow().number(uncertainty.clone()));
+        }
+        let affine = unit.and_then(|unit| self.token_builder.val_alias.borrow().get_affine(unit));
+        if let Some((scale, zero)) = affine {
+            let magn = magn.via_exact(|magn| Ok(magn.mul(&scale).add(&zero)))
+                .map_err(|e| CalcError::from_val_error(e, span))?;
+            return Ok(Val::from_number(magn, base_units::K, opts.clone()).absolute());
+        }
+        let val = Val::from_number(magn, base_units::D, opts.clone());
+        match unit {
+            // the unit is always at the end of the literal
+            Some(unit) => (val * self.get_var(unit, Span::new(span.end - unit.len(), span.end))?)
+                .map_err(|e| CalcError::from_val_error(e, span)),
+            None => Ok(val),
+        }
+    }
+
+    /// Evaluates a call. Calls of user functions in tail position of the body
+    /// are run in a loop instead of recursing, so tail recursion does not grow the stack.
+    /// Errors inside the body are reported at the call, as the body comes from another input
+    fn eval_call(&self, name: &str, args: &[Node], span: Span, frame: &[Val], state: &mut EvalState) -> Result<Val, CalcError>{
+        let (func, vals) = match self.call_tail(name, args, span, frame, state)? {
+            Tail::Done(val) => return Ok(val),
+            Tail::Call(func, vals) => (func, vals),
+        };
+        state.enter(&self.limits, span)?;
+        let res = self.run_function(func, vals, state).map_err(|e| e.at(span));
+        state.depth -= 1;
+        res
+    }
+
+    fn run_function(&self, mut func: Function, mut vals: Vec<Val>, state: &mut EvalState) -> Result<Val, CalcError>{
+        loop {
+            let mut node = func.get_body().expect("only user functions are called in tail position");
+            // walk down to the expression in tail position
+            let tail = loop {
+                match &node.kind {
+                    NodeKind::Ternary { cond, if_true, if_false } => {
+                        state.step(&self.limits, node.span)?;
+                        node = self.choose_branch(cond, if_true, if_false, &vals, state)?;
+                    },
+                    NodeKind::Call { name, args } => {
+                        state.step(&self.limits, node.span)?;
+                        break self.call_tail(name, args, node.span, &vals, state)?;
+                    },
+                    _ => return self.eval_in_frame(node, &vals, state),
+                }
+            };
+            match tail {
+                Tail::Done(val) => return Ok(val),
+                Tail::Call(next, next_vals) => {
+                    func = next;
+                    vals = next_vals;
                 },
-                Rule::EOI => break,
-                _ => todo!("unimplemented rule: {:?}", pair.as_rule()),
             }
         }
-        if val_op_sequence.is_empty() {
-            return Err(ParseError{desc: "".to_string()});
+    }
+
+    /// Evaluates the arguments of a call and computes it if the function is native
+    fn call_tail(&self, name: &str, args: &[Node], span: Span, frame: &[Val], state: &mut EvalState) -> Result<Tail, CalcError>{
+        let func = self.find_function(name, span)?;
+        let mut vals = Vec::with_capacity(args.len());
+        for arg in args {
+            vals.push(self.eval_in_frame(arg, frame, state)?);
         }
-        let val_op_sequence = Self::shounting_yard(&val_op_sequence)?;
-        Self::compute_expr_vec(&val_op_sequence)
+        Self::apply_function(name, func, vals, span)
+    }
+
+    fn find_function(&self, name: &str, span: Span) -> Result<Function, CalcError>{
+        self.token_builder.function_from_str(name)
+            .map_err(|_| CalcError::UnknownFunction{
+                name: name.to_string(),
+                suggestions: self.token_builder.func_alias.borrow().suggest(name),
+                span: Span::new(span.start, span.start + name.len()),
+            })
     }
 
-    fn get_args_from_func_pair(&mut self, pair: &pest::iterators::Pair<Rule>) -> Option<Vec<Val>> {
+    /// Checks the arguments of a call and computes it if the function is native
+    fn apply_function(name: &str, func: Function, vals: Vec<Val>, span: Span) -> Result<Tail, CalcError>{
+        if vals.len() != func.get_argc() as usize {
+            return Err(CalcError::ArityMismatch{
+                name: name.to_string(),
+                expected: func.get_argc() as usize,
+                found: vals.len(),
+                span,
+            });
+        }
+        match func.get_body() {
+            Some(_) => Ok(Tail::Call(func, vals)),
+            None => Ok(Tail::Done(func.compute(vals).map_err(|e| CalcError::from_val_error(e, span))?)),
+        }
+    }
+
+    /// The grammar recurses on every bracket, so too deep nesting is stopped before parsing
+    fn check_brackets(&self, expr: &str) -> Result<(), CalcError>{
+        let mut depth = 0usize;
+        for (i, c) in expr.char_indices() {
+            match c {
+                '(' | '[' => depth += 1,
+                ')' | ']' => depth = depth.saturating_sub(1),
+                _ => continue,
+            }
+            if depth > self.limits.max_nesting {
+                return Err(CalcError::NestingLimitExceeded{limit: self.limits.max_nesting, span: Span::new(i, i + 1)});
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks the height of a node being built against `EvalLimits::max_nesting`
+    fn nested(&self, node: Node, height: usize) -> Result<(Node, usize), CalcError>{
+        match height > self.limits.max_nesting {
+            true => Err(CalcError::NestingLimitExceeded{limit: self.limits.max_nesting, span: node.span}),
+            false => Ok((node, height)),
+        }
+    }
+
+    fn build_statement(&self, pair: Pair<Rule>) -> Result<Ast, CalcError> {
         match pair.as_rule() {
-            Rule::func => {
-                let mut args = Vec::new();
-                let inner = pair.clone().into_inner().skip(1);
-                
-                for arg in inner {
-                    match arg.as_rule() {
-                        Rule::expr =>{
-                            let arg_val = self.eval_parsed(arg.into_inner());
-                            match arg_val {
-                                Ok(val) => args.push(val),
-                                Err(_) => return None,
-                            }
-                        }
-                        _ => unreachable!("Reached: {:?}", arg.as_rule()),
+            Rule::expr => Ok(Ast::Expr(self.build_expr(pair)?.0)),
+            Rule::conversion => {
+                let mut inner = pair.into_inner().filter(|p| p.as_rule() == Rule::expr);
+                let value = self.build_expr(inner.next().unwrap())?.0;
+                let target = self.build_expr(inner.next().unwrap())?.0;
+                Ok(Ast::Convert { value, target })
+            },
+            Rule::add_var => {
+                let mut inner = pair.into_inner();
+                let name = inner.next().unwrap().as_str().to_string();
+                let value = self.build_expr(inner.next().unwrap())?.0;
+                Ok(Ast::VarDef { name, value })
+            },
+            Rule::add_function => {
+                let mut names = Vec::new();
+                let mut body = None;
+                for pair in pair.into_inner() {
+                    match pair.as_rule() {
+                        Rule::name => names.push(pair.as_str().to_string()),
+                        Rule::expr => body = Some(self.build_expr(pair)?.0),
+                        _ => unreachable!(),
                     }
                 }
-                Some(args)
+                let name = names.remove(0);
+                Ok(Ast::FnDef { name, args: names, body: body.unwrap() })
             },
-            _ => None,
+            _ => unreachable!("unexpected statement: {:?}", pair.as_rule()),
+        }
+    }
+
+    /// Builds an expression, returns it with its height
+    fn build_expr(&self, pair: Pair<Rule>) -> Result<(Node, usize), CalcError> {
+        let mut val_op_sequence = Vec::new();
+        for pair in pair.into_inner() {
+            let span = Span::from(pair.as_span());
+            let syntax_error = |message| CalcError::Syntax{message, span};
+            match pair.as_rule() {
+                Rule::infix => val_op_sequence.push(
+                    Expr::BinOp(BinOperator::match_str(pair.as_str()).map_err(syntax_error)?, span)),
+                Rule::prefix => val_op_sequence.push(
+                    Expr::UnOp(UnOperator::match_str(pair.as_str()).map_err(syntax_error)?, span)),
+                _ => {
+                    let (node, height) = self.build_primary(pair)?;
+                    val_op_sequence.push(Expr::Node(node, height));
+                },
+            }
+        }
+        let val_op_sequence = Self::shounting_yard(&val_op_sequence)?;
+        self.build_tree(val_op_sequence)
+    }
+
+    /// Builds an operand, returns it with its height.
+    /// Bulky cases live in separate functions to keep the recursive frames of nested brackets small
+    fn build_primary(&self, pair: Pair<Rule>) -> Result<(Node, usize), CalcError> {
+        let span = Span::from(pair.as_span());
+        let kind = match pair.as_rule() {
+            Rule::number => Self::build_number(pair, span)?,
+            Rule::var => NodeKind::Var(pair.as_str().to_string()),
+            Rule::boolean => NodeKind::Bool(pair.as_str() == "true"),
+            Rule::func => return self.build_call(pair, span),
+            Rule::expr => return self.build_expr(pair),
+            Rule::paren | Rule::array => return self.build_brackets(pair, span),
+            _ => unreachable!("unimplemented rule: {:?}", pair.as_rule()),
+        };
+        self.nested(Node::new(kind, span), 1)
+    }
+
+    fn build_number(pair: Pair<Rule>, span: Span) -> Result<NodeKind, CalcError> {
+        let mut mantissas = Vec::new();
+        let mut unit = None;
+        for inner in pair.into_inner() {
+            match inner.as_rule() {
+                Rule::unit => unit = Some(inner.as_str().to_string()),
+                _ => mantissas.push(Self::parse_mantissa(inner)?),
+            }
+        }
+        // `9.81(2)` and `9.81 ± 0.02` are both `9.81 ± 0.02`, the unit goes to both parts
+        let uncertainty = match mantissas.as_slice() {
+            [(_, concise)] => concise.clone(),
+            [_, (uncertainty, None)] => Some(uncertainty.clone()),
+            _ => return Err(CalcError::Syntax{message: "A number can only have one uncertainty".to_string(), span}),
+        };
+        let value = mantissas.swap_remove(0).0;
+        Ok(NodeKind::Number { value, uncertainty, unit })
+    }
+
+    fn build_call(&self, pair: Pair<Rule>, span: Span) -> Result<(Node, usize), CalcError> {
+        let mut height = 1;
+        let mut inner = pair.into_inner();
+        let name = inner.next().unwrap().as_str().to_string();
+        let mut args = Vec::new();
+        for arg in inner {
+            let (arg, arg_height) = self.build_expr(arg)?;
+            height = height.max(arg_height + 1);
+            args.push(arg);
+        }
+        // a function would evaluate both branches, which recursion can not stop at
+        if name == "if" {
+            if args.len() != 3 {
+                return Err(CalcError::ArityMismatch{name, expected: 3, found: args.len(), span});
+            }
+            return self.nested(Self::ternary(args, span), height);
         }
+        self.nested(Node::new(NodeKind::Call { name, args }, span), height)
     }
+
+    /// `(cond ? if_true : if_false)` out of its three parts
+    fn ternary(mut parts: Vec<Node>, span: Span) -> Node {
+        let (if_false, if_true, cond) = (parts.pop().unwrap(), parts.pop().unwrap(), parts.pop().unwrap());
+        Node::new(NodeKind::Ternary {
+            cond: Box::new(cond),
+            if_true: Box::new(if_true),
+            if_false: Box::new(if_false),
+        }, span)
+    }
+
+    /// Builds a parenthesis, a ternary operator, an array or an interval, with the unit after it
+    fn build_brackets(&self, pair: Pair<Rule>, span: Span) -> Result<(Node, usize), CalcError> {
+        let is_array = pair.as_rule() == Rule::array;
+        let mut height = 1;
+        let mut elements = Vec::new();
+        let mut unit = None;
+        let mut is_interval = false;
+        for inner in pair.into_inner() {
+            match inner.as_rule() {
+                Rule::unit => unit = Some(inner),
+                Rule::range => is_interval = true,
+                _ => {
+                    let (element, element_height) = self.build_expr(inner)?;
+                    height = height.max(element_height + 1);
+                    elements.push(element);
+                },
+            }
+        }
+        let expr = match is_array {
+            true if is_interval => {
+                let (hi, lo) = (elements.pop().unwrap(), elements.pop().unwrap());
+                Node::new(NodeKind::Interval { lo: Box::new(lo), hi: Box::new(hi) }, span)
+            },
+            true => Node::new(NodeKind::Array(elements), span),
+            // `(cond ? a : b)` is the only parenthesis with more than one expression
+            false if elements.len() == 3 => Self::ternary(elements, span),
+            // parentheses do not add a level
+            false => {
+                height -= 1;
+                elements.pop().unwrap()
+            },
+        };
+        let Some(unit) = unit else {
+            return self.nested(expr, height);
+        };
+        let kind = NodeKind::BinOp {
+            op: BinOperator::match_str("*").unwrap(),
+            lhs: Box::new(expr),
+            rhs: Box::new(Node::new(NodeKind::Var(unit.as_str().to_string()), Span::from(unit.as_span()))),
+        };
+        self.nested(Node::new(kind, span), height + 1)
+    }
+
+    /// Parses a number without a unit, along with the uncertainty written in parentheses
+    /// after its last digits: `9.81(2)e3` is 9.81e3 and 0.02e3
+    fn parse_mantissa(pair: Pair<Rule>) -> Result<(BigRational, Option<BigRational>), CalcError> {
+        let span = Span::from(pair.as_span());
+        let syntax_error = |message| CalcError::Syntax{message, span};
+        let inner = |rule| pair.clone().into_inner().find(|p| p.as_rule() == rule).map(|p| p.as_str());
+        let Some(concise) = inner(Rule::concise) else {
+            return Ok((parse_literal(pair.as_str()).map_err(syntax_error)?, None));
+        };
+        let places = inner(Rule::fract).map_or(0, |fract| fract.replace('_', "").len() as i32);
+        let exp = inner(Rule::exp).map_or(Ok(0), |exp| exp[1..].trim_start_matches('+').parse::<i32>())
+            .map_err(|e| syntax_error(e.to_string()))?;
+        let value = parse_literal(&pair.as_str().replacen(concise, "", 1)).map_err(syntax_error)?;
+        let digits = concise.trim_matches(['(', ')']);
+        let uncertainty = parse_literal(&format!("{}e{}", digits, exp - places)).map_err(syntax_error)?;
+        Ok((value, Some(uncertainty)))
+    }
+
     /// makes operation tree considering operators' precedence
-    fn shounting_yard (val_op_sequence: &Vec<Expr>) -> Result<Vec<Expr>, ParseError> {
+    fn shounting_yard (val_op_sequence: &Vec<Expr>) -> Result<Vec<Expr>, CalcError> {
         use crate::tokens::Associativity;
         let val_op_sequence = VecDeque::from((*val_op_sequence).clone());
         let mut reversed_polish: Vec<Expr> = Vec::new();
@@ -227,10 +773,17 @@ impl  SyntCalc {
 
         for val_op in val_op_sequence {
             match val_op {
-                Expr::Val(_) => reversed_polish.push(val_op),
-                Expr::BinOp(op) => {
+                Expr::Node(..) => reversed_polish.push(val_op),
+                Expr::BinOp(op, span) => {
                     while !op_stack.is_empty() {
                         if let Some(last_op) = op_stack.last() {
+                            // `1 < x < 3` would compare a boolean to 3
+                            if op.get_precedence() == last_op.get_precedence() && op.get_associativity() == Associativity::Non {
+                                return Err(CalcError::Syntax{
+                                    message: format!("{} can not follow a comparison; join comparisons with &&", op),
+                                    span,
+                                });
+                            }
                             if op.get_precedence() < last_op.get_precedence() || 
                                 op.get_precedence() == last_op.get_precedence() && op.get_associativity() == Associativity::Left {
                                     reversed_polish.push(op_stack.pop().unwrap().as_expr());
@@ -238,9 +791,9 @@ impl  SyntCalc {
                             else {break;}
                         }
                     }
-                    op_stack.push(Op::Bin(op));
+                    op_stack.push(Op::Bin(op, span));
                 }
-                Expr::UnOp(op)=> {
+                Expr::UnOp(op, span)=> {
                     while !op_stack.is_empty() {
                         if let Some(last_op) = op_stack.last() {
                             if op.get_precedence() < last_op.get_precedence() || 
@@ -250,7 +803,7 @@ impl  SyntCalc {
                             else {break;}
                         }
                     }
-                    op_stack.push(Op::Un(op));
+                    op_stack.push(Op::Un(op, span));
                 },
             }
         }
@@ -262,64 +815,35 @@ impl  SyntCalc {
         Ok(reversed_polish)
     }
 
-    fn compute_expr_vec (val_op_sequence: &Vec<Expr>) -> Result<Val , ParseError> {
-        let mut val_op_sequence = (*val_op_sequence).clone();
-
-        let find_last_vals = |val_op_sequence: &[Expr], count: u32| -> Vec<usize> {
-            let mut last_vals = Vec::new();
-            let mut foud = 0;
-            let mut counter = val_op_sequence.len()-1;
-            for val_op in val_op_sequence.iter().rev(){
-                if let Expr::Val(_) = val_op {
-                    last_vals.push(counter);
-                    foud += 1;
-                    if foud == count {break;}
-                }
-                if counter == 0 {break;}
-                counter -= 1;
-            }
-            last_vals
-        };
-        let mut i = 0;
-        while i < val_op_sequence.len() {
-            match val_op_sequence[i] {
-                Expr::Val(_) => {i += 1; continue;},
-                Expr::BinOp(op) => {
-                    let operands_positions = find_last_vals(&val_op_sequence[0..i], 2);
-                    let rhs = match val_op_sequence.remove(operands_positions[0]){
-                        Expr::Val(val) => val,
-                        _ => unreachable!(),
-                    };
-                    let lhs = match val_op_sequence.remove(operands_positions[1]){
-                        Expr::Val(val) => val,
-                        _ => unreachable!(),
-                    };                    
-                    i -= 2;
-                    let result = op.compute(lhs, rhs).map_err(|e| ParseError{desc: format!("Error in while processing operators: {}", e)})?;
-                    val_op_sequence.remove(i);
-                    val_op_sequence.insert(i, Expr::Val(result));
+    /// builds an expression tree from a sequence in reversed polish notation,
+    /// returns it with its height
+    fn build_tree (&self, val_op_sequence: Vec<Expr>) -> Result<(Node, usize), CalcError> {
+        let malformed = |span| CalcError::Syntax{message: "Malformed expression".to_string(), span};
+        let mut stack: Vec<(Node, usize)> = Vec::new();
+        for val_op in val_op_sequence {
+            match val_op {
+                Expr::Node(node, height) => stack.push((node, height)),
+                Expr::BinOp(op, op_span) => {
+                    let (rhs, rhs_height) = stack.pop().ok_or_else(|| malformed(op_span))?;
+                    let (lhs, lhs_height) = stack.pop().ok_or_else(|| malformed(op_span))?;
+                    let span = Span::new(lhs.span.start, rhs.span.end);
+                    let node = Node::new(NodeKind::BinOp { op, lhs: Box::new(lhs), rhs: Box::new(rhs) }, span);
+                    stack.push(self.nested(node, lhs_height.max(rhs_height) + 1)?);
                 },
-                Expr::UnOp(op)=> {
-                    let operands_positions = find_last_vals(&val_op_sequence[0..i], 1);
-                    let lrhs = match val_op_sequence.remove(operands_positions[0]){
-                        Expr::Val(val) => val,
-                        _ => unreachable!(),
-                    };
-                    i -= 1;
-                    let result = op.compute(lrhs).map_err(|e| ParseError{desc: format!("Error in while processing operators: {}", e)})?;
-                    val_op_sequence.remove(i);
-                    val_op_sequence.insert(i, Expr::Val(result));
+                Expr::UnOp(op, op_span) => {
+                    let (operand, height) = stack.pop().ok_or_else(|| malformed(op_span))?;
+                    let span = Span::new(op_span.start, operand.span.end);
+                    let node = Node::new(NodeKind::UnOp { op, operand: Box::new(operand) }, span);
+                    stack.push(self.nested(node, height + 1)?);
                 },
             }
-            i += 1;
-        };
-        if val_op_sequence.len() == 1 {
-            return match &val_op_sequence[0] {
-                Expr::Val(val) => Ok(val.clone()),
-                _ => unreachable!(),
-            };
         }
-        unreachable!()
+        // an operand left over has no operator between it and the next one
+        match (stack.pop(), stack.is_empty()) {
+            (Some(node), true) => Ok(node),
+            (Some((node, _)), false) => Err(malformed(node.span)),
+            (None, _) => Err(malformed(Span::default())),
+        }
     }
 
 }
@@ -332,6 +856,19 @@ pub struct MathParser{}
 #[cfg(test)]
 mod tests{
     use super::SyntCalc;
+    use crate::error::{CalcError, Span};
+    use crate::tokens::val::Backend;
+
+    /// Evaluates a statement and prints its value, which is empty for declarations
+    fn eval(sc: &mut SyntCalc, s: &str) -> Result<String, CalcError>{
+        sc.eval_statement(s).map(|val| val.map(|val| val.to_string()).unwrap_or_default())
+    }
+
+    fn with_backend(backend: Backend) -> SyntCalc{
+        let sc = SyntCalc::default();
+        sc.token_builder.val_opts.borrow_mut().set_backend(backend);
+        sc
+    }
 
     #[test]
     fn some_check(){
@@ -344,4 +881,708 @@ mod tests{
         assert_eq!(a, 16.);
         assert_eq!(b, 6000.)
     }
+
+    #[test]
+    fn parse_once_eval_many(){
+        let mut sc = SyntCalc::default();
+        let ast = sc.parse("2x^2 + 1").unwrap();
+        for i in 0..5 {
+            eval(&mut sc, &format!("x = {i}")).unwrap();
+            let res = sc.eval(&ast).unwrap().unwrap().get_magnetude();
+            // `2x` is a single literal, so it binds tighter than `^`
+            assert_eq!(res, (4*i*i) as f64 + 1.);
+        }
+    }
+
+    #[test]
+    fn ast_display_reparses(){
+        let sc = SyntCalc::default();
+        for s in ["-1 + 2 * (3 - 4) ^ 2", "2 ^ 3 ^ 2", "(2 ^ 3) ^ 2", "8 / (4 / 2)", "-(-x)",
+                  "f(x, y) = (x > y ? x : sin(y))", "a = 2km * 3", "(3 + 4i) * ohm",
+                  "[[1, 2], [3, x]] * m", "valid(x) = x > 0 && x < 10 || !(x == 5) xor true",
+                  "(!a) == b", "!(a == b)", "(a < b) == true", "[1.9 .. 2.1] * m"] {
+            let printed = sc.parse(s).unwrap().to_string();
+            assert_eq!(printed, s);
+            assert_eq!(sc.parse(&printed).unwrap().to_string(), printed);
+        }
+    }
+
+    #[test]
+    fn eval_str_returns_a_value(){
+        let mut sc = SyntCalc::default();
+        assert_eq!(sc.eval_str("x = 3").unwrap().get_magnetude(), 3.);
+        // the declaration is made, but has no value to return
+        let err = sc.eval_str("f(y) = y + x").unwrap_err();
+        assert_eq!(err.to_string(), "Statement has no value");
+        assert!(matches!(err.error(), CalcError::Other{..}));
+        assert_eq!(sc.eval_str("f(1)").unwrap().get_magnetude(), 4.);
+        let err = sc.eval_str("1 +").unwrap_err();
+        assert!(matches!(err.error(), CalcError::Syntax{..}));
+        assert_eq!(std::error::Error::source(&err).unwrap().to_string(), err.to_string());
+    }
+
+    #[test]
+    fn user_functions(){
+        let mut sc = SyntCalc::default();
+        assert!(sc.eval_statement("f(y) = y^2").unwrap().is_none());
+        eval(&mut sc, "x = 3").unwrap();
+        assert_eq!(eval(&mut sc, "f(x)").unwrap(), "9");
+        eval(&mut sc, "g(y) = 2y + f(x)").unwrap();
+        assert_eq!(eval(&mut sc, "g(1)").unwrap(), "11");
+    }
+
+    #[test]
+    fn user_functions_bind_globals_at_call_time(){
+        let mut sc = SyntCalc::default();
+        eval(&mut sc, "a = 1").unwrap();
+        eval(&mut sc, "f(x) = x + a").unwrap();
+        assert_eq!(eval(&mut sc, "f(1)").unwrap(), "2");
+        eval(&mut sc, "a = 10").unwrap();
+        assert_eq!(eval(&mut sc, "f(1)").unwrap(), "11");
+        // arguments shadow globals
+        eval(&mut sc, "h(a) = 3a").unwrap();
+        assert_eq!(eval(&mut sc, "h(2)").unwrap(), "6");
+        assert!(eval(&mut sc, "h(1, 2)").is_err());
+    }
+
+    #[test]
+    fn infinite_recursion_fails_cleanly(){
+        // the defaults should fit the stack of a spawned thread
+        std::thread::Builder::new().stack_size(2 << 20).spawn(recursion_limits).unwrap().join().unwrap();
+    }
+
+    fn recursion_limits(){
+        let mut sc = SyntCalc::default();
+        eval(&mut sc, "f(x) = f(x) + 1").unwrap();
+        let err = eval(&mut sc, "f(1)").unwrap_err();
+        assert!(matches!(err, CalcError::DepthLimitExceeded{limit: 384, ..}), "{err:?}");
+        // deep bodies and deep recursion share the budget
+        for open in ["(1+", "abs(", "-(", "[1, "] {
+            let close = if open.starts_with('[') { "]" } else { ")" };
+            eval(&mut sc, &format!("h(x) = {}h(x){}", open.repeat(120), close.repeat(120))).unwrap();
+            assert!(matches!(eval(&mut sc, "h(1)"), Err(CalcError::DepthLimitExceeded{..})), "{open}");
+        }
+
+        sc.limits.max_steps = Some(100_000);
+        eval(&mut sc, "t(x) = t(x)").unwrap();
+        assert!(matches!(eval(&mut sc, "t(1)"), Err(CalcError::StepLimitExceeded{..})));
+
+        // a call of `g` takes three levels: the call, the sum and the function
+        sc.limits.max_depth = 31;
+        eval(&mut sc, "g(x) = (x > 0 ? g(x-1) + 1 : 0)").unwrap();
+        assert_eq!(eval(&mut sc, "g(9)").unwrap(), "9");
+        assert!(matches!(eval(&mut sc, "g(10)"), Err(CalcError::DepthLimitExceeded{..})));
+    }
+
+    #[test]
+    fn malformed_expression_points_at_operator(){
+        use super::{Expr, BinOperator};
+        use crate::ast::{Node, NodeKind};
+        let sc = SyntCalc::default();
+        let one = |span| Expr::Node(Node::new(NodeKind::Var("x".to_string()), span), 1);
+        let plus = Expr::BinOp(BinOperator::match_str("+").unwrap(), Span::new(2, 3));
+        let err = sc.build_tree(vec![one(Span::new(0, 1)), plus]).unwrap_err();
+        assert!(matches!(err, CalcError::Syntax{span: Span{start: 2, end: 3}, ..}), "{err:?}");
+        let err = sc.build_tree(vec![one(Span::new(0, 1)), one(Span::new(2, 3))]).unwrap_err();
+        assert!(matches!(err, CalcError::Syntax{span: Span{start: 2, end: 3}, ..}), "{err:?}");
+    }
+
+    #[test]
+    fn unit_power_overflow_fails_cleanly(){
+        let mut sc = SyntCalc::default();
+        let err = eval(&mut sc, "1m^2000000000 * 1m^2000000000").unwrap_err();
+        assert_eq!(err, CalcError::Domain{message: "Power of the unit is too large".to_string(), span: Span::new(0, 29)});
+        assert!(matches!(eval(&mut sc, "(1m^2000000000)^2"), Err(CalcError::Domain{..})));
+        assert_eq!(eval(&mut sc, "1m^2000000000 / 1m^2000000000").unwrap(), "1");
+    }
+
+    #[test]
+    fn deep_nesting_fails_cleanly(){
+        let mut sc = SyntCalc::default();
+        let parens = format!("{}1{}", "(".repeat(100_000), ")".repeat(100_000));
+        let err = eval(&mut sc, &parens).unwrap_err();
+        assert!(matches!(err, CalcError::NestingLimitExceeded{limit: 128, ..}), "{err:?}");
+        assert_eq!(err.span().start, 128);
+        let chain = vec!["1"; 100_000].join("+");
+        assert!(matches!(eval(&mut sc, &chain), Err(CalcError::NestingLimitExceeded{..})));
+        let calls = format!("{}1{}", "sin(".repeat(300), ")".repeat(300));
+        assert!(matches!(eval(&mut sc, &calls), Err(CalcError::NestingLimitExceeded{..})));
+    }
+
+    #[test]
+    fn nesting_within_the_limit(){
+        let mut sc = SyntCalc::default();
+        // each parenthesis is parsed once
+        let start = std::time::Instant::now();
+        let nested = format!("{}1{}", "(".repeat(120), ")".repeat(120));
+        assert_eq!(eval(&mut sc, &nested).unwrap(), "1");
+        assert!(start.elapsed() < std::time::Duration::from_secs(1));
+        let chain = vec!["1"; 120].join("+");
+        assert_eq!(eval(&mut sc, &chain).unwrap(), "120");
+        assert_eq!(eval(&mut sc, "((1 > 0 ? 2 : 3) + 1)").unwrap(), "3");
+    }
+
+    #[test]
+    fn step_and_time_budget(){
+        use std::time::Duration;
+        let mut sc = SyntCalc::default();
+        eval(&mut sc, "f(x) = (x > 0 ? f(x-1) + f(x-1) : 1)").unwrap();
+        sc.limits.max_steps = Some(1000);
+        assert!(matches!(eval(&mut sc, "f(20)"), Err(CalcError::StepLimitExceeded{limit: 1000, ..})));
+        assert_eq!(eval(&mut sc, "f(3)").unwrap(), "8");
+
+        sc.limits.max_steps = None;
+        sc.limits.timeout = Some(Duration::from_millis(10));
+        assert!(matches!(eval(&mut sc, "f(40)"), Err(CalcError::TimeLimitExceeded{..})));
+    }
+
+    #[test]
+    fn recursive_functions(){
+        let mut sc = SyntCalc::default();
+        eval(&mut sc, "fact(n) = (n <= 1 ? 1 : n*fact(n-1))").unwrap();
+        assert_eq!(eval(&mut sc, "fact(10)").unwrap(), "3628800");
+        assert_eq!(eval(&mut sc, "fact(20)").unwrap(), "2432902008176640000");
+        let fact_100 = eval(&mut sc, "fact(100)").unwrap().parse::<f64>().unwrap();
+        assert!((fact_100 / 9.332621544394415e157 - 1.).abs() < 1e-12);
+        eval(&mut sc, "fib(n) = (n < 2 ? n : fib(n-1) + fib(n-2))").unwrap();
+        assert_eq!(eval(&mut sc, "fib(20)").unwrap(), "6765");
+    }
+
+    #[test]
+    fn tail_recursion_does_not_grow_the_stack(){
+        let mut sc = SyntCalc::default();
+        eval(&mut sc, "sum(n, acc) = (n == 0 ? acc : sum(n - 1, acc + n))").unwrap();
+        assert_eq!(eval(&mut sc, "sum(100000, 0)").unwrap(), "5000050000");
+        eval(&mut sc, "gcd(a, b) = (a == b ? a : (a > b ? gcd(a - b, b) : gcd(a, b - a)))").unwrap();
+        assert_eq!(eval(&mut sc, "gcd(1071, 462)").unwrap(), "21");
+        assert_eq!(eval(&mut sc, "gcd(100000, 1)").unwrap(), "1");
+    }
+
+    #[test]
+    fn functions_are_bound_late(){
+        let mut sc = SyntCalc::default();
+        eval(&mut sc, "even(n) = (n == 0 ? 1 : odd(n - 1))").unwrap();
+        assert!(eval(&mut sc, "even(3)").is_err());
+        eval(&mut sc, "odd(n) = (n == 0 ? 0 : even(n - 1))").unwrap();
+        assert_eq!(eval(&mut sc, "even(10001)").unwrap(), "0");
+        // redefinition is picked up by existing callers
+        eval(&mut sc, "odd(n) = 42").unwrap();
+        assert_eq!(eval(&mut sc, "even(3)").unwrap(), "42");
+    }
+
+    #[test]
+    fn remainder_operators(){
+        let mut sc = SyntCalc::default();
+        assert_eq!(eval(&mut sc, "7m % 2m").unwrap(), "1m");
+        assert_eq!(eval(&mut sc, "1 + 7 % 4 * 2").unwrap(), "7");
+        assert_eq!(eval(&mut sc, "rem(-7, 3)").unwrap(), "-1");
+        assert_eq!(eval(&mut sc, "mod(-7, 3)").unwrap(), "2");
+        assert_eq!(eval(&mut sc, "-7 div 2").unwrap(), "-4");
+        assert_eq!(eval(&mut sc, "7km div 2km").unwrap(), "3");
+        assert!(eval(&mut sc, "7m % 2s").is_err());
+        assert!(eval(&mut sc, "1 div 0").is_err());
+    }
+
+    #[test]
+    fn complex_remainders(){
+        let mut sc = SyntCalc::default();
+        // complex numbers are not ordered, so there is nothing to round to
+        for input in ["(1 + 2i) % 2", "5 div 2i", "mod(3i, 2)", "rem(2, [1, i])", "(3 + 0i) div 2i"] {
+            assert!(matches!(eval(&mut sc, input), Err(CalcError::TypeMismatch{..})), "{}", input);
+        }
+        assert_eq!(eval(&mut sc, "(5 + 0i) % 2").unwrap(), "1");
+    }
+
+    #[test]
+    fn errors_carry_kind_and_span(){
+        let mut sc = SyntCalc::default();
+        let mut err = |s: &str| eval(&mut sc, s).unwrap_err();
+        assert_eq!(err("1 + foo * 2"), CalcError::UnknownVariable{
+            name: "foo".to_string(), suggestions: vec![], span: Span::new(4, 7)});
+        assert_eq!(err("2 + 3xyz"), CalcError::UnknownVariable{
+            name: "xyz".to_string(), suggestions: vec![], span: Span::new(5, 8)});
+        assert_eq!(err("1 + foo(2)"), CalcError::UnknownFunction{
+            name: "foo".to_string(), suggestions: vec![], span: Span::new(4, 7)});
+        assert_eq!(err("sin(1, 2)"), CalcError::ArityMismatch{
+            name: "sin".to_string(), expected: 1, found: 2, span: Span::new(0, 9)});
+        assert!(matches!(err("1 + (2m + 3s)"), CalcError::DimensionMismatch{span: Span{start: 5, end: 12}, ..}));
+        assert!(matches!(err("2m to 0m"), CalcError::Domain{span: Span{start: 6, end: 8}, ..}));
+        assert!(matches!(err("-5 div 0"), CalcError::DivisionByZero{span: Span{start: 0, end: 8}, ..}));
+        assert!(matches!(err("1 + * 2"), CalcError::Syntax{span: Span{start: 4, ..}, ..}));
+    }
+
+    #[test]
+    fn errors_in_functions_are_reported_at_the_call(){
+        let mut sc = SyntCalc::default();
+        eval(&mut sc, "f(x) = x + 1s").unwrap();
+        assert!(matches!(eval(&mut sc, "2 * f(1m)"), Err(CalcError::DimensionMismatch{span: Span{start: 4, end: 9}, ..})));
+    }
+
+    #[test]
+    fn unknown_names_get_suggestions(){
+        let mut sc = SyntCalc::default();
+        eval(&mut sc, "velocity = 3").unwrap();
+        let mut suggestions = |s: &str| match eval(&mut sc, s) {
+            Err(CalcError::UnknownVariable{suggestions, ..} | CalcError::UnknownFunction{suggestions, ..}) => suggestions,
+            res => panic!("unexpected result: {res:?}"),
+        };
+        assert_eq!(suggestions("asin(0)"), vec!["arcsin", "sin"]);
+        assert_eq!(suggestions("3 Kg"), vec!["kg"]);
+        assert_eq!(suggestions("kM * 2"), vec!["km"]);
+        assert_eq!(suggestions("2 * pii"), vec!["pi"]);
+        assert_eq!(suggestions("3 kw"), vec!["kW"]);
+        assert_eq!(suggestions("velcoity"), vec!["velocity"]);
+    }
+
+    #[test]
+    fn prefixed_units(){
+        let mut sc = SyntCalc::default();
+        assert_eq!(eval(&mut sc, "3MJ / 1kJ").unwrap(), "3000");
+        assert_eq!(eval(&mut sc, "250 µs / 1ms").unwrap(), "0.25");
+        assert_eq!(eval(&mut sc, "1GW / W").unwrap(), "1000000000");
+        assert_eq!(eval(&mut sc, "2 nm / 1 m").unwrap(), "0.000000002");
+        assert_eq!(eval(&mut sc, "500mg / 1g").unwrap(), "0.5");
+    }
+
+    #[test]
+    fn derived_units(){
+        let mut sc = SyntCalc::default();
+        assert_eq!(eval(&mut sc, "2kg * 3m/s^2").unwrap(), "6N");
+        assert_eq!(eval(&mut sc, "12V / 4A").unwrap(), "3Ω");
+        assert_eq!(eval(&mut sc, "1 kΩ / 1 ohm").unwrap(), "1000");
+        assert_eq!(eval(&mut sc, "5 A * 2 s").unwrap(), "10C");
+        assert_eq!(eval(&mut sc, "1 / 4s").unwrap(), "0.25Hz");
+        assert_eq!(eval(&mut sc, "3 mol / 1 s").unwrap(), "3kat");
+        assert_eq!(eval(&mut sc, "1 / (1 Ω)").unwrap(), "1S");
+        assert_eq!(eval(&mut sc, "2 Wb / 1 m^2").unwrap(), "2T");
+        assert_eq!(eval(&mut sc, "2kg * m^3").unwrap(), "2kg*m^3");
+        assert_eq!(eval(&mut sc, "3 A * s / kg").unwrap(), "3C/kg");
+    }
+
+    #[test]
+    fn unit_conversion(){
+        let mut sc = SyntCalc::default();
+        assert_eq!(eval(&mut sc, "36 km/h to m/s").unwrap(), "10m/s");
+        assert_eq!(eval(&mut sc, "1 kWh in J").unwrap(), "3600000J");
+        assert_eq!(eval(&mut sc, "1 kWh in MJ").unwrap(), "3.6MJ");
+        assert_eq!(eval(&mut sc, "2.5 h -> min").unwrap(), "150min");
+        assert_eq!(eval(&mut sc, "10 Hz to 1/s").unwrap(), "10 (1/s)");
+        assert_eq!(eval(&mut sc, "300 K to °C").unwrap(), "26.85°C");
+        assert_eq!(eval(&mut sc, "1500 to km").unwrap_err().to_string(),
+                   "Can not convert to km: units have different dimensions");
+        assert!(matches!(eval(&mut sc, "3 kg in m^2"), Err(CalcError::DimensionMismatch{span, ..}) if span == Span::new(8, 11)));
+        assert!(matches!(eval(&mut sc, "3 to"), Err(CalcError::Syntax{..})));
+    }
+
+    #[test]
+    fn conversions_reparse(){
+        let mut sc = SyntCalc::default();
+        let ast = sc.parse("36 km/h to m/s").unwrap();
+        assert_eq!(ast.to_string(), "36km / h to m / s");
+        assert_eq!(sc.eval(&sc.parse(&ast.to_string()).unwrap()).unwrap().unwrap().to_string(), "10m/s");
+    }
+
+    #[test]
+    fn temperature_scales(){
+        let mut sc = SyntCalc::default();
+        // scales are shifted exactly, without float errors
+        let cases = [
+            ("20 degC to K", "293.15K"),
+            ("20 °C", "293.15K"),
+            ("300 K to degC", "26.85degC"),
+            ("0 degC to degF", "32degF"),
+            ("-40 degC to degF", "-40degF"),
+            ("212 degF in °C", "100°C"),
+            ("98.6 degF to degC", "37degC"),
+            ("491.67 degR in degF", "32degF"),
+            ("20 degC + 5 delta_degC to degC", "25degC"),
+            ("2 * 5 delta_degC", "10K"),
+            ("9 delta_degF to delta_degC", "5delta_degC"),
+            ("30 degC - 20 degC", "10K"),
+            ("20 degC - 5 K to degC", "15degC"),
+            ("300 K to degR", "540degR"),
+        ];
+        for (input, output) in cases {
+            assert_eq!(eval(&mut sc, input).unwrap(), output, "{}", input);
+        }
+        assert!(matches!(eval(&mut sc, "2 * degC"), Err(CalcError::Syntax{span, ..}) if span == Span::new(4, 8)));
+        assert!(matches!(eval(&mut sc, "5 m to degC"), Err(CalcError::DimensionMismatch{..})));
+    }
+
+    #[test]
+    fn absolute_temperatures_are_not_differences(){
+        let mut sc = SyntCalc::default();
+        assert!(matches!(eval(&mut sc, "10 degC to delta_degC"), Err(CalcError::DimensionMismatch{..})));
+        assert!(matches!(eval(&mut sc, "10 degF in delta_degF"), Err(CalcError::DimensionMismatch{..})));
+        assert_eq!(eval(&mut sc, "0 - 20 degC").unwrap_err().to_string(),
+                   "Can only subtract an absolute temperature from another absolute temperature");
+        assert_eq!(eval(&mut sc, "1 m - 1 s").unwrap_err().to_string(), "Units should be the same for subtraction");
+
+        // absolute temperatures can only be shifted by differences
+        for input in ["20 degC + 20 degC", "2 * 20 degC", "20 degC / 2", "5 K - 20 degC", "sqrt(20 degC)",
+                      "[20 degC, 5 K]"] {
+            assert!(matches!(eval(&mut sc, input), Err(CalcError::DimensionMismatch{..})), "{}", input);
+        }
+        eval(&mut sc, "t = 20 degC").unwrap();
+        assert_eq!(eval(&mut sc, "t - 1 degC").unwrap(), "19K");
+        assert!(eval(&mut sc, "t * 2").is_err());
+        assert!(!sc.eval_statement("t - 1 degC").unwrap().unwrap().is_absolute());
+        assert!(sc.eval_statement("t + 1 delta_degC").unwrap().unwrap().is_absolute());
+    }
+
+    #[test]
+    fn imperial_units(){
+        use crate::tokens::token_builder::{Builder, UnitPack};
+        assert!(eval(&mut SyntCalc::default(), "1 ft").is_err());
+
+        let mut sc = SyntCalc{token_builder: Builder::with_packs(&[UnitPack::Imperial]), ..Default::default()};
+        let cases = [
+            ("12 inch / 1 ft", 1.),
+            ("1 mi / 1 km", 1.609344),
+            ("60 mph / (1 km/h)", 96.56064),
+            ("1 lb / 16 oz", 1.),
+            ("1 gal / 4 qt", 1.),
+            ("30 psi / 1 kPa", 206.8427187950508),
+            ("1 hp / 1 W", 745.699_871_582_270_2),
+            ("1 BTU / 1 J", 1055.05585262),
+            ("1 lbf * 1 ft / 1 ftlbf", 1.),
+        ];
+        for (input, value) in cases {
+            let magn = eval(&mut sc, input).unwrap().parse::<f64>().unwrap();
+            assert!((magn - value).abs() < 1e-9 * value, "{} gave {}", input, magn);
+        }
+    }
+
+    #[test]
+    fn rational_unit_powers(){
+        let mut sc = SyntCalc::default();
+        assert_eq!(eval(&mut sc, "(m^(1/3))^3 + m").unwrap(), "2m");
+        assert_eq!(eval(&mut sc, "(m^2)^0.5 + m").unwrap(), "2m");
+        assert_eq!(eval(&mut sc, "4 * m^0.5").unwrap(), "4m^(1/2)");
+        assert!(matches!(eval(&mut sc, "m^pi"), Err(CalcError::DimensionMismatch{..})));
+    }
+
+    #[test]
+    fn number_bases(){
+        let mut sc = SyntCalc::default();
+        assert_eq!(eval(&mut sc, "0x1F").unwrap(), "31");
+        assert_eq!(eval(&mut sc, "0xff_ff").unwrap(), "65535");
+        assert_eq!(eval(&mut sc, "0x1e5").unwrap(), "485");
+        assert_eq!(eval(&mut sc, "0x1.8").unwrap(), "1.5");
+        assert_eq!(eval(&mut sc, "0x1F m").unwrap(), "31m");
+        assert_eq!(eval(&mut sc, "0b101.1").unwrap(), "5.5");
+        assert_eq!(eval(&mut sc, "0o17 s").unwrap(), "15s");
+        for input in ["0x1Fm", "0x1G", "0xFFdegC", "0b102", "0o8", "0x"] {
+            assert!(matches!(eval(&mut sc, input), Err(CalcError::Syntax{..})), "{}", input);
+        }
+    }
+
+    #[test]
+    fn exponents(){
+        let mut sc = SyntCalc::default();
+        assert_eq!(eval(&mut sc, "0.1 + 0.2").unwrap(), "0.30000000000000004");
+        // exponents go right after the integer part too, up to `MAX_LITERAL_EXPONENT` either way
+        assert_eq!(eval(&mut sc, "1e5").unwrap(), "100000");
+        assert_eq!(eval(&mut sc, "2E+3m").unwrap(), "2000m");
+        assert_eq!(eval(&mut sc, "1e-400").unwrap(), "0");
+        assert!(matches!(eval(&mut sc, "1e10001"), Err(CalcError::Syntax{..})));
+        assert!(matches!(eval(&mut sc, "1e-10001"), Err(CalcError::Syntax{..})));
+    }
+
+    #[test]
+    fn rational_backend(){
+        let mut sc = with_backend(Backend::Rational);
+        assert_eq!(eval(&mut sc, "(0.1 + 0.2 == 0.3 ? 1 : 0)").unwrap(), "1");
+        assert_eq!(eval(&mut sc, "0.1m + 0.2m").unwrap(), "0.3m");
+        assert_eq!(eval(&mut sc, "(1/3) * km to m").unwrap(), "(1000/3)m");
+        assert_eq!(eval(&mut sc, "2^70").unwrap(), "1180591620717411303424");
+        assert_eq!(eval(&mut sc, "20 degC to degF").unwrap(), "68degF");
+        assert_eq!(eval(&mut sc, "1e-400 * 1e400").unwrap(), "1");
+        assert!(matches!(eval(&mut sc, "1/0"), Err(CalcError::DivisionByZero{..})));
+    }
+
+    #[test]
+    fn decimal_backend(){
+        let mut sc = with_backend(Backend::Decimal);
+        sc.token_builder.val_opts.borrow_mut().set_decimal_digits(30);
+        assert_eq!(eval(&mut sc, "1/3").unwrap(), format!("0.{}", "3".repeat(30)));
+        assert_eq!(eval(&mut sc, "2^0.5").unwrap(), "1.41421356237309504880168872421");
+        assert_eq!(eval(&mut sc, "123456789.123456789 * 1000000").unwrap(), "123456789123456.789");
+        assert_eq!(eval(&mut sc, "1e-400 * 3").unwrap(), format!("0.{}3", "0".repeat(399)));
+    }
+
+    #[test]
+    fn decimal_functions(){
+        let mut sc = with_backend(Backend::Decimal);
+        sc.token_builder.val_opts.borrow_mut().set_decimal_digits(30);
+        // functions and pi get all of the digits
+        assert_eq!(eval(&mut sc, "pi").unwrap(), "3.14159265358979323846264338328");
+        assert_eq!(eval(&mut sc, "sin(1)").unwrap(), "0.84147098480789650665250232163");
+        assert_eq!(eval(&mut sc, "ln(2) * 10").unwrap(), "6.93147180559945309417232121458");
+        assert_eq!(eval(&mut sc, "2^pi").unwrap(), "8.82497782707628762385642960421");
+        assert_eq!(eval(&mut sc, "arctan(1) * 4").unwrap(), eval(&mut sc, "pi").unwrap());
+        assert_eq!(eval(&mut sc, "arg(-1)").unwrap(), eval(&mut sc, "pi").unwrap());
+        assert_eq!(eval(&mut sc, "ln(-1)").unwrap(), "(3.141592653589793i)");
+    }
+
+    #[test]
+    fn complex_numbers(){
+        let mut sc = SyntCalc::default();
+        assert_eq!(eval(&mut sc, "i^2").unwrap(), "-1");
+        assert_eq!(eval(&mut sc, "(3 + 4i) * (1 - 2j)").unwrap(), "(11-2i)");
+        assert_eq!(eval(&mut sc, "sqrt(-4)").unwrap(), "(2i)");
+        assert_eq!(eval(&mut sc, "abs(3 + 4i)").unwrap(), "5");
+        assert_eq!(eval(&mut sc, "conj(3 + 4i)").unwrap(), "(3-4i)");
+        assert_eq!(eval(&mut sc, "re(3 + 4i) + im(3 + 4i)").unwrap(), "7");
+        assert_eq!(eval(&mut sc, "(arg(-1) == pi ? 1 : 0)").unwrap(), "1");
+        assert_eq!(eval(&mut sc, "(ln(-1) == i*pi ? 1 : 0)").unwrap(), "1");
+        assert!(eval(&mut sc, "arcsin(2)").unwrap().starts_with('('));
+    }
+
+    #[test]
+    fn complex_numbers_with_units(){
+        let mut sc = SyntCalc::default();
+        assert_eq!(eval(&mut sc, "(3 + 4i) ohm").unwrap(), "(3+4i)Ω");
+        assert_eq!(eval(&mut sc, "abs((3 + 4i) ohm)").unwrap(), "5Ω");
+        assert_eq!(eval(&mut sc, "sqrt(-4 * m^2)").unwrap(), "(2i)m");
+        assert_eq!(eval(&mut sc, "(2 + 1i) km to m").unwrap(), "(2000+1000i)m");
+    }
+
+    #[test]
+    fn uncertainty_literals(){
+        let mut sc = SyntCalc::default();
+        assert_eq!(eval(&mut sc, "9.81 ± 0.02 m/s^2").unwrap(), "(9.810±0.020)m/s^2");
+        assert_eq!(eval(&mut sc, "9.81(2)").unwrap(), "(9.810±0.020)");
+        assert_eq!(eval(&mut sc, "6.674(15)e-11").unwrap(), "(0.00000000006674±0.00000000000015)");
+        assert_eq!(eval(&mut sc, "(2 +/- 0.1) m * 3").unwrap(), "(6.00±0.30)m");
+        assert_eq!(eval(&mut sc, "[1, 2] ± 0.5").unwrap(), "[(1.00±0.50), (2.00±0.50)]");
+        assert_eq!(eval(&mut sc, "1.5 ± 0.2 km / h").unwrap(), "(0.417±0.056)m/s");
+
+        let printed = sc.parse("9.81(2) m").unwrap().to_string();
+        assert_eq!(printed, "9.81 ± 0.02m");
+        assert_eq!(sc.parse(&printed).unwrap().to_string(), printed);
+    }
+
+    #[test]
+    fn uncertainty_propagation(){
+        let mut sc = SyntCalc::default();
+        // independent values add in quadrature
+        assert_eq!(eval(&mut sc, "(1 ± 0.3) + (1 ± 0.4)").unwrap(), "(2.00±0.50)");
+        eval(&mut sc, "x = 2 ± 0.1").unwrap();
+        // the same value is correlated with itself
+        assert_eq!(eval(&mut sc, "x - x").unwrap(), "(0±0)");
+        assert_eq!(eval(&mut sc, "x * x").unwrap(), "(4.00±0.40)");
+        assert_eq!(eval(&mut sc, "sin(0 ± 0.01)").unwrap(), "(0.000±0.010)");
+        assert_eq!(eval(&mut sc, "sqrt(4 ± 0.1)").unwrap(), "(2.000±0.025)");
+        assert_eq!(eval(&mut sc, "uncertainty(1.5(2) km)").unwrap(), "200m");
+        assert_eq!(eval(&mut sc, "nominal(1.5(2) km)").unwrap(), "1500m");
+        // complex results can not carry an uncertainty
+        for input in ["sqrt(-1 ± 0.1)", "ln(-1 ± 0.1)", "(-1 ± 0.1)^0.5", "[4, -1 ± 0.1]^0.5"] {
+            assert!(matches!(eval(&mut sc, input), Err(CalcError::Domain{..})), "{}", input);
+        }
+    }
+
+    #[test]
+    fn uncertainties_with_units(){
+        let mut sc = SyntCalc::default();
+        assert!(matches!(eval(&mut sc, "1m ± 1s"), Err(CalcError::DimensionMismatch{..})));
+        eval(&mut sc, "x = 2 ± 0.1").unwrap();
+        assert!(matches!(eval(&mut sc, "x ± 1s"), Err(CalcError::DimensionMismatch{..})));
+        // the uncertainty of a temperature literal is a difference, any other one is not
+        assert_eq!(eval(&mut sc, "20.0(5) degC to degC").unwrap(), "(20.00±0.50)degC");
+        assert_eq!(eval(&mut sc, "-20 ± 1 degC to degC").unwrap(), "(-20.0±1.0)degC");
+        assert_eq!(eval(&mut sc, "20 degC ± 0.5 delta_degC to degC").unwrap(), "(20.00±0.50)degC");
+        eval(&mut sc, "d = 0.5 degC").unwrap();
+        assert!(matches!(eval(&mut sc, "20 degC ± d"), Err(CalcError::DimensionMismatch{..})));
+    }
+
+    #[test]
+    fn comparisons(){
+        let mut sc = SyntCalc::default();
+        assert_eq!(eval(&mut sc, "1 + 1 == 2").unwrap(), "true");
+        assert_eq!(eval(&mut sc, "2 km > 1500 m").unwrap(), "true");
+        assert_eq!(eval(&mut sc, "true == (1 != 2)").unwrap(), "true");
+        assert!(matches!(eval(&mut sc, "true == 1"), Err(CalcError::TypeMismatch{..})));
+        assert!(matches!(eval(&mut sc, "1m < 1s"), Err(CalcError::DimensionMismatch{..})));
+        // values without an order can not be compared, only tested for equality
+        for input in ["2i > 1", "1 <= 1 + 2i", "[1, 2] < [3, 4]", "[1, 2] >= 1"] {
+            assert!(matches!(eval(&mut sc, input), Err(CalcError::TypeMismatch{..})), "{}", input);
+        }
+        assert_eq!(eval(&mut sc, "2i == 2i").unwrap(), "true");
+        assert_eq!(eval(&mut sc, "[1, 2] != [1, 3]").unwrap(), "true");
+        assert_eq!(eval(&mut sc, "1 + 0i < 2").unwrap(), "true");
+    }
+
+    #[test]
+    fn comparisons_do_not_chain(){
+        let mut sc = SyntCalc::default();
+        // the error points at the second comparison
+        assert!(matches!(eval(&mut sc, "1 < 2 < 3"), Err(CalcError::Syntax{span, ..}) if span == Span::new(6, 7)));
+        assert!(matches!(eval(&mut sc, "1 == 1 != false"), Err(CalcError::Syntax{span, ..}) if span == Span::new(7, 9)));
+        assert!(matches!(eval(&mut sc, "1 < -2 + 3 >= 0"), Err(CalcError::Syntax{span, ..}) if span == Span::new(11, 13)));
+        assert_eq!(eval(&mut sc, "(1 < 2) == true").unwrap(), "true");
+        assert_eq!(eval(&mut sc, "1 < 2 && 2 < 3").unwrap(), "true");
+    }
+
+    #[test]
+    fn logical_operators(){
+        let mut sc = SyntCalc::default();
+        assert_eq!(eval(&mut sc, "!(1 < 2) || false").unwrap(), "false");
+        assert_eq!(eval(&mut sc, "!1 > 2").unwrap(), "true");
+        assert_eq!(eval(&mut sc, "true xor 1 >= 1").unwrap(), "false");
+        // only the needed operands are evaluated
+        assert_eq!(eval(&mut sc, "false && 1/0 > 1").unwrap(), "false");
+        assert!(matches!(eval(&mut sc, "true + 1"), Err(CalcError::TypeMismatch{..})));
+        assert!(matches!(eval(&mut sc, "sin(true)"), Err(CalcError::TypeMismatch{..})));
+        assert!(matches!(eval(&mut sc, "1 && true"), Err(CalcError::TypeMismatch{..})));
+    }
+
+    #[test]
+    fn conditionals(){
+        let mut sc = SyntCalc::default();
+        eval(&mut sc, "valid(x) = x > 0 && x < 10").unwrap();
+        assert_eq!(eval(&mut sc, "valid(5)").unwrap(), "true");
+        assert_eq!(eval(&mut sc, "valid(-1)").unwrap(), "false");
+        assert_eq!(eval(&mut sc, "if(valid(20), 1, 2) * m").unwrap(), "2m");
+        eval(&mut sc, "b = 3 > 2").unwrap();
+        assert_eq!(eval(&mut sc, "(b ? 1 : 0)").unwrap(), "1");
+        // only the chosen branch is evaluated
+        eval(&mut sc, "fact(n) = if(n <= 1, 1, n*fact(n-1))").unwrap();
+        assert_eq!(eval(&mut sc, "fact(5)").unwrap(), "120");
+        assert!(matches!(eval(&mut sc, "(1 ? 2 : 3)"), Err(CalcError::TypeMismatch{span: Span{start: 1, ..}, ..})));
+        assert!(matches!(eval(&mut sc, "if(true, 1)"), Err(CalcError::ArityMismatch{expected: 3, ..})));
+    }
+
+    #[test]
+    fn programs(){
+        let mut sc = SyntCalc::default();
+        let results = sc.eval_program("x = 2 # two\nf(y) = (y > 0 ?\n  y : -y) # absolute value\nf(-x); 7 div 2 # floor division\n\n").unwrap();
+        let results: Vec<_> = results.into_iter().map(|v| v.map(|v| v.to_string())).collect();
+        assert_eq!(results, [Some("2".to_string()), None, Some("2".to_string()), Some("3".to_string())]);
+        assert!(sc.eval_program("# nothing\n").unwrap().is_empty());
+        // the examples of the readme
+        let readme = "x = 2\nf(y) = y^2\nf(x) //output: 4\ng(y) = f(x)^2\ng(x) //output: 16\n(1+2*3)/3.5 + sin(pi) //output: 2\nkg*m^2/s^2 //output: 1J\n";
+        let results = sc.eval_program(readme).unwrap();
+        assert_eq!(results.last().unwrap().as_ref().unwrap().to_string(), "1J");
+    }
+
+    #[test]
+    fn program_comments(){
+        let mut sc = SyntCalc::default();
+        let results = sc.eval_program("// note\nx = 9; x div 2 // 2; x\nx //2\n").unwrap();
+        let results: Vec<_> = results.into_iter().map(|v| v.map(|v| v.to_string())).collect();
+        assert_eq!(results, [Some("9".to_string()), Some("4".to_string()), Some("9".to_string())]);
+    }
+
+    #[test]
+    fn program_errors(){
+        let mut sc = SyntCalc::default();
+        // spans point into the whole program, and statements before an error stay
+        let program = "a = 1\nb = a +\nc = 3";
+        let err = sc.eval_program(program).unwrap_err();
+        assert_eq!(err.span().start, 13);
+        assert!(err.render(program).starts_with("b = a +\n"));
+        assert!(eval(&mut sc, "a").is_ok());
+        assert!(eval(&mut sc, "c").is_err());
+        let errors: Vec<_> = sc.eval_statements("1m + 1s; y = 2; y; zz").map(|r| r.is_err()).collect();
+        assert_eq!(errors, [true, false, false, true]);
+        assert!(matches!(&sc.parse_program("1 +; 2")[..], [Err(CalcError::Syntax{span: Span{start: 3, ..}, ..}), Ok(_)]));
+    }
+
+    #[test]
+    fn interval_arithmetic(){
+        let mut sc = with_backend(Backend::Interval);
+        assert_eq!(eval(&mut sc, "interval(1.5, 2.5) * 1m + 1m").unwrap(), "[2.5 .. 3.5]m");
+        assert_eq!(eval(&mut sc, "interval(1, 2) * 1km to m").unwrap(), "[1000 .. 2000]m");
+        assert_eq!(eval(&mut sc, "interval(-1, 2)^2").unwrap(), "[0 .. 4]");
+        assert_eq!(eval(&mut sc, "2 ± 0.5").unwrap(), "[1.5 .. 2.5]");
+        assert_eq!(eval(&mut sc, "interval(1, 2) * interval(-1, 3)").unwrap(), "[-2 .. 6]");
+        assert_eq!(eval(&mut sc, "sin(interval(0, 2))").unwrap(), eval(&mut sc, "interval(sin(0), 1)").unwrap());
+        // literals that are not floats are enclosed by the nearest ones
+        assert_eq!(eval(&mut sc, "0.1").unwrap(), "[0.09999999999999999 .. 0.1]");
+        assert!(eval(&mut sc, "1 / interval(-1, 1)").unwrap().contains("inf"));
+        assert!(eval(&mut sc, "ln(interval(-1, 1))").unwrap().starts_with("[-inf .. 0.0"));
+        // brackets still make vectors and matrices, of intervals
+        assert_eq!(eval(&mut sc, "[1, 2]").unwrap(), "[[1 .. 1], [2 .. 2]]");
+        assert_eq!(eval(&mut sc, "matmul([[1, 2], [3, 4]], [1, 1])").unwrap(), "[[3 .. 3], [7 .. 7]]");
+        assert_eq!(eval(&mut sc, "det([[1, 2], [3, 4]])").unwrap(), "[-2.0000000000000004 .. -1.9999999999999996]");
+    }
+
+    #[test]
+    fn interval_comparisons(){
+        let mut sc = with_backend(Backend::Interval);
+        assert_eq!(eval(&mut sc, "(pi > 3.14159265358979 ? 1 : 0)").unwrap(), "[1 .. 1]");
+        assert_eq!(eval(&mut sc, "(interval(1, 2) < interval(3, 4) ? 1 : 0)").unwrap(), "[1 .. 1]");
+        assert_eq!(eval(&mut sc, "(interval(1, 2) == 3 ? 1 : 0)").unwrap(), "[0 .. 0]");
+        assert_eq!(eval(&mut sc, "(2 == 2 ? 1 : 0)").unwrap(), "[1 .. 1]");
+        // the order of overlapping intervals is not known
+        for input in ["0.1 + 0.2 > 0.3", "interval(1, 3) > 2", "interval(1, 3) <= 2", "interval(1, 3) == 2",
+                      "interval(1, 3) != interval(1, 3)"] {
+            assert!(matches!(eval(&mut sc, input), Err(CalcError::Domain{..})), "{}", input);
+        }
+    }
+
+    #[test]
+    fn interval_errors(){
+        let mut sc = with_backend(Backend::Interval);
+        for input in ["interval(-2, -1)^0.5", "sqrt(interval(-2, -1))", "ln(interval(-2, -1))", "arcsin(2)",
+                      "arccos(interval(-3, -2))", "[4, -1]^0.5"] {
+            assert!(matches!(eval(&mut sc, input), Err(CalcError::Domain{..})), "{}", input);
+        }
+        assert!(matches!(eval(&mut sc, "[1m, 2s]"), Err(CalcError::DimensionMismatch{..})));
+        assert!(matches!(eval(&mut sc, "interval(2, 1)"), Err(CalcError::Domain{..})));
+        assert!(matches!(eval(&mut sc, "interval(1m, 2s)"), Err(CalcError::DimensionMismatch{..})));
+    }
+
+    #[test]
+    fn interval_literals(){
+        let mut sc = with_backend(Backend::Interval);
+        // `[lo .. hi]` is written the same as intervals are printed
+        assert_eq!(eval(&mut sc, "[1.5 .. 2.5] m + 1m").unwrap(), "[2.5 .. 3.5]m");
+        assert_eq!(eval(&mut sc, "[1 .. 2] km to m").unwrap(), "[1000 .. 2000]m");
+        assert_eq!(eval(&mut sc, "[[1 .. 2], [-1 .. 1]]").unwrap(), "[[1 .. 2], [-1 .. 1]]");
+        assert!(matches!(eval(&mut sc, "[2 .. 1]"), Err(CalcError::Domain{..})));
+        assert!(matches!(eval(&mut sc, "[1m .. 2s]"), Err(CalcError::DimensionMismatch{..})));
+        assert!(matches!(eval(&mut sc, "[1 .. 2, 3]"), Err(CalcError::Syntax{..})));
+    }
+
+    #[test]
+    fn non_monotonic_interval_functions(){
+        let mut sc = with_backend(Backend::Interval);
+        // the results are enclosed by the extrema
+        assert_eq!(eval(&mut sc, "[-1 .. 2]^2").unwrap(), "[0 .. 4]");
+        assert_eq!(eval(&mut sc, "cos([-1 .. 1]) > 0.54").unwrap(), "true");
+        assert!(eval(&mut sc, "cos([-1 .. 1])").unwrap().ends_with(" .. 1]"));
+        assert_eq!(eval(&mut sc, "sin([0 .. 4])").unwrap(), eval(&mut sc, "[sin(4) .. 1]").unwrap());
+        assert_eq!(eval(&mut sc, "tan([1 .. 2])").unwrap(), "[-inf .. inf]");
+    }
+
+    #[test]
+    fn array_arithmetic(){
+        let mut sc = SyntCalc::default();
+        assert_eq!(eval(&mut sc, "[1, 2, 3] m").unwrap(), "[1, 2, 3]m");
+        assert_eq!(eval(&mut sc, "[1, 2, 3] m + [1km, 0m, 1cm]").unwrap(), "[1001, 2, 3.01]m");
+        assert_eq!(eval(&mut sc, "2 * [1, 2] / 4s").unwrap(), "[0.5, 1]Hz");
+        assert_eq!(eval(&mut sc, "[[1, 2], [3, 4]] ^ 2").unwrap(), "[[1, 4], [9, 16]]");
+        assert_eq!(eval(&mut sc, "abs([-1, 3 + 4i])").unwrap(), "[1, 5]");
+        assert_eq!(eval(&mut sc, "([1, 2] m == [1, 2] m ? 1 : 0)").unwrap(), "1");
+        assert!(matches!(eval(&mut sc, "[1, 2] + [1, 2, 3]"), Err(CalcError::ShapeMismatch{..})));
+        assert!(matches!(eval(&mut sc, "[1m, 2s]"), Err(CalcError::DimensionMismatch{..})));
+        assert!(matches!(eval(&mut sc, "[[1, 2], [3]]"), Err(CalcError::ShapeMismatch{..})));
+    }
+
+    #[test]
+    fn linear_algebra(){
+        let mut sc = SyntCalc::default();
+        assert_eq!(eval(&mut sc, "dot([1, 2, 3] N, [1, 0, 0] m)").unwrap(), "1J");
+        assert_eq!(eval(&mut sc, "cross([1, 0, 0] m, [0, 2, 0] N)").unwrap(), "[0, 0, 2]J");
+        assert_eq!(eval(&mut sc, "norm([3, 4] m)").unwrap(), "5m");
+        assert_eq!(eval(&mut sc, "matmul([[1, 2], [3, 4]], [1, 1])").unwrap(), "[3, 7]");
+        assert_eq!(eval(&mut sc, "transpose([[1, 2], [3, 4]])").unwrap(), "[[1, 3], [2, 4]]");
+        assert_eq!(eval(&mut sc, "det([[1, 2], [3, 4]] m)").unwrap(), "-2m^2");
+        assert_eq!(eval(&mut sc, "inv([[2, 0], [0, 4]] s)").unwrap(), "[[0.5, 0], [0, 0.25]]Hz");
+        assert_eq!(eval(&mut sc, "solve([[2, 1], [1, 3]] kg, [3, 5] N)").unwrap(), "[0.8, 1.4]m/s^2");
+        assert!(matches!(eval(&mut sc, "inv([[1, 2], [2, 4]])"), Err(CalcError::Domain{..})));
+        assert!(matches!(eval(&mut sc, "cross([1, 2], [3, 4])"), Err(CalcError::ShapeMismatch{..})));
+    }
 }
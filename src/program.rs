@@ -0,0 +1,68 @@
+//! Splitting of programs into statements.
+//! Statements are separated by newlines or `;`, except inside brackets,
+//! and comments run from `#` or `//` to the end of the line
+
+use std::ops::Range;
+
+/// Replaces comments with spaces. Byte offsets are kept,
+/// so that spans in the result point to the same places of the program
+pub(crate) fn strip_comments(program: &str) -> String {
+    let mut ret = String::with_capacity(program.len());
+    for line in program.split_inclusive('\n') {
+        let start = [line.find('#'), line.find("//")].into_iter().flatten().min().unwrap_or(line.len());
+        let (code, comment) = line.split_at(start);
+        ret.push_str(code);
+        for c in comment.chars() {
+            match c {
+                '\n' | '\r' => ret.push(c),
+                c => ret.push_str(&" ".repeat(c.len_utf8())),
+            }
+        }
+    }
+    ret
+}
+
+/// Byte ranges of the statements of a program with comments already stripped.
+/// Blank statements are left out
+pub(crate) fn split_statements(program: &str) -> Vec<Range<usize>> {
+    let mut ret = Vec::new();
+    let mut depth = 0usize;
+    let mut start = 0;
+    let mut push = |range: Range<usize>| {
+        if !program[range.clone()].trim().is_empty() {
+            ret.push(range);
+        }
+    };
+    for (i, c) in program.char_indices() {
+        match c {
+            '(' | '[' => depth += 1,
+            // unmatched brackets are left for the parser to report
+            ')' | ']' => depth = depth.saturating_sub(1),
+            ';' | '\n' if depth == 0 => {
+                push(start..i);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    push(start..program.len());
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn statements() {
+        let program = "x = 2 # two\n// note\nf(y) = (y > 0 ?\n  y : -y); f(-x); // output: 2\n7 div 2; x//2 # x\n\n";
+        let stripped = strip_comments(program);
+        assert_eq!(stripped.len(), program.len());
+        let statements: Vec<_> = split_statements(&stripped).into_iter()
+            .map(|range| stripped[range].trim().to_string())
+            .collect();
+        assert_eq!(statements, ["x = 2", "f(y) = (y > 0 ?\n  y : -y)", "f(-x)", "7 div 2", "x"]);
+        assert_eq!(strip_comments("°C # °F"), "°C      ");
+        assert_eq!(strip_comments("x //2 # y\ny"), "x        \ny");
+    }
+}
@@ -0,0 +1,414 @@
+//! Interactive shell of the `syntcalc` binary: line editing, a history kept between sessions,
+//! completion of names, input continued on the next line while brackets are open
+//! and colon commands for looking into the session. The session is restored at the start
+//! and saved at the exit
+
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use rustyline::completion::{Completer, Pair};
+use rustyline::error::ReadlineError;
+use rustyline::highlight::{Highlighter, MatchingBracketHighlighter};
+use rustyline::hint::Hinter;
+use rustyline::history::DefaultHistory;
+use rustyline::validate::{ValidationContext, ValidationResult, Validator};
+use rustyline::{Config, Context, Editor, Helper};
+use syntcalc::ast::Ast;
+use syntcalc::tokens::associations::{FnAlias, ValAlias};
+use syntcalc::tokens::suggest::did_you_mean;
+use syntcalc::{CalcError, SyntCalc};
+
+const PROMPT: &str = "> ";
+
+const COMMANDS_HELP: &str = "\
+:vars               user variables
+:funcs              user functions and the names of the built-in ones
+:units              names of units and constants
+:del name           removes a user variable or function
+:reset              removes all user definitions and sets the options back
+:help name          what a name stands for
+:type expr          dimension of the value of an expression
+:opts [name=value]  shows or sets options: cmp_epsilon, backend, decimal_digits
+:save [path]        saves the definitions and the options, to the session file by default
+:load [path]        replaces the definitions and the options with saved ones";
+
+/// Completes names of the calculator's variables, units and functions
+struct CalcHelper {
+    val_alias: Arc<RefCell<ValAlias>>,
+    func_alias: Arc<RefCell<FnAlias>>,
+    brackets: MatchingBracketHighlighter,
+}
+
+impl CalcHelper {
+    fn new(sc: &SyntCalc) -> Self {
+        CalcHelper {
+            val_alias: sc.token_builder.val_alias.clone(),
+            func_alias: sc.token_builder.func_alias.clone(),
+            brackets: MatchingBracketHighlighter::new(),
+        }
+    }
+
+    /// Names starting with `word`, sorted. Functions are completed with the opening parenthesis
+    fn candidates(&self, word: &str) -> Vec<Pair> {
+        let vals = self.val_alias.borrow();
+        let funcs = self.func_alias.borrow();
+        let vals = vals.names()
+            .filter(|name| name.starts_with(word))
+            .map(|name| Pair { display: name.to_string(), replacement: name.to_string() });
+        let funcs = funcs.get_map().keys()
+            .filter(|name| name.starts_with(word))
+            .map(|name| Pair { display: format!("{}()", name), replacement: format!("{}(", name) });
+        let mut ret: Vec<Pair> = vals.chain(funcs).collect();
+        ret.sort_by(|a, b| a.display.cmp(&b.display));
+        ret
+    }
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '°'
+}
+
+/// Start of the name that ends at `pos`; the digits of `2km` are not a part of it
+fn name_start(line: &str, pos: usize) -> usize {
+    let start = line[..pos].char_indices().rev()
+        .take_while(|(_, c)| is_name_char(*c))
+        .last()
+        .map_or(pos, |(i, _)| i);
+    let word = &line[start..pos];
+    start + word.find(|c: char| !c.is_ascii_digit()).unwrap_or(word.len())
+}
+
+/// Whether a bracket is left open, so that the input goes on at the next line
+fn has_open_brackets(input: &str) -> bool {
+    let depth = input.chars().fold(0i32, |depth, c| match c {
+        '(' | '[' => depth + 1,
+        ')' | ']' => depth - 1,
+        _ => depth,
+    });
+    depth > 0
+}
+
+impl Completer for CalcHelper {
+    type Candidate = Pair;
+
+    fn complete(&self, line: &str, pos: usize, _ctx: &Context<'_>) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let start = name_start(line, pos);
+        if start == pos {
+            return Ok((pos, Vec::new()));
+        }
+        Ok((start, self.candidates(&line[start..pos])))
+    }
+}
+
+impl Hinter for CalcHelper {
+    type Hint = String;
+}
+
+impl Highlighter for CalcHelper {
+    fn highlight<'l>(&self, line: &'l str, pos: usize) -> Cow<'l, str> {
+        self.brackets.highlight(line, pos)
+    }
+
+    fn highlight_char(&self, line: &str, pos: usize, forced: bool) -> bool {
+        self.brackets.highlight_char(line, pos, forced)
+    }
+}
+
+impl Validator for CalcHelper {
+    /// Mismatched closing brackets are let through, so that the parser reports where they are
+    fn validate(&self, ctx: &mut ValidationContext) -> rustyline::Result<ValidationResult> {
+        Ok(match has_open_brackets(ctx.input()) {
+            true => ValidationResult::Incomplete,
+            false => ValidationResult::Valid(None),
+        })
+    }
+}
+
+impl Helper for CalcHelper {}
+
+/// File named by the environment variable `var`, or the file `name` in the home directory
+fn user_file(var: &str, name: &str) -> Option<PathBuf> {
+    std::env::var_os(var)
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(name)))
+}
+
+fn history_path() -> Option<PathBuf> {
+    user_file("SYNTCALC_HISTORY", ".syntcalc_history")
+}
+
+/// File the session is restored from at the start and saved to at the exit
+fn session_path() -> Option<PathBuf> {
+    user_file("SYNTCALC_SESSION", ".syntcalc_session")
+}
+
+fn save_session(sc: &SyntCalc, path: &Path) -> Result<(), String> {
+    std::fs::write(path, sc.save_session()).map_err(|e| format!("Can not save the session to {}: {}", path.display(), e))
+}
+
+/// Copies a session that could not be loaded next to it, as the session file is saved over at the exit
+fn back_up_session(path: &Path) -> Result<PathBuf, String> {
+    let mut backup = path.as_os_str().to_owned();
+    backup.push(".bak");
+    let backup = PathBuf::from(backup);
+    std::fs::copy(path, &backup)
+        .map_err(|e| format!("Can not back up the session to {}: {}", backup.display(), e))?;
+    Ok(backup)
+}
+
+fn load_session(sc: &mut SyntCalc, path: &Path) -> Result<(), String> {
+    let session = std::fs::read_to_string(path)
+        .map_err(|e| format!("Can not read the session from {}: {}", path.display(), e))?;
+    sc.load_session(&session)
+        .map_err(|e| format!("{}:\n{}", path.display(), e.render(&session)))
+}
+
+/// Path given to `:save` or `:load`, the session file by default
+fn command_path(arg: &str) -> Result<PathBuf, String> {
+    match arg {
+        "" => session_path().ok_or_else(|| "There is no home directory, give a path".to_string()),
+        path => Ok(PathBuf::from(path)),
+    }
+}
+
+/// Prints a message to stderr, in red on terminals
+fn print_red(message: &str) {
+    if std::io::stderr().is_terminal() {
+        eprintln!("\x1b[1;31m{}\x1b[0m", message);
+    } else {
+        eprintln!("{}", message);
+    }
+}
+
+/// Prints an error with the offending part of the input underlined
+pub fn print_error(e: &CalcError, source: &str) {
+    let rendered = e.render(source);
+    // the first line is the input itself
+    match rendered.split_once('\n') {
+        Some((line, rest)) => {
+            eprintln!("{}", line);
+            print_red(rest);
+        }
+        None => print_red(&rendered),
+    }
+}
+
+/// Runs a colon command, such as `:vars` or `:del x`.
+/// Returns a message for a wrong command
+fn run_command(sc: &mut SyntCalc, line: &str) -> Result<(), String> {
+    let (name, arg) = line.split_once(char::is_whitespace).map_or((line, ""), |(name, arg)| (name, arg.trim()));
+    let builder = &sc.token_builder;
+    match (name, arg) {
+        (":vars", "") => {
+            for (name, val) in builder.val_alias.borrow().vars() {
+                println!("{} = {}", name, val);
+            }
+        }
+        (":funcs", "") => {
+            let funcs = builder.func_alias.borrow();
+            for (name, func) in funcs.user_fns() {
+                match func.get_definition() {
+                    Some(definition) => println!("{}", definition),
+                    None => println!("{}", name),
+                }
+            }
+            println!("built-in: {}", funcs.builtins().join(", "));
+        }
+        (":units", "") => println!("{}", builder.val_alias.borrow().units().join(", ")),
+        (":del", name) if !name.is_empty() => {
+            if !builder.undefine(name) {
+                return Err(format!("There is no user variable or function '{}'", name));
+            }
+        }
+        (":reset", "") => builder.reset(),
+        (":help", "") => println!("{}", COMMANDS_HELP),
+        (":help", name) => help(sc, name)?,
+        (":type", expr) if !expr.is_empty() => {
+            let val = match sc.parse(expr) {
+                Ok(ast @ (Ast::Expr(_) | Ast::Convert { .. })) => sc.eval(&ast),
+                Ok(_) => return Err(":type takes an expression, not a declaration".to_string()),
+                Err(e) => Err(e),
+            };
+            match val {
+                Ok(Some(val)) if val.as_bool().is_some() => println!("boolean"),
+                Ok(Some(val)) => match val.format_unit() {
+                    unit if unit.is_empty() => println!("dimensionless"),
+                    unit => println!("{}", unit),
+                },
+                Ok(None) => {}
+                Err(e) => print_error(&e, expr),
+            }
+        }
+        (":opts", "") => {
+            for (name, value) in builder.options() {
+                println!("{} = {}", name, value);
+            }
+        }
+        (":opts", args) => {
+            for arg in args.split_whitespace() {
+                let (name, value) = arg.split_once('=')
+                    .ok_or_else(|| format!("Options are set as name=value, not '{}'", arg))?;
+                builder.set_option(name, value)?;
+            }
+        }
+        (":save", path) => save_session(sc, &command_path(path)?)?,
+        (":load", path) => load_session(sc, &command_path(path)?)?,
+        _ => return Err(format!("Unknown command or missing argument: '{}', see :help", line)),
+    }
+    Ok(())
+}
+
+/// Prints what a name stands for: a function, a variable or a unit
+fn help(sc: &SyntCalc, name: &str) -> Result<(), String> {
+    let vals = sc.token_builder.val_alias.borrow();
+    let funcs = sc.token_builder.func_alias.borrow();
+    let mut found = false;
+    if let Some(func) = funcs.get_fn(name) {
+        match (func.get_definition(), FnAlias::help(name)) {
+            (Some(definition), _) => println!("{}", definition),
+            (None, Some(help)) => println!("{}", help),
+            (None, None) => println!("{}: built-in function of {} arguments", name, func.get_argc()),
+        }
+        found = true;
+    } else if let Some(help) = FnAlias::help(name) {
+        println!("{}", help);
+        found = true;
+    }
+    if let Some(val) = vals.get_val(name) {
+        println!("{} = {}", name, val);
+        found = true;
+    } else if let Some((scale, zero)) = vals.get_affine(name) {
+        println!("{}: temperature scale, the degree is {}K and the zero is at {}K", name, scale, zero);
+        found = true;
+    }
+    if !found {
+        let mut suggestions = vals.suggest(name);
+        suggestions.extend(funcs.suggest(name));
+        return Err(format!("Nothing is called '{}'{}", name, did_you_mean(&suggestions)));
+    }
+    Ok(())
+}
+
+/// Reads and evaluates statements until `exit()` or Ctrl-D
+pub fn run(sc: &mut SyntCalc) -> rustyline::Result<()> {
+    let config = Config::builder().auto_add_history(true).build();
+    let mut editor: Editor<CalcHelper, DefaultHistory> = Editor::with_config(config)?;
+    let history = history_path();
+    if let Some(path) = &history {
+        // there is no history on the first run
+        let _ = editor.load_history(path);
+    }
+    let mut session = session_path();
+    if let Some(path) = session.clone().filter(|path| path.exists()) {
+        if let Err(message) = load_session(sc, &path) {
+            print_red(&message);
+            match back_up_session(&path) {
+                Ok(backup) => print_red(&format!("The session that failed to load is kept in {}", backup.display())),
+                Err(message) => {
+                    print_red(&format!("{}, so the session will not be saved at the exit", message));
+                    session = None;
+                }
+            }
+        }
+    }
+    editor.set_helper(Some(CalcHelper::new(sc)));
+    loop {
+        let input = match editor.readline(PROMPT) {
+            Ok(input) => input,
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(e) => return Err(e),
+        };
+        let input = input.trim_end();
+        if input.trim() == "exit()" {
+            break;
+        }
+        if input.trim().is_empty() {
+            continue;
+        }
+        if input.trim_start().starts_with(':') {
+            if let Err(message) = run_command(sc, input.trim()) {
+                print_red(&message);
+            }
+            // `:load` replaces the definitions the names are completed from
+            editor.set_helper(Some(CalcHelper::new(sc)));
+            continue;
+        }
+        for result in sc.eval_statements(input) {
+            match result {
+                Ok(Some(val)) => println!("{}", val),
+                Ok(None) => {},
+                Err(e) => {
+                    print_error(&e, input);
+                    break;
+                }
+            }
+        }
+    }
+    if let Some(path) = &session {
+        if let Err(message) = save_session(sc, path) {
+            print_red(&message);
+        }
+    }
+    if let Some(path) = &history {
+        editor.save_history(path)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn completion() {
+        let sc = SyntCalc::default();
+        let helper = CalcHelper::new(&sc);
+        let names = |word: &str| helper.candidates(word).into_iter().map(|p| p.replacement).collect::<Vec<_>>();
+        assert_eq!(names("si"), ["sin("]);
+        assert!(names("deg").contains(&"degC".to_string()));
+        assert_eq!(name_start("1 + 2km", 7), 5);
+        assert_eq!(name_start("f(x_1", 5), 2);
+        assert_eq!(name_start("2 + ", 4), 4);
+        assert!(has_open_brackets("f(x) = (x > 0 ?"));
+        assert!(!has_open_brackets("[1, 2])"));
+    }
+
+    #[test]
+    fn commands() {
+        let mut sc = SyntCalc::default();
+        sc.eval_statement("m = 2").unwrap();
+        sc.eval_statement("f(x) = x^2").unwrap();
+        assert!(run_command(&mut sc, ":del m").is_ok());
+        assert!(run_command(&mut sc, ":del m").is_err());
+        assert!(run_command(&mut sc, ":del").is_err());
+        assert_eq!(sc.eval_statement("1 km").unwrap().unwrap().to_string(), "1000m");
+        assert!(run_command(&mut sc, ":opts backend=rational decimal_digits=20").is_ok());
+        assert!(run_command(&mut sc, ":opts backend").is_err());
+        assert!(run_command(&mut sc, ":type x = 2").is_err());
+        assert!(run_command(&mut sc, ":help f").is_ok());
+        assert!(run_command(&mut sc, ":help ff").unwrap_err().contains("f"));
+        let path = std::env::temp_dir().join(format!("syntcalc_session_{}", std::process::id()));
+        let path_arg = path.to_str().unwrap();
+        assert!(run_command(&mut sc, &format!(":save {}", path_arg)).is_ok());
+        assert!(run_command(&mut sc, ":reset").is_ok());
+        assert!(run_command(&mut sc, &format!(":load {}", path_arg)).is_ok());
+        assert_eq!(sc.eval_statement("f(3)").unwrap().unwrap().to_string(), "9");
+        std::fs::write(&path, "x = 1 +\n").unwrap();
+        assert!(run_command(&mut sc, &format!(":load {}", path_arg)).is_err());
+        // a session that fails to load leaves the definitions as they were
+        assert_eq!(sc.eval_statement("f(3)").unwrap().unwrap().to_string(), "9");
+        let backup = back_up_session(&path).unwrap();
+        assert_eq!(std::fs::read_to_string(&backup).unwrap(), "x = 1 +\n");
+        std::fs::remove_file(&backup).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(run_command(&mut sc, &format!(":load {}", path_arg)).is_err());
+        assert!(back_up_session(&path).is_err());
+        assert!(run_command(&mut sc, ":reset").is_ok());
+        assert!(sc.eval_statement("f(1)").is_err());
+        assert!(run_command(&mut sc, ":frobnicate").is_err());
+    }
+}
@@ -0,0 +1,114 @@
+//! Saving and restoring the user defined state of a calculator.
+//! A session is written as a program: options in `#opt name=value` lines,
+//! then the variables with their values and the functions as they were declared
+
+use crate::error::{CalcError, Span};
+use crate::SyntCalc;
+
+const HEADER: &str = "# syntcalc session";
+/// Start of a line that sets an option, a comment for anything but [`SyntCalc::load_session`]
+const OPTION_PREFIX: &str = "#opt ";
+
+impl SyntCalc {
+    /// Writes the options, the user variables and the user functions as a program
+    /// that [`SyntCalc::load_session`] restores them from.
+    /// Variables replacing units come last, so that the values of the others still read right.
+    /// Every variable is written as its value, so uncertain ones lose their correlations
+    pub fn save_session(&self) -> String {
+        let builder = &self.token_builder;
+        let mut lines = vec![HEADER.to_string()];
+        for (name, value) in builder.options() {
+            lines.push(format!("{}{}={}", OPTION_PREFIX, name, value));
+        }
+        let vals = builder.val_alias.borrow();
+        let mut vars = vals.vars();
+        vars.sort_by_key(|(name, _)| vals.shadows(name));
+        for (name, val) in vars {
+            lines.push(format!("{} = {}", name, val.to_source()));
+        }
+        for (name, func) in builder.func_alias.borrow().user_fns() {
+            match func.get_definition() {
+                Some(definition) => lines.push(definition.to_string()),
+                None => lines.push(format!("# {} has no declaration to be saved from", name)),
+            }
+        }
+        lines.push(String::new());
+        lines.join("\n")
+    }
+
+    /// Replaces the user definitions and the options with those of a session
+    /// written by [`SyntCalc::save_session`]. Any other program can be loaded as well.
+    /// The session is loaded into a new calculator that replaces this one only if it loads,
+    /// so a failed load leaves the definitions as they were
+    pub fn load_session(&mut self, session: &str) -> Result<(), CalcError> {
+        let mut loaded = SyntCalc{ token_builder: self.token_builder.fresh(), limits: self.limits };
+        let mut offset = 0;
+        for line in session.split_inclusive('\n') {
+            if let Some(option) = line.trim_end().strip_prefix(OPTION_PREFIX) {
+                let span = Span::new(offset, offset + line.trim_end().len());
+                let (name, value) = option.split_once('=')
+                    .ok_or_else(|| CalcError::Syntax { message: "An option should be set as name=value".to_string(), span })?;
+                loaded.token_builder.set_option(name.trim(), value.trim())
+                    .map_err(|message| CalcError::Syntax { message, span })?;
+            }
+            offset += line.len();
+        }
+        loaded.eval_program(session)?;
+        *self = loaded;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sessions() {
+        let mut sc = SyntCalc::default();
+        sc.token_builder.set_option("backend", "rational").unwrap();
+        sc.token_builder.set_option("cmp_epsilon", "1e-9").unwrap();
+        sc.eval_program("x = 3 * 1km^2\nv = [1/3, 2] s\nu = 2 ± 0.123456\nm = 2\nf(a, b) = a*b + m\nsin(x) = -x").unwrap();
+        sc.eval_statement("y = interval(1, 2) * 1s").unwrap();
+        sc.eval_statement("t = 20 degC").unwrap();
+        sc.eval_statement("w = [-ln(0), ln(0) - ln(0)] s").unwrap();
+        sc.eval_statement("k = interval(ln(0), 1)").unwrap();
+        let session = sc.save_session();
+        // `m` replaces meters, so it comes after `x`
+        assert!(session.find("x = ").unwrap() < session.find("m = ").unwrap());
+
+        let mut restored = SyntCalc::default();
+        restored.eval_statement("z = 1").unwrap();
+        restored.load_session(&session).unwrap();
+        let mut eval = |s: &str| restored.eval_statement(s).map(|v| v.unwrap().to_string());
+        assert_eq!(eval("x").unwrap(), "3000000m^2");
+        assert_eq!(eval("v").unwrap(), "[(1/3), 2]s");
+        assert_eq!(eval("uncertainty(u)").unwrap(), "0.123456");
+        assert_eq!(eval("y").unwrap(), "[1 .. 2]s");
+        assert_eq!(eval("f(2, 3)").unwrap(), "8");
+        assert_eq!(eval("sin(1)").unwrap(), "-1");
+        assert_eq!(eval("t - 0 degC").unwrap(), "20K");
+        assert!(eval("t + t").is_err());
+        assert_eq!(eval("w").unwrap(), "[inf, NaN]s");
+        assert_eq!(eval("k").unwrap(), "[-inf .. 1]");
+        assert!(eval("z").is_err());
+        assert_eq!(restored.token_builder.options(), sc.token_builder.options());
+        assert_eq!(restored.save_session(), session);
+
+        let err = restored.load_session("x = 1\n#opt backend=double\n").unwrap_err();
+        assert_eq!(err.span(), Span::new(6, 25));
+    }
+
+    #[test]
+    fn failed_load_keeps_the_session() {
+        let mut sc = SyntCalc::default();
+        sc.token_builder.set_option("backend", "rational").unwrap();
+        sc.eval_program("x = 1/3\nf(a) = a + x").unwrap();
+        let session = sc.save_session();
+        assert!(sc.load_session("#opt backend=decimal\ny = 2\nx +").is_err());
+        assert!(sc.load_session("#opt backend=double\n").is_err());
+        assert_eq!(sc.save_session(), session);
+        assert_eq!(sc.eval_statement("f(1)").unwrap().unwrap().to_string(), "(4/3)");
+        assert!(sc.eval_statement("y").is_err());
+    }
+}
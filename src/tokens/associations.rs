@@ -1,83 +1,300 @@
 use std::cell::RefCell;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
-use super::{val, Function, Val};
-use super::val::{base_units::*, Unit, ValComputeError, ValComputeErrorType, ValOpts};
+use num_complex::Complex64;
+use num_rational::BigRational;
+use super::{suggest, val, Function, Val};
+use super::val::{base_units::*, decimal, derived_units::*, Interval, Number, Ratio, Unit, ValOpts, SI_PREFIXES};
 
+/// Names of units used to print values.
+/// `all_units` holds the named units a value can be shown in
 #[derive(Clone, Debug)]
 pub(crate) struct UnitAlias{
     all_units: Vec<(val::unit::Unit, String)>
 }
 
+/// Names of the base units in the order of `Unit::dim`
+const BASE_NAMES: [&str; 7] = ["s", "m", "kg", "A", "K", "mol", "cd"];
+/// Order in which base units are printed, as in `kg*m/s^2`
+const BASE_ORDER: [usize; 7] = [2, 1, 0, 3, 4, 5, 6];
+
+/// A unit written as a product of named units raised to powers
+type Factors = Vec<(String, Ratio)>;
+
 impl UnitAlias {
-    pub(crate) fn get_name(&self, unit: &Unit, precision: f64) -> Option<String>{
+    /// Returns the name of the unit if it has one
+    pub(crate) fn get_name(&self, unit: &Unit) -> Option<String>{
         for (u, name) in &self.all_units {
-            let mut flag = true;
-            for i in 0..7 {
-                flag = flag && (u.dim[i] - unit.dim[i]).abs() < precision;
-            }
-            if flag {
+            if u.same_unit(unit) {
                 return Some(name.clone());
             }
         };
         None
     }
 
-    pub(crate) fn append(&mut self, unit: Unit, name: String) {
-        self.all_units.push((unit, name));
+    /// Returns the shortest way to write the unit: its name, a product of base units,
+    /// or a named unit times base units (`N*s` rather than `kg*m/s`)
+    pub(crate) fn format(&self, unit: &Unit) -> String{
+        if let Some(name) = self.get_name(unit) {
+            return name;
+        }
+        let mut best = Self::base_factors(unit);
+        let mut best_complexity = Self::complexity(&best, false);
+        for (u, name) in &self.all_units {
+            if BASE_NAMES.contains(&name.as_str()) {
+                continue;
+            }
+            for p in [Ratio::ONE, -Ratio::ONE] {
+                let Some(rest) = u.checked_pow(p).and_then(|u| unit.checked_div(u)) else { continue };
+                let mut factors = vec![(name.clone(), p)];
+                factors.extend(Self::base_factors(&rest));
+                let complexity = Self::complexity(&factors, true);
+                if complexity < best_complexity {
+                    best = factors;
+                    best_complexity = complexity;
+                }
+            }
+        }
+        Self::render(&best)
     }
-    
-    pub(crate) fn new() -> Self {
-        Self { all_units: Vec::new() }
+
+    fn base_factors(unit: &Unit) -> Factors{
+        BASE_ORDER.iter()
+            .filter(|&&i| !unit.dim[i].is_zero())
+            .map(|&i| (BASE_NAMES[i].to_string(), unit.dim[i]))
+            .collect()
+    }
+
+    /// Number of factors, then whether a derived unit is used, then the sum of the powers.
+    /// So base units win a tie (`m/s^2`, not `N/kg`)
+    fn complexity(factors: &Factors, derived: bool) -> (usize, bool, f64){
+        (factors.len(), derived, factors.iter().map(|(_, p)| p.abs().to_f64()).sum())
+    }
+
+    /// Writes factors as `a*b^2/(c*d^(1/2))`; with no positive powers it is just `/(c*d)`,
+    /// so that it still reads right after a number
+    fn render(factors: &Factors) -> String{
+        let join = |positive: bool| factors.iter()
+            .filter(|(_, p)| (p.numer() > 0) == positive)
+            .map(|(name, p)| match p.abs() {
+                p if p == Ratio::ONE => name.clone(),
+                p if p.is_integer() => format!("{}^{}", name, p),
+                p => format!("{}^({})", name, p),
+            })
+            .collect::<Vec<_>>();
+        let num = join(true);
+        let den = join(false);
+        let mut ret = num.join("*");
+        match den.len() {
+            0 => {},
+            1 => ret += &format!("/{}", den[0]),
+            _ => ret += &format!("/({})", den.join("*")),
+        }
+        ret
     }
 }
 
 impl Default for UnitAlias {
     fn default() -> Self {
-        use val::unit::base_units::*;
+        // Units sharing a dimension with a more common one (Bq, Gy, Sv, lm)
+        // are left out so that they are never picked by accident
         let vec = vec![
-            (M, "m".to_string()),
             (D, "".to_string()),
-            (KG, "kg".to_string()),
-            (KG*M.pow(2.)/S.pow(2.), "J".to_string()),
-            (KG*M.pow(2.)/S.pow(3.), "W".to_string()),
             (S, "s".to_string()),
-            (M/S, "m/s".to_string()),
+            (M, "m".to_string()),
+            (KG, "kg".to_string()),
+            (A, "A".to_string()),
+            (K, "K".to_string()),
+            (MOL, "mol".to_string()),
+            (CD, "cd".to_string()),
+            (HZ, "Hz".to_string()),
+            (N, "N".to_string()),
+            (PA, "Pa".to_string()),
+            (J, "J".to_string()),
+            (W, "W".to_string()),
+            (C, "C".to_string()),
+            (V, "V".to_string()),
+            (F, "F".to_string()),
+            (OHM, "Ω".to_string()),
+            (SIEMENS, "S".to_string()),
+            (WB, "Wb".to_string()),
+            (T, "T".to_string()),
+            (H, "H".to_string()),
+            (LX, "lx".to_string()),
+            (KAT, "kat".to_string()),
         ];
         let vec = vec.into_iter().map(|x|{(x.0, x.1.to_string())}).collect();
         Self { all_units: vec }
     }
 }
 
+/// Variables and units available by name.
+/// Units marked as prefixable can be used with any SI prefix (`km`, `µs`, `GW`)
+/// without having their prefixed forms stored.
+/// Affine units (`degC`) are kept apart, as they are not a multiple of a base unit
 #[derive(Clone)]
 pub struct ValAlias{
     map: HashMap<String, Val>,
+    prefixable: HashSet<String>,
+    /// Name -> (size in kelvins, zero point in kelvins)
+    affine: HashMap<String, (Number, Number)>,
+    /// Variables declared by the user, with what they replaced
+    vars: HashMap<String, Shadowed>,
     valopts: Arc<RefCell<ValOpts>>, 
 }
 
+/// A name as it was before a variable was declared with it
+#[derive(Clone)]
+struct Shadowed{
+    val: Option<Val>,
+    prefixable: bool,
+    affine: Option<(Number, Number)>,
+}
+
 
 impl ValAlias {
     pub fn new (valopts: Arc<RefCell<ValOpts>>) -> Self{
         let map: HashMap<String, Val> = HashMap::new();
-        ValAlias{map, valopts}
+        ValAlias{map, prefixable: HashSet::new(), affine: HashMap::new(), vars: HashMap::new(), valopts}
     }
     pub fn insert_default (&mut self) -> &Self{
-        self.map.insert(String::from("m"), Val::new(1., M, self.valopts.clone()));
-        self.map.insert(String::from("km"), Val::new(1000., M, self.valopts.clone()));
-        self.map.insert(String::from("g"), Val::new(0.001, KG, self.valopts.clone()));
-        self.map.insert(String::from("kg"), Val::new(1., KG, self.valopts.clone()));
-        self.map.insert(String::from("min"), Val::new(60., S, self.valopts.clone()));
-        self.map.insert(String::from("ms"), Val::new(0.001, S, self.valopts.clone()));
-        self.map.insert(String::from("s"), Val::new(1., S, self.valopts.clone()));
-        self.map.insert(String::from("J"), Val::new(1., KG*M.pow(2.)/S.pow(2.), self.valopts.clone()));
-        self.map.insert(String::from("W"), Val::new(1., KG*M.pow(2.)/S.pow(3.), self.valopts.clone()));
-        self.map.insert(String::from("pi"), Val::new(std::f64::consts::PI, D, self.valopts.clone()));
+        self.add_unit("m", 1., M, true);
+        // prefixes go on gram, kilogram is just an alias
+        self.add_unit("g", 0.001, KG, true);
+        self.add_unit("kg", 1., KG, false);
+        self.add_unit("min", 60., S, false);
+        self.add_unit("h", 3600., S, false);
+        self.add_unit("Wh", 3600., W*S, true);
+        self.add_unit("s", 1., S, true);
+        self.add_unit("A", 1., A, true);
+        self.add_unit("K", 1., K, true);
+        self.add_unit("mol", 1., MOL, true);
+        self.add_unit("cd", 1., CD, true);
+
+        self.add_unit("rad", 1., D, true);
+        self.add_unit("sr", 1., D, true);
+        self.add_unit("Hz", 1., HZ, true);
+        self.add_unit("N", 1., N, true);
+        self.add_unit("Pa", 1., PA, true);
+        self.add_unit("J", 1., J, true);
+        self.add_unit("W", 1., W, true);
+        self.add_unit("C", 1., C, true);
+        self.add_unit("V", 1., V, true);
+        self.add_unit("F", 1., F, true);
+        self.add_unit("Ω", 1., OHM, true);
+        self.add_unit("ohm", 1., OHM, true);
+        self.add_unit("S", 1., SIEMENS, true);
+        self.add_unit("Wb", 1., WB, true);
+        self.add_unit("T", 1., T, true);
+        self.add_unit("H", 1., H, true);
+        self.add_unit("lm", 1., LM, true);
+        self.add_unit("lx", 1., LX, true);
+        self.add_unit("Bq", 1., BQ, true);
+        self.add_unit("Gy", 1., GY, true);
+        self.add_unit("Sv", 1., SV, true);
+        self.add_unit("kat", 1., KAT, true);
+        // not a unit, so not made exact
+        self.add_alias("pi".to_string(), Val::new(std::f64::consts::PI, D, self.valopts.clone()));
+        // named as floats print them, so that printed values read back
+        self.add_alias("inf".to_string(), Val::new(f64::INFINITY, D, self.valopts.clone()));
+        self.add_alias("NaN".to_string(), Val::new(f64::NAN, D, self.valopts.clone()));
+        // imaginary unit, `j` as used in electrical engineering
+        for name in ["i", "j"] {
+            self.add_alias(name.to_string(), Val::from_number(Number::complex(0., 1.), D, self.valopts.clone()));
+        }
 
+        // temperature scales; differences of temperatures are written with `delta_`
+        let rankine = Number::rational(BigRational::new(5.into(), 9.into()));
+        for (names, scale, zero) in [
+            (["degC", "°C"], Number::exact_from_f64(1.), Number::exact_from_f64(273.15)),
+            (["degF", "°F"], rankine.clone(), Number::exact_from_f64(459.67).mul(&rankine)),
+            (["degR", "°R"], rankine.clone(), Number::exact_from_f64(0.)),
+        ] {
+            for name in names {
+                self.add_affine(name, scale.clone(), zero.clone());
+            }
+        }
+        self.add_unit("delta_degC", 1., K, false);
+        for name in ["delta_degF", "delta_degR"] {
+            self.add_unit_number(name, rankine.clone(), K, false);
+        }
+
+        self
+    }
+    /// Adds imperial and US customary units. Not added by default, as names like `pt` or `ton`
+    /// are ambiguous. Inch is `inch`, since `in` is a conversion keyword
+    pub fn insert_imperial (&mut self) -> &Self{
+        let units = [
+            // length
+            ("inch", 0.0254, M), ("ft", 0.3048, M), ("yd", 0.9144, M),
+            ("mi", 1609.344, M), ("nmi", 1852., M), ("mil", 2.54e-5, M),
+            // area
+            ("acre", 4046.8564224, M.powi(2)),
+            // mass
+            ("gr", 6.479891e-5, KG), ("oz", 0.028349523125, KG), ("lb", 0.45359237, KG),
+            ("st", 6.35029318, KG), ("ton", 907.18474, KG), ("long_ton", 1016.0469088, KG),
+            // volume, US unless said otherwise
+            ("tsp", 4.92892159375e-6, M.powi(3)), ("tbsp", 1.478676478125e-5, M.powi(3)),
+            ("floz", 2.95735295625e-5, M.powi(3)), ("cup", 2.365882365e-4, M.powi(3)),
+            ("pt", 4.73176473e-4, M.powi(3)), ("qt", 9.46352946e-4, M.powi(3)),
+            ("gal", 3.785411784e-3, M.powi(3)), ("imp_gal", 4.54609e-3, M.powi(3)),
+            ("bbl", 0.158987294928, M.powi(3)),
+            // speed
+            ("mph", 0.44704, M/S), ("kn", 1852./3600., M/S),
+            // force and pressure
+            ("lbf", 4.4482216152605, N), ("psi", 6894.757293168361, PA), ("inHg", 3386.389, PA),
+            // energy and power
+            ("BTU", 1055.05585262, J), ("ftlbf", 1.3558179483314004, J), ("therm", 105_505_585.262, J),
+            ("hp", 745.699_871_582_270_2, W),
+        ];
+        for (name, magn, unit) in units {
+            self.add_unit(name, magn, unit, false);
+        }
         self
     }
+    /// Adds a unit; `prefixable` tells if SI prefixes can be applied to it.
+    /// Sizes of units are definitions, so they are kept as exact numbers
+    pub fn add_unit (&mut self, name: &str, magn: f64, unit: Unit, prefixable: bool){
+        self.add_unit_number(name, Number::exact_from_f64(magn), unit, prefixable);
+    }
+    /// Same as [`ValAlias::add_unit`] for sizes that are not nice floats, like 5/9
+    pub fn add_unit_number (&mut self, name: &str, magn: Number, unit: Unit, prefixable: bool){
+        self.map.insert(name.to_string(), Val::from_number(magn, unit, self.valopts.clone()));
+        if prefixable {
+            self.prefixable.insert(name.to_string());
+        }else{
+            self.prefixable.remove(name);
+        }
+    }
+    /// Adds a temperature scale whose degree is `scale` kelvins and whose zero is at `zero` kelvins
+    pub fn add_affine (&mut self, name: &str, scale: Number, zero: Number){
+        self.map.remove(name);
+        self.prefixable.remove(name);
+        self.affine.insert(name.to_string(), (scale, zero));
+    }
+    /// Returns the size of the degree and the zero point of a temperature scale, both in kelvins
+    pub fn get_affine (&self, key: &str) -> Option<(Number, Number)>{
+        self.affine.get(key).cloned()
+    }
+    pub fn is_prefixable (&self, key: &str) -> bool{
+        self.prefixable.contains(key)
+    }
+    /// Looks up a name, resolving an SI prefix if there is no exact match.
+    /// Longer prefixes are tried first, so `dam` is a decameter
     pub fn get_val(&self, key: &str) -> Option<Val>{
-        self.map.get(key).cloned()
-
+        // pi has as many digits as the decimals it is used with, unless the user replaced it
+        if key == "pi" && !self.vars.contains_key(key) {
+            let opts = self.valopts.borrow();
+            return self.map.get(key).map(|val| val.map_number(|_| Number::pi(opts.get_backend(), opts.get_decimal_digits())));
+        }
+        if let Some(val) = self.map.get(key) {
+            return Some(val.clone());
+        }
+        let (prefix, factor) = SI_PREFIXES.iter()
+            .filter(|(prefix, _)| key.strip_prefix(prefix).is_some_and(|rest| self.prefixable.contains(rest)))
+            .max_by_key(|(prefix, _)| prefix.len())?;
+        let unit = self.map.get(&key[prefix.len()..])?;
+        Some(unit.map_number(|magn| magn.mul(&Number::exact_from_f64(*factor))))
     }
     pub fn get_map (&self) -> &HashMap<String, Val>{
         &self.map
@@ -86,17 +303,126 @@ impl ValAlias {
         self.map = map;
         self
     }
+    /// Adds a variable. Variables do not take prefixes,
+    /// even if they replace a unit that did
     pub fn add_alias (&mut self, key: String, value: Val){
+        self.prefixable.remove(&key);
+        self.affine.remove(&key);
         self.map.insert(key, value);
     }
+    /// Declares a user variable. Unlike [`ValAlias::add_alias`], the unit or constant
+    /// it replaces comes back when the variable is removed with [`ValAlias::undefine`]
+    pub fn define (&mut self, key: String, value: Val){
+        if !self.vars.contains_key(&key) {
+            let shadowed = Shadowed{
+                val: self.map.get(&key).cloned(),
+                prefixable: self.prefixable.contains(&key),
+                affine: self.affine.get(&key).cloned(),
+            };
+            self.vars.insert(key.clone(), shadowed);
+        }
+        self.add_alias(key, value);
+    }
+    /// Removes a user variable, returns false if there is no such variable
+    pub fn undefine (&mut self, key: &str) -> bool{
+        let Some(shadowed) = self.vars.remove(key) else {return false};
+        self.map.remove(key);
+        if let Some(val) = shadowed.val {
+            self.map.insert(key.to_string(), val);
+        }
+        if shadowed.prefixable {
+            self.prefixable.insert(key.to_string());
+        }
+        if let Some(affine) = shadowed.affine {
+            self.affine.insert(key.to_string(), affine);
+        }
+        true
+    }
+    /// Whether a user variable replaces a unit or a constant
+    pub fn shadows (&self, key: &str) -> bool{
+        self.vars.get(key).is_some_and(|shadowed| shadowed.val.is_some() || shadowed.affine.is_some())
+    }
+    /// User variables with their values, sorted by name
+    pub fn vars (&self) -> Vec<(&str, &Val)>{
+        let mut ret: Vec<_> = self.vars.keys()
+            .filter_map(|name| Some((name.as_str(), self.map.get(name)?)))
+            .collect();
+        ret.sort_by_key(|(name, _)| *name);
+        ret
+    }
+    /// Names of the units, temperature scales and constants, sorted.
+    /// Prefixed forms are not listed
+    pub fn units (&self) -> Vec<&str>{
+        let mut ret: Vec<_> = self.names().filter(|name| !self.vars.contains_key(*name)).collect();
+        ret.sort();
+        ret
+    }
+    /// Names of the variables, units and temperature scales, without the prefixed ones
+    pub fn names (&self) -> impl Iterator<Item = &str>{
+        self.map.keys().chain(self.affine.keys()).map(String::as_str)
+    }
+    /// Returns names that a missing `name` was most likely meant to be, best first.
+    /// Besides plain typos, tries splitting off an SI prefix and correcting the rest (`kM` -> `km`)
+    pub fn suggest (&self, name: &str) -> Vec<String>{
+        let names = || self.names();
+        let mut scored: Vec<_> = names()
+            .filter_map(|key| suggest::score(name, key).map(|s| (s, key.to_string())))
+            .collect();
+        for (prefix, _) in SI_PREFIXES {
+            let Some(rest) = name.strip_prefix(prefix).filter(|rest| !rest.is_empty()) else {continue};
+            for key in names() {
+                let candidate = format!("{}{}", prefix, key);
+                if let (Some(s), Some(_)) = (suggest::score(rest, key), self.get_val(&candidate)) {
+                    scored.push((s, candidate));
+                }
+            }
+        }
+        suggest::best(scored)
+    }
 }
 
 type FnMap = HashMap<String, Function>;
 #[derive(Clone)]
 pub struct FnAlias {
     map: FnMap,
+    /// Functions declared by the user, with the built-in function they replaced
+    user: HashMap<String, Option<Function>>,
 }
 
+/// Short descriptions of the built-in functions, `if` included
+const FN_HELP: &[(&str, &str)] = &[
+    ("ln", "ln(x): natural logarithm"),
+    ("sin", "sin(x): sine of an angle in radians"),
+    ("cos", "cos(x): cosine of an angle in radians"),
+    ("tan", "tan(x): tangent of an angle in radians"),
+    ("cot", "cot(x): cotangent of an angle in radians"),
+    ("arcsin", "arcsin(x): inverse sine, in radians"),
+    ("arccos", "arccos(x): inverse cosine, in radians"),
+    ("arctan", "arctan(x): inverse tangent, in radians"),
+    ("arccot", "arccot(x): inverse cotangent, in radians"),
+    ("abs", "abs(x): absolute value, the modulus of a complex number"),
+    ("sqrt", "sqrt(x): square root, the unit is rooted as well"),
+    ("arg", "arg(z): argument of a complex number"),
+    ("conj", "conj(z): complex conjugate"),
+    ("re", "re(z): real part of a complex number"),
+    ("im", "im(z): imaginary part of a complex number"),
+    ("fract", "fract(x): fractional part"),
+    ("dot", "dot(a, b): dot product of vectors"),
+    ("cross", "cross(a, b): cross product of 3-vectors"),
+    ("matmul", "matmul(a, b): matrix product"),
+    ("solve", "solve(a, b): solution x of the linear system a*x = b"),
+    ("transpose", "transpose(a): transposed matrix"),
+    ("det", "det(a): determinant of a square matrix"),
+    ("inv", "inv(a): inverse of a square matrix"),
+    ("norm", "norm(a): euclidean norm of a vector"),
+    ("interval", "interval(lo, hi): interval from lo to hi"),
+    ("uncertainty", "uncertainty(x): standard uncertainty of a value"),
+    ("nominal", "nominal(x): value without its uncertainty"),
+    ("rem", "rem(a, b): remainder of a / b, with the sign of a"),
+    ("mod", "mod(a, b): remainder of a / b, with the sign of b"),
+    ("if", "if(cond, a, b): a if cond is true, otherwise b; only one of them is evaluated"),
+];
+
 impl Default for FnAlias {
     fn default() -> Self {
         let mut ret = FnAlias::new();
@@ -108,94 +434,113 @@ impl Default for FnAlias {
 
 impl  FnAlias {
     pub fn new  () -> Self{
-        Self { map: FnMap::new()}
+        Self { map: FnMap::new(), user: HashMap::new()}
     }
     pub fn insert_default(&mut self) -> &Self{
-        self.map.insert( "ln".to_string(), Function { lambda: Arc::new(
+        self.map.insert( "ln".to_string(), Function::new(Arc::new(
             |x: Vec<Val>|{
-                if x[0].get_magnetude() < 0.{
-                    return Err(ValComputeError::new(
-                            "Can not take a logarithm of a negative number".to_string(),
-                            ValComputeErrorType::Other));
-                }
-                let mut ret = x[0].clone();
-                ret.set_magnetude(ret.get_magnetude().ln());
-                Ok(ret)
+                x[0].try_map_number(|n| n.map_analytic(f64::ln, Complex64::ln, Interval::ln, decimal::ln))
             }),
-            argc: 1});
+            1));
         
-        self.map.insert("sin".to_string(), Function {
-            lambda: 
+        self.map.insert("sin".to_string(), Function::new(
             Arc::new( |x| {
-                let mut ret = x[0].clone();
-                ret.set_magnetude(ret.get_magnetude().sin());
-                Ok(ret)
+                x[0].try_map_number(|n| n.map_analytic(f64::sin, Complex64::sin, Interval::sin, decimal::sin))
             }),
-            argc: 1
-        });
-        self.map.insert("cos".to_string(), Function {
-            lambda: 
+            1
+        ));
+        self.map.insert("cos".to_string(), Function::new(
             Arc::new(|x| {
-                let mut ret = x[0].clone();
-                ret.set_magnetude(ret.get_magnetude().cos());
-                Ok(ret)
+                x[0].try_map_number(|n| n.map_analytic(f64::cos, Complex64::cos, Interval::cos, decimal::cos))
             }),
-            argc: 1
-        });
+            1
+        ));
         self.map.insert("tan".to_string(), Function::new(
                 Arc::new(|x|{
-                    let mut ret = x[0].clone();
-                    ret.set_magnetude(ret.get_magnetude().tan());
-                    Ok(ret)
+                    x[0].try_map_number(|n| n.map_analytic(f64::tan, Complex64::tan, Interval::tan, decimal::tan))
                 }),1));
         self.map.insert("cot".to_string(), Function::new(
                 Arc::new(|x|{
-                    let mut ret = x[0].clone();
-                    ret.set_magnetude(ret.get_magnetude().tan().recip());
-                    Ok(ret)
+                    x[0].try_map_number(|n| n.map_analytic(|x| x.tan().recip(), |z| z.tan().inv(), Interval::cot, decimal::cot))
                 }),1));
         self.map.insert("arcsin".to_string(), Function::new(
                 Arc::new(|x|{
-                    let mut ret = x[0].clone();
-                    ret.set_magnetude(ret.get_magnetude().asin());
-                    Ok(ret)
+                    x[0].try_map_number(|n| n.map_analytic(f64::asin, Complex64::asin, Interval::asin, decimal::asin))
                 }),1));
         self.map.insert("arccos".to_string(), Function::new(
                 Arc::new(|x|{
-                    let mut ret = x[0].clone();
-                    ret.set_magnetude(ret.get_magnetude().acos());
-                    Ok(ret)
+                    x[0].try_map_number(|n| n.map_analytic(f64::acos, Complex64::acos, Interval::acos, decimal::acos))
                 }),1));
-        self.map.insert("arctan".to_string(), Function{
-            lambda: Arc::new(|x|{
-                let mut ret = x[0].clone();
-                ret.set_magnetude(ret.get_magnetude().atan());
-                Ok(ret)
+        self.map.insert("arctan".to_string(), Function::new(
+            Arc::new(|x|{
+                x[0].try_map_number(|n| n.map_analytic(f64::atan, Complex64::atan, Interval::atan, decimal::atan))
             }),
-            argc: 1
-        });
-        self.map.insert("arccot".to_string(), Function{
-            lambda: Arc::new(|x|{
-                let mut ret = x[0].clone();
-                ret.set_magnetude(-(ret.get_magnetude().atan()-std::f64::consts::PI/2.));
-                Ok(ret)
+            1
+        ));
+        self.map.insert("arccot".to_string(), Function::new(
+            Arc::new(|x|{
+                x[0].try_map_number(|n| n.map_analytic(|x| std::f64::consts::PI/2. - x.atan(), |z| std::f64::consts::PI/2. - z.atan(), Interval::acot, decimal::acot))
             }),
-            argc: 1
-        });
+            1
+        ));
         self.map.insert("abs".to_string() , Function::new(
             Arc::new(|x|{
-                let mut ret = x[0].clone();
-                ret.set_magnetude(ret.get_magnetude().abs());
-                Ok(ret)
+                Ok(x[0].map_number(Number::abs))
             }),1   
         ));
+        self.map.insert("sqrt".to_string() , Function::new(
+            Arc::new(|x| x[0].sqrt()), 1));
+        self.map.insert("arg".to_string() , Function::new(
+            Arc::new(|x|{
+                Ok(Val::from_number(x[0].get_number().arg(), D, x[0].get_opts()))
+            }),1
+        ));
+        self.map.insert("conj".to_string() , Function::new(
+            Arc::new(|x|{
+                Ok(x[0].map_number(Number::conj))
+            }),1
+        ));
+        self.map.insert("re".to_string() , Function::new(
+            Arc::new(|x|{
+                Ok(x[0].map_number(Number::re))
+            }),1
+        ));
+        self.map.insert("im".to_string() , Function::new(
+            Arc::new(|x|{
+                Ok(x[0].map_number(Number::im))
+            }),1
+        ));
         self.map.insert("fract".to_string() , Function::new(
             Arc::new(|x|{
-                let mut ret = x[0].clone();
-                ret.set_magnetude(ret.get_magnetude().fract());
-                Ok(ret)
+                Ok(x[0].map_number(Number::fract))
             }),1   
         ));
+        self.map.insert("dot".to_string(), Function::new(
+            Arc::new(|x| x[0].dot(&x[1])), 2));
+        self.map.insert("cross".to_string(), Function::new(
+            Arc::new(|x| x[0].cross(&x[1])), 2));
+        self.map.insert("matmul".to_string(), Function::new(
+            Arc::new(|x| x[0].matmul(&x[1])), 2));
+        self.map.insert("solve".to_string(), Function::new(
+            Arc::new(|x| x[0].solve(&x[1])), 2));
+        self.map.insert("transpose".to_string(), Function::new(
+            Arc::new(|x| x[0].transpose()), 1));
+        self.map.insert("det".to_string(), Function::new(
+            Arc::new(|x| x[0].det()), 1));
+        self.map.insert("inv".to_string(), Function::new(
+            Arc::new(|x| x[0].inv()), 1));
+        self.map.insert("norm".to_string(), Function::new(
+            Arc::new(|x| x[0].norm()), 1));
+        self.map.insert("interval".to_string(), Function::new(
+            Arc::new(|x| x[0].interval(&x[1])), 2));
+        self.map.insert("uncertainty".to_string(), Function::new(
+            Arc::new(|x| Ok(x[0].map_number(Number::uncertainty))), 1));
+        self.map.insert("nominal".to_string(), Function::new(
+            Arc::new(|x| Ok(x[0].map_number(Number::nominal))), 1));
+        self.map.insert("rem".to_string(), Function::new(
+            Arc::new(|x| x[0].rem(&x[1])), 2));
+        self.map.insert("mod".to_string(), Function::new(
+            Arc::new(|x| x[0].modulo(&x[1])), 2));
         self
     }
     pub fn get_fn(&self, key: &str) -> Option<Function>{
@@ -211,18 +556,135 @@ impl  FnAlias {
     pub fn add_alias(&mut self, key: String, value: Function){
         self.map.insert(key, value);
     }
+    /// Declares a user function. The built-in function it replaces
+    /// comes back when it is removed with [`FnAlias::undefine`]
+    pub fn define(&mut self, key: String, value: Function){
+        if !self.user.contains_key(&key) {
+            self.user.insert(key.clone(), self.map.get(&key).cloned());
+        }
+        self.map.insert(key, value);
+    }
+    /// Removes a user function, returns false if there is no such function
+    pub fn undefine(&mut self, key: &str) -> bool{
+        let Some(replaced) = self.user.remove(key) else {return false};
+        match replaced {
+            Some(func) => self.map.insert(key.to_string(), func),
+            None => self.map.remove(key),
+        };
+        true
+    }
+    /// User functions, sorted by name
+    pub fn user_fns(&self) -> Vec<(&str, &Function)>{
+        let mut ret: Vec<_> = self.user.keys()
+            .filter_map(|name| Some((name.as_str(), self.map.get(name)?)))
+            .collect();
+        ret.sort_by_key(|(name, _)| *name);
+        ret
+    }
+    /// Names of the built-in functions that are not replaced by user ones, sorted
+    pub fn builtins(&self) -> Vec<&str>{
+        let mut ret: Vec<_> = self.map.keys()
+            .map(String::as_str)
+            .filter(|name| !self.user.contains_key(*name))
+            .collect();
+        ret.sort();
+        ret
+    }
+    /// Description of a built-in function
+    pub fn help(name: &str) -> Option<&'static str>{
+        FN_HELP.iter().find(|(key, _)| *key == name).map(|(_, help)| *help)
+    }
+    /// Returns names of functions that a missing `name` was most likely meant to be, best first
+    pub fn suggest(&self, name: &str) -> Vec<String>{
+        suggest::closest(name, self.map.keys().map(String::as_str))
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn si_prefixes(){
+        let mut al = ValAlias::new(ValOpts::shared());
+        al.insert_default();
+        let magn = |name: &str| al.get_val(name).map(|v| v.get_magnetude());
+        assert_eq!(magn("km"), Some(1000.));
+        assert_eq!(magn("mg"), Some(1e-6));
+        assert_eq!(magn("Mg"), Some(1000.));
+        assert_eq!(magn("µs"), Some(1e-6));
+        assert_eq!(magn("us"), Some(1e-6));
+        assert_eq!(magn("dam"), Some(10.));
+        assert_eq!(magn("GW"), Some(1e9));
+        assert_eq!(magn("QJ"), Some(1e30));
+        assert_eq!(magn("min"), Some(60.));
+        assert_eq!(magn("mkg"), None);
+        assert_eq!(magn("kmin"), None);
+        assert_eq!(magn("kkm"), None);
+        assert_eq!(al.get_val("mg").unwrap().get_unit(), KG);
+
+
+        al.add_alias("x".to_string(), Val::new(2., D, al.valopts.clone()));
+        assert!(al.get_val("kx").is_none());
+        al.add_alias("s".to_string(), Val::new(2., D, al.valopts.clone()));
+        assert!(al.get_val("ms").is_none());
+    }
+
+    #[test]
+    fn user_definitions(){
+        let mut al = ValAlias::new(ValOpts::shared());
+        al.insert_default();
+        al.define("x".to_string(), Val::new(2., D, al.valopts.clone()));
+        al.define("s".to_string(), Val::new(3., D, al.valopts.clone()));
+        al.define("s".to_string(), Val::new(4., D, al.valopts.clone()));
+        assert_eq!(al.vars().iter().map(|(name, _)| *name).collect::<Vec<_>>(), ["s", "x"]);
+        assert!(!al.units().contains(&"s"));
+        assert!(al.undefine("s"));
+        assert_eq!(al.get_val("ms").unwrap().get_unit(), S);
+        assert!(al.undefine("x"));
+        assert!(!al.undefine("x"));
+        assert!(!al.undefine("m"));
+        assert!(al.get_val("x").is_none());
+
+        let mut fal = FnAlias::default();
+        let sin = fal.get_fn("sin").unwrap();
+        fal.define("sin".to_string(), fal.get_fn("cos").unwrap());
+        fal.define("f".to_string(), sin);
+        assert_eq!(fal.user_fns().iter().map(|(name, _)| *name).collect::<Vec<_>>(), ["f", "sin"]);
+        assert!(!fal.builtins().contains(&"sin"));
+        assert!(fal.undefine("sin") && fal.undefine("f"));
+        assert!(fal.builtins().contains(&"sin"));
+        assert!(fal.get_fn("f").is_none());
+        assert!(fal.builtins().iter().all(|name| FnAlias::help(name).is_some()));
+    }
+
     #[test]
     fn unit_alias(){
        let al = UnitAlias::default();
-       assert_eq!(al.get_name(&M, 0.0001).unwrap(), "m");
-       assert_eq!(al.get_name(&KG, 0.0001).unwrap(), "kg");
-       assert_ne!(al.get_name(&D, 0.0001).unwrap(), "kg");
+       assert_eq!(al.get_name(&M).unwrap(), "m");
+       assert_eq!(al.get_name(&KG).unwrap(), "kg");
+       assert_ne!(al.get_name(&D).unwrap(), "kg");
+       assert_eq!(al.get_name(&(KG*M/S.powi(2))).unwrap(), "N");
+       assert_eq!(al.get_name(&(W/A)).unwrap(), "V");
+       assert_eq!(al.get_name(&(V/A)).unwrap(), "Ω");
+       assert_eq!(al.get_name(&S.powi(-1)).unwrap(), "Hz");
+    }
+
+    #[test]
+    fn unit_format(){
+        let al = UnitAlias::default();
+        let format = |unit: Unit| al.format(&unit);
+        assert_eq!(format(KG*M/S.powi(2)), "N");
+        assert_eq!(format(M/S), "m/s");
+        assert_eq!(format(M/S.powi(2)), "m/s^2");
+        assert_eq!(format(M.powi(3)), "m^3");
+        assert_eq!(format(KG*M/S), "N*s");
+        assert_eq!(format(KG/(M*S)), "Pa*s");
+        assert_eq!(format(V/M), "V/m");
+        assert_eq!(format(M.powi(-1)), "/m");
+        assert_eq!(format(KG/(M.powi(3)*MOL)), "kg/(m^3*mol)");
+        assert_eq!(format(M.pow(Ratio::new(1, 2))), "m^(1/2)");
+        assert_eq!(format(KG.pow(Ratio::new(-3, 2))), "/kg^(3/2)");
     }
 }
 
@@ -1,39 +1,86 @@
 pub mod val;
 pub mod token_builder;
 pub mod associations;
+pub mod suggest;
 
 mod operators;
 
 use std::sync::Arc;
 
-use val::ValComputeError;
+use val::{ValComputeError, ValComputeErrorType};
 pub use val::Val as Val;
 pub use operators::*;
 
 
+#[derive(Clone)]
+enum FnImpl {
+    Native(Arc<dyn Fn(Vec<Val>) -> Result<Val, ValComputeError>>),
+    /// Expression tree with argument names replaced by `Node::Arg` slots
+    Compiled(Arc<Node>),
+}
+
 #[derive(Clone)]
 pub struct Function{
-    lambda: Arc<dyn Fn(Vec<Val>) -> Result<Val, ValComputeError>>,
+    imp: FnImpl,
     argc: u32,
+    /// Declaration a user defined function was made from, as it was written
+    definition: Option<Arc<Ast>>,
 }
 
 
 use self::associations::FnAlias;
+use crate::ast::{Ast, Node};
 impl Function{
     pub fn new (lambda: Arc<dyn Fn(Vec<Val>) -> Result<Val, ValComputeError>>,  argc: u32) -> Self{
-        Function{lambda, argc}
+        Function{imp: FnImpl::Native(lambda), argc, definition: None}
+    }
+    /// Creates a user defined function from a compiled body.
+    /// Such functions are evaluated by `SyntCalc`, see [`Function::get_body`]
+    pub fn compiled (body: Node, argc: u32) -> Self{
+        Function{imp: FnImpl::Compiled(Arc::new(body)), argc, definition: None}
+    }
+    /// Attaches the declaration the function was made from
+    pub fn with_definition (mut self, definition: Ast) -> Self{
+        self.definition = Some(Arc::new(definition));
+        self
+    }
+    /// Returns the declaration of a user defined function, e.g. `f(x) = x^2`
+    pub fn get_definition(&self) -> Option<&Ast> {
+        self.definition.as_deref()
     }
-    pub fn compute (&self, args: Vec<Val>) -> Result<Val, String> {
-        if args.len() ==  self.argc as usize{
-            (self.lambda)(args).map_err(|x| x.to_string())
-        }else{
-            Err(format!("Argument number do not match.\nExpected: {}, found: {}", self.argc, args.len()))
+    /// Returns the body of a user defined function
+    /// or `None` for a native one
+    pub fn get_body(&self) -> Option<&Node> {
+        match &self.imp {
+            FnImpl::Native(_) => None,
+            FnImpl::Compiled(body) => Some(body),
         }
     }
-    pub fn from_str(s: &str, al: &FnAlias) -> Result<Self, String>{
-        match al.get_fn(s){
-            Some(x) => Ok(Function::new(x.lambda, x.argc)),
-            None => Err(format!("No such function: {}", s)),
+    pub fn get_argc(&self) -> u32 {
+        self.argc
+    }
+    /// Computes a native function.
+    /// User defined functions need an evaluation context and can not be computed this way
+    pub fn compute (&self, args: Vec<Val>) -> Result<Val, ValComputeError> {
+        if args.len() != self.argc as usize {
+            return Err(ValComputeError::new(
+                    format!("Argument number do not match.\nExpected: {}, found: {}", self.argc, args.len()),
+                    ValComputeErrorType::Other));
+        }
+        match &self.imp {
+            FnImpl::Native(lambda) => {
+                for arg in &args {
+                    arg.check_number("Argument of a built-in function")?;
+                    arg.check_relative("Argument of a built-in function")?;
+                }
+                lambda(args)
+            }
+            FnImpl::Compiled(_) => Err(ValComputeError::new(
+                    "User defined functions can only be computed by SyntCalc".to_string(),
+                    ValComputeErrorType::Other)),
         }
     }
+    pub fn from_str(s: &str, al: &FnAlias) -> Result<Self, String>{
+        al.get_fn(s).ok_or_else(|| format!("No such function: {}{}", s, suggest::did_you_mean(&al.suggest(s))))
+    }
 }
@@ -1,4 +1,4 @@
-use std::{fmt::Display, str::FromStr};
+use std::{cmp::Ordering, fmt::Display, str::FromStr};
 
 use super::{val::ValComputeError, Val};
 
@@ -17,14 +17,26 @@ pub trait Operator  where Self: Clone{
 }
 
 /// A binary operator struct
-#[derive(Clone, Copy, Debug)]
-enum BinOps {
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BinOps {
     Add,
     Sub,
     Mul,
     Div,
     Pow,
-    Mod,
+    Rem,
+    FloorDiv,
+    /// Value with an uncertainty
+    PlusMinus,
+    Greater,
+    Less,
+    Equal,
+    GreaterEqual,
+    LessEqual,
+    NotEqual,
+    And,
+    Or,
+    Xor,
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -39,26 +51,64 @@ impl BinOperator {
     }
     pub fn compute (&self, lhs: Val, rhs: Val) -> Result<Val, ValComputeError> {
         use BinOps::*;
+        let opts = lhs.get_opts();
+        let ordered = |accept: &[Ordering]| -> Result<Val, ValComputeError> {
+            let ordering = lhs.compare(&rhs)?;
+            Ok(Val::boolean(accept.contains(&ordering), opts.clone()))
+        };
+        match self.kind {
+            Greater => return ordered(&[Ordering::Greater]),
+            Less => return ordered(&[Ordering::Less]),
+            GreaterEqual => return ordered(&[Ordering::Greater, Ordering::Equal]),
+            LessEqual => return ordered(&[Ordering::Less, Ordering::Equal]),
+            Equal => return Ok(Val::boolean(lhs.equals(&rhs)?, opts)),
+            NotEqual => return Ok(Val::boolean(!lhs.equals(&rhs)?, opts)),
+            And => return lhs.logical(&rhs, "&&", |a, b| a && b),
+            Or => return lhs.logical(&rhs, "||", |a, b| a || b),
+            Xor => return lhs.logical(&rhs, "xor", |a, b| a != b),
+            _ => {}
+        }
+        lhs.check_number(&format!("Operand of {}", self))?;
+        rhs.check_number(&format!("Operand of {}", self))?;
+        // absolute temperatures can only be shifted, so only sums and differences check them themselves
+        if !matches!(self.kind, Add | Sub | PlusMinus) {
+            lhs.check_relative(&format!("Operand of {}", self))?;
+            rhs.check_relative(&format!("Operand of {}", self))?;
+        }
         match self.kind {
             Add => lhs + rhs,
             Sub => lhs - rhs,
-            Mul => Ok(lhs * rhs),
-            Div => Ok(lhs / rhs),
+            Mul => lhs * rhs,
+            Div => lhs / rhs,
             Pow => lhs.pow_val(&rhs),
-            Mod => todo!(),
+            Rem => lhs.rem(&rhs),
+            FloorDiv => lhs.floor_div(&rhs),
+            PlusMinus => lhs.plus_minus(&rhs),
+            Greater | Less | Equal | GreaterEqual | LessEqual | NotEqual | And | Or | Xor => unreachable!(),
         }
     }
     pub fn match_str(s: &str) -> Result<Self, String> {
         use BinOps::*;
         use Associativity::*;
         match match s{
-            "+" => Some((Add, 2, Left)),
-            "-" => Some((Sub, 2, Left)),
-            "*" => Some((Mul, 4, Left)),
-            "/" => Some((Div, 4, Left)),
-            "^" => Some((Pow, 6, Right)),
-            "**" => Some((Pow, 6, Right)),
-            "%" => Some((Mod, 6, Left)),
+            "||" => Some((Or, 1, Left)),
+            "xor" => Some((Xor, 2, Left)),
+            "&&" => Some((And, 3, Left)),
+            ">" => Some((Greater, 5, Non)),
+            "<" => Some((Less, 5, Non)),
+            "==" => Some((Equal, 5, Non)),
+            ">=" => Some((GreaterEqual, 5, Non)),
+            "<=" => Some((LessEqual, 5, Non)),
+            "!=" => Some((NotEqual, 5, Non)),
+            "+" => Some((Add, 6, Left)),
+            "-" => Some((Sub, 6, Left)),
+            "±" | "+/-" => Some((PlusMinus, 7, Left)),
+            "*" => Some((Mul, 8, Left)),
+            "/" => Some((Div, 8, Left)),
+            "%" => Some((Rem, 8, Left)),
+            "div" => Some((FloorDiv, 8, Left)),
+            "^" => Some((Pow, 10, Right)),
+            "**" => Some((Pow, 10, Right)),
             _ => None,
         }{
             Some((op, prec, ass)) => Ok(Self::new(op, prec, ass)),
@@ -68,6 +118,9 @@ impl BinOperator {
     pub fn get_precedence(&self) -> u32{
         self.precedence
     }
+    pub fn get_op_type(&self) -> BinOps{
+        self.kind
+    }
     pub fn get_associativity(&self) -> Associativity{
         self.associativity
     }
@@ -82,7 +135,18 @@ impl Display for BinOperator{
             Mul => "*",
             Div => "/",
             Pow => "^",
-            Mod => "%",
+            Rem => "%",
+            FloorDiv => "div",
+            PlusMinus => "±",
+            Greater => ">",
+            Less => "<",
+            Equal => "==",
+            GreaterEqual => ">=",
+            LessEqual => "<=",
+            NotEqual => "!=",
+            And => "&&",
+            Or => "||",
+            Xor => "xor",
         };
         write!(f, "{}", name)
     }
@@ -108,7 +172,9 @@ impl Operator for BinOperator{
 
 #[derive(Clone, Copy, Debug)]
 pub enum UnOps {
-    Neg, 
+    Neg,
+    /// Logical not
+    Not,
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -126,7 +192,9 @@ impl UnOperator{
         use UnOps::*;
         use Associativity::*;
         match match s{
-            "-" => Some((Neg, 10, Right)),
+            "-" => Some((Neg, 12, Right)),
+            // looser than comparisons, so `!x > 0` is `!(x > 0)`
+            "!" => Some((Not, 4, Right)),
             _ => None,
         }{
             Some((op, prec, ass)) => Ok(Self::new(op, prec, ass)),
@@ -136,7 +204,12 @@ impl UnOperator{
     pub fn compute  (&self, val: Val  ) -> Result<Val , ValComputeError> {
         use UnOps::*;
         match self.kind {
-            Neg => Ok(-val),
+            Neg => {
+                val.check_number("Operand of -")?;
+                val.check_relative("Operand of -")?;
+                Ok(-val)
+            }
+            Not => val.not(),
         }
     }
     pub fn get_precedence(&self) -> u32{
@@ -154,6 +227,7 @@ impl Display for UnOperator{
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let name = match self.kind{
             UnOps::Neg => "-",
+            UnOps::Not => "!",
         };
         write!(f, "{}", name)
     }
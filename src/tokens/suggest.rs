@@ -0,0 +1,126 @@
+//! Fuzzy matching of mistyped names for "did you mean" hints
+
+/// How far a candidate is from the typed name: whether it is not the `arc` spelling of the name,
+/// then edit distance ignoring case, then edit distance respecting case
+pub(crate) type Score = (bool, usize, usize);
+
+/// Maximum number of suggestions shown to the user
+pub(crate) const MAX_SUGGESTIONS: usize = 3;
+
+/// Optimal string alignment distance: the number of insertions, deletions,
+/// substitutions and transpositions of adjacent characters turning `a` into `b`
+fn edit_distance(a: &[char], b: &[char]) -> usize {
+    let mut d = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in d.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in d[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            d[i][j] = (d[i - 1][j] + 1).min(d[i][j - 1] + 1).min(d[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                d[i][j] = d[i][j].min(d[i - 2][j - 2] + 1);
+            }
+        }
+    }
+    d[a.len()][b.len()]
+}
+
+/// `abbr` is `word` with some letters left out, e.g. `asin` and `arcsin`
+fn is_abbreviation(abbr: &[char], word: &[char]) -> bool {
+    if abbr.len() < 3 || abbr.first() != word.first() {
+        return false;
+    }
+    let mut word = word.iter();
+    abbr.iter().all(|c| word.any(|w| w == c))
+}
+
+/// `word` is `short` with its leading `a` spelled out as `arc` or `ar`, e.g. `arcsin` and `asin`,
+/// or `arsinh` and `asinh`
+fn is_arc_spelling(short: &[char], word: &[char]) -> bool {
+    let Some(('a', rest)) = short.split_first() else { return false };
+    [&['a', 'r', 'c'][..], &['a', 'r'][..]].iter().any(|prefix| word.strip_prefix(*prefix) == Some(rest))
+}
+
+/// Scores `candidate` as a correction of `name`, or returns `None` if it is too far off
+pub(crate) fn score(name: &str, candidate: &str) -> Option<Score> {
+    if name == candidate {
+        return None;
+    }
+    let name_chars: Vec<char> = name.chars().collect();
+    let cand_chars: Vec<char> = candidate.chars().collect();
+    let name_lower: Vec<char> = name.to_lowercase().chars().collect();
+    let cand_lower: Vec<char> = candidate.to_lowercase().chars().collect();
+    let dist = edit_distance(&name_lower, &cand_lower);
+    let max_dist = match name_lower.len() {
+        0..=1 => 0,
+        2..=4 => 1,
+        _ => 2,
+    };
+    let arc = is_arc_spelling(&name_lower, &cand_lower);
+    if dist <= max_dist || arc || is_abbreviation(&name_lower, &cand_lower) {
+        Some((!arc, dist, edit_distance(&name_chars, &cand_chars)))
+    } else {
+        None
+    }
+}
+
+/// Sorts scored candidates best first and keeps the few best distinct ones.
+/// If some candidates differ from the name only in case, the rest are dropped
+pub(crate) fn best(mut scored: Vec<(Score, String)>) -> Vec<String> {
+    scored.sort();
+    let case_only = scored.iter().any(|((_, dist, _), _)| *dist == 0);
+    let mut ret: Vec<String> = Vec::new();
+    for (_, candidate) in scored.into_iter().filter(|((_, dist, _), _)| !case_only || *dist == 0) {
+        if !ret.contains(&candidate) {
+            ret.push(candidate);
+        }
+    }
+    ret.truncate(MAX_SUGGESTIONS);
+    ret
+}
+
+/// Returns the names from `candidates` that `name` was most likely meant to be, best first
+pub fn closest<'a>(name: &str, candidates: impl IntoIterator<Item = &'a str>) -> Vec<String> {
+    best(candidates
+        .into_iter()
+        .filter_map(|candidate| score(name, candidate).map(|s| (s, candidate.to_string())))
+        .collect())
+}
+
+/// Formats suggestions as a hint to append to an error message
+pub fn did_you_mean(suggestions: &[String]) -> String {
+    if suggestions.is_empty() {
+        return String::new();
+    }
+    let names: Vec<String> = suggestions.iter().map(|s| format!("`{}`", s)).collect();
+    format!("; did you mean {}?", names.join(", "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn distances() {
+        let chars = |s: &str| s.chars().collect::<Vec<_>>();
+        assert_eq!(edit_distance(&chars("kitten"), &chars("sitting")), 3);
+        assert_eq!(edit_distance(&chars("sni"), &chars("sin")), 1);
+        assert_eq!(edit_distance(&chars(""), &chars("abc")), 3);
+    }
+
+    #[test]
+    fn closest_names() {
+        let names = ["arcsin", "arccos", "sin", "kg", "km", "min", "ms"];
+        assert_eq!(closest("asin", names), vec!["arcsin", "sin"]);
+        assert_eq!(closest("acos", names), vec!["arccos"]);
+        assert_eq!(closest("asinh", ["sinh", "arsinh"]), vec!["arsinh", "sinh"]);
+        assert_eq!(closest("KG", names), vec!["kg"]);
+        assert_eq!(closest("sni", names), vec!["sin"]);
+        assert_eq!(closest("x", names), Vec::<String>::new());
+        assert_eq!(did_you_mean(&["kg".to_string(), "km".to_string()]), "; did you mean `kg`, `km`?");
+    }
+}
@@ -2,16 +2,32 @@
 use std::cell::RefCell;
 use std::sync::Arc;
 
-use super::val::ValOpts;
+use super::val::{Backend, ValOpts};
 use super::associations::{ValAlias, FnAlias};
 use super::{BinOperator, Function, UnOperator, Val};
+use super::suggest::{closest, did_you_mean};
+
+/// Optional sets of units that are not available by default
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UnitPack {
+    /// Imperial and US customary units: `inch`, `ft`, `mi`, `lb`, `gal`, `psi`, `BTU`, `hp`, ...
+    Imperial,
+}
+
+/// Names of the options of [`Builder::set_option`]
+const OPTIONS: [&str; 3] = ["cmp_epsilon", "backend", "decimal_digits"];
 
 #[derive(Clone)]
 pub struct Builder {
     pub val_opts: Arc<RefCell<ValOpts>>,
     pub val_alias: Arc<RefCell<ValAlias>>,
     pub func_alias: Arc<RefCell<FnAlias>>,
+    /// Names looked up before `val_alias` by [`Builder::get_var_val`].
+    /// Arguments of user functions no longer go here, they are slots of the compiled body
+    #[deprecated(note = "arguments of user functions are compiled into argument slots; use `val_alias`")]
     pub local_val_alias: ValAlias,
+    /// Unit packs given to [`Builder::with_packs`]
+    packs: Vec<UnitPack>,
 }
 
 impl Default for Builder {
@@ -24,12 +40,25 @@ impl Default for Builder {
 
 
 impl Builder {
+    // the state is shared through `Arc<RefCell<_>>` within a single thread
+    #[allow(deprecated, clippy::arc_with_non_send_sync)]
     pub fn new () -> Self{
-        let val_opts = Arc::new(RefCell::new(ValOpts::default()));
+        let val_opts = ValOpts::shared();
         Builder{val_opts: val_opts.clone(),
                 val_alias: Arc::new(RefCell::new(ValAlias::new(val_opts.clone()))),
                 func_alias: Arc::new(RefCell::new(FnAlias::new())),
-                local_val_alias: ValAlias::new(val_opts)}
+                local_val_alias: ValAlias::new(val_opts),
+                packs: Vec::new()}
+    }
+    /// Creates a builder with the default units and functions and the given unit packs
+    pub fn with_packs (packs: &[UnitPack]) -> Self{
+        let ret = Self{packs: packs.to_vec(), ..Self::default()};
+        for pack in packs {
+            match pack {
+                UnitPack::Imperial => {ret.val_alias.borrow_mut().insert_imperial();},
+            }
+        }
+        ret
     }
     pub fn val_from_str(&self, s: &str) -> Result<Val, String>{
          Val::from_str(s, &self.val_alias.borrow(), self.val_opts.clone())
@@ -43,17 +72,67 @@ impl Builder {
     pub fn function_from_str(&self, s: &str) -> Result<Function, String>{
          Function::from_str(s, &self.func_alias.borrow())
     }
+    #[allow(deprecated)]
     pub fn get_var_val(&self, s: &str) -> Result<Val, String>{
-        let local = self.local_val_alias.get_val(s);
-        match local{
-            Some(v) => Ok(v),
-            None => self.val_alias.borrow().get_val(s).ok_or_else(|| format!("Variable {} not found", s)),
+        if let Some(val) = self.local_val_alias.get_val(s) {
+            return Ok(val);
         }
+        let al = self.val_alias.borrow();
+        al.get_val(s).ok_or_else(|| format!("Variable {} not found{}", s, did_you_mean(&al.suggest(s))))
     }
     pub fn insert_defaults(&self){
         self.val_alias.borrow_mut().insert_default();
         self.func_alias.borrow_mut().insert_default();
     }
+    /// Removes a user variable and a user function with the given name.
+    /// Returns false if there was neither
+    pub fn undefine(&self, name: &str) -> bool{
+        let var = self.val_alias.borrow_mut().undefine(name);
+        let func = self.func_alias.borrow_mut().undefine(name);
+        var || func
+    }
+    /// Removes all user variables and functions and sets the options back to their defaults.
+    /// Unit packs stay
+    pub fn reset(&self){
+        let vars: Vec<String> = self.val_alias.borrow().vars().iter().map(|(name, _)| name.to_string()).collect();
+        let funcs: Vec<String> = self.func_alias.borrow().user_fns().iter().map(|(name, _)| name.to_string()).collect();
+        for name in vars.iter().chain(&funcs) {
+            self.undefine(name);
+        }
+        // values keep a handle to the options, so they are changed in place
+        *self.val_opts.borrow_mut() = ValOpts::default();
+    }
+    /// New builder with the default units and functions and the unit packs of this one,
+    /// that shares no state with it
+    pub fn fresh(&self) -> Self{
+        Self::with_packs(&self.packs)
+    }
+    /// Options that can be set with [`Builder::set_option`] and their current values
+    pub fn options(&self) -> Vec<(&'static str, String)>{
+        let opts = self.val_opts.borrow();
+        vec![
+            ("cmp_epsilon", opts.get_cmp_epsilon().to_string()),
+            ("backend", opts.get_backend().to_string()),
+            ("decimal_digits", opts.get_decimal_digits().to_string()),
+        ]
+    }
+    /// Sets an option by its name, as in `cmp_epsilon=1e-9` or `backend=rational`
+    pub fn set_option(&self, name: &str, value: &str) -> Result<(), String>{
+        let mut opts = self.val_opts.borrow_mut();
+        match name {
+            "cmp_epsilon" => match value.parse::<f64>() {
+                Ok(eps) if eps >= 0. => {opts.set_cmp_epsilon(eps);},
+                _ => return Err(format!("cmp_epsilon should be a non-negative number, not '{value}'")),
+            },
+            "backend" => {opts.set_backend(value.parse::<Backend>()?);},
+            "decimal_digits" => match value.parse::<u32>() {
+                Ok(digits) if digits > 0 => {opts.set_decimal_digits(digits);},
+                _ => return Err(format!("decimal_digits should be a positive integer, not '{value}'")),
+            },
+            _ => return Err(format!("No such option: '{name}'{}", did_you_mean(&closest(name, OPTIONS)))),
+        }
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -70,4 +149,27 @@ mod tests{
         let insert =  b.func_alias.borrow().get_fn("sin").unwrap();
         b.func_alias.borrow_mut().add_alias("b".to_string(), insert);
     }
+
+    #[test]
+    fn options_and_reset(){
+        let b = Builder::default();
+        b.set_option("cmp_epsilon", "1e-9").unwrap();
+        b.set_option("backend", "rational").unwrap();
+        assert_eq!(b.val_opts.borrow().get_backend(), Backend::Rational);
+        assert!(b.set_option("backend", "double").is_err());
+        assert!(b.set_option("decimal_digits", "0").is_err());
+        assert!(b.set_option("cmp_epsilom", "1").unwrap_err().contains("cmp_epsilon"));
+        assert_eq!(b.options()[0], ("cmp_epsilon", "0.000000001".to_string()));
+
+        let val = b.val_from_str("2").unwrap();
+        b.val_alias.borrow_mut().define("m".to_string(), val);
+        let sin = b.func_alias.borrow().get_fn("sin").unwrap();
+        b.func_alias.borrow_mut().define("f".to_string(), sin);
+        assert!(b.undefine("f"));
+        assert!(!b.undefine("f"));
+        b.reset();
+        assert!(b.val_alias.borrow().vars().is_empty());
+        assert_eq!(b.get_var_val("km").unwrap().get_magnetude(), 1000.);
+        assert_eq!(b.val_opts.borrow().get_backend(), Backend::Float);
+    }
 }
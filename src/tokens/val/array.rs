@@ -0,0 +1,318 @@
+//! Vectors and matrices of numbers. The unit belongs to the whole array,
+//! so the elements are plain numbers
+
+use std::fmt::Display;
+
+use num_rational::BigRational;
+use num_traits::Zero;
+
+use super::number::Number;
+use super::{ValComputeError, ValComputeErrorType};
+
+/// Vector or matrix of numbers, never empty
+#[derive(Clone, Debug)]
+pub struct Array {
+    /// Elements, row by row
+    data: Vec<Number>,
+    /// Number of rows, `None` for vectors
+    rows: Option<usize>,
+}
+
+impl Array {
+    pub fn vector(data: Vec<Number>) -> Self {
+        Array { data, rows: None }
+    }
+
+    /// Matrix from its rows, which should all have the same length
+    pub fn matrix(rows: Vec<Vec<Number>>) -> Self {
+        let count = rows.len();
+        Array { data: rows.into_iter().flatten().collect(), rows: Some(count) }
+    }
+
+    fn from_parts(data: Vec<Number>, rows: Option<usize>) -> Self {
+        Array { data, rows }
+    }
+
+    pub fn is_vector(&self) -> bool {
+        self.rows.is_none()
+    }
+
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows.unwrap_or(1)
+    }
+
+    pub fn cols(&self) -> usize {
+        self.data.len() / self.rows()
+    }
+
+    pub fn elements(&self) -> &[Number] {
+        &self.data
+    }
+
+    fn get(&self, row: usize, col: usize) -> &Number {
+        &self.data[row * self.cols() + col]
+    }
+
+    /// Shape as written in messages: `3` for vectors, `2x3` for matrices
+    pub fn shape(&self) -> String {
+        match self.rows {
+            None => self.len().to_string(),
+            Some(rows) => format!("{}x{}", rows, self.cols()),
+        }
+    }
+
+    pub fn same_shape(&self, other: &Array) -> bool {
+        self.rows == other.rows && self.len() == other.len()
+    }
+
+    pub fn map(&self, f: impl Fn(&Number) -> Number) -> Self {
+        Self::from_parts(self.data.iter().map(f).collect(), self.rows)
+    }
+
+    pub fn try_map<E>(&self, f: impl Fn(&Number) -> Result<Number, E>) -> Result<Self, E> {
+        Ok(Self::from_parts(self.data.iter().map(f).collect::<Result<_, _>>()?, self.rows))
+    }
+
+    /// Combines elements at the same places, the arrays should have the same shape
+    pub fn zip(&self, other: &Array, f: impl Fn(&Number, &Number) -> Number) -> Self {
+        Self::from_parts(self.data.iter().zip(&other.data).map(|(a, b)| f(a, b)).collect(), self.rows)
+    }
+
+    pub fn try_zip<E>(&self, other: &Array, f: impl Fn(&Number, &Number) -> Result<Number, E>) -> Result<Self, E> {
+        let data = self.data.iter().zip(&other.data).map(|(a, b)| f(a, b)).collect::<Result<_, _>>()?;
+        Ok(Self::from_parts(data, self.rows))
+    }
+
+    /// Whether all elements are equal, as told by `eq`
+    pub fn all_eq(&self, other: &Array, eq: impl Fn(&Number, &Number) -> bool) -> bool {
+        self.same_shape(other) && self.data.iter().zip(&other.data).all(|(a, b)| eq(a, b))
+    }
+
+    pub fn dot(&self, other: &Array) -> Result<Number, ValComputeError> {
+        if !self.is_vector() || !other.is_vector() || self.len() != other.len() {
+            return Err(shape_error(format!("Can not take a dot product of arrays of shapes {} and {}", self.shape(), other.shape())));
+        }
+        Ok(sum(self.data.iter().zip(&other.data).map(|(a, b)| a.mul(b))))
+    }
+
+    pub fn cross(&self, other: &Array) -> Result<Array, ValComputeError> {
+        if !self.is_vector() || !other.is_vector() || self.len() != 3 || other.len() != 3 {
+            return Err(shape_error(format!("Cross product is defined for 3-vectors, not for shapes {} and {}",
+                               self.shape(), other.shape())));
+        }
+        let (a, b) = (&self.data, &other.data);
+        let component = |i: usize, j: usize| a[i].mul(&b[j]).sub(&a[j].mul(&b[i]));
+        Ok(Array::vector(vec![component(1, 2), component(2, 0), component(0, 1)]))
+    }
+
+    /// Matrix product. A vector is a column on the right and a row on the left,
+    /// and the product of a matrix and a vector is a vector
+    pub fn matmul(&self, other: &Array) -> Result<Array, ValComputeError> {
+        let (rows, inner) = if self.is_vector() { (1, self.len()) } else { (self.rows(), self.cols()) };
+        let (other_inner, cols) = if other.is_vector() { (other.len(), 1) } else { (other.rows(), other.cols()) };
+        if inner != other_inner {
+            return Err(shape_error(format!("Can not multiply matrices of shapes {} and {}", self.shape(), other.shape())));
+        }
+        let at = |a: &Array, row: usize, col: usize, cols: usize| a.data[row * cols + col].clone();
+        let data = (0..rows)
+            .flat_map(|row| (0..cols).map(move |col| (row, col)))
+            .map(|(row, col)| sum((0..inner).map(|k| at(self, row, k, inner).mul(&at(other, k, col, cols)))))
+            .collect();
+        let rows = if self.is_vector() || other.is_vector() { None } else { Some(rows) };
+        Ok(Array::from_parts(data, rows))
+    }
+
+    /// Swaps rows and columns; a vector becomes a column
+    pub fn transpose(&self) -> Array {
+        let (rows, cols) = (self.rows(), self.cols());
+        let data = (0..cols).flat_map(|col| (0..rows).map(move |row| (row, col)))
+            .map(|(row, col)| self.get(row, col).clone())
+            .collect();
+        Array::from_parts(data, Some(cols))
+    }
+
+    fn check_square(&self, what: &str) -> Result<usize, ValComputeError> {
+        match self.rows {
+            Some(rows) if rows == self.cols() => Ok(rows),
+            _ => Err(shape_error(format!("Can not take {} of an array of shape {}, it should be a square matrix", what, self.shape()))),
+        }
+    }
+
+    /// Determinant by Gaussian elimination. Pivots of floats smaller than `epsilon`
+    /// times the largest element count as zero, see [`Array::solve`]
+    pub fn det(&self, epsilon: f64) -> Result<Number, ValComputeError> {
+        let n = self.check_square("a determinant")?;
+        let mut rows = self.row_vecs();
+        let tolerance = epsilon * self.max_abs();
+        let mut det = exact_one();
+        for col in 0..n {
+            let Some(pivot) = pivot_row(&rows, col, tolerance) else {
+                return Ok(det.mul(&exact_zero()));
+            };
+            if pivot != col {
+                rows.swap(pivot, col);
+                det = det.neg();
+            }
+            det = det.mul(&rows[col][col]);
+            eliminate(&mut rows, col, col + 1..n)?;
+        }
+        Ok(det)
+    }
+
+    pub fn inv(&self, epsilon: f64) -> Result<Array, ValComputeError> {
+        let n = self.check_square("an inverse")?;
+        let identity = (0..n)
+            .map(|row| (0..n).map(|col| if row == col { exact_one() } else { exact_zero() }).collect())
+            .collect();
+        self.solve(&Array::matrix(identity), epsilon)
+    }
+
+    /// Finds `x` for which `self` times `x` is `rhs`, by Gaussian elimination.
+    /// `rhs` is a vector, or a matrix for several systems at once.
+    /// Rounding leaves floats where exact numbers would cancel out, so the matrix is singular
+    /// if a pivot is smaller than `epsilon` times its largest element
+    pub fn solve(&self, rhs: &Array, epsilon: f64) -> Result<Array, ValComputeError> {
+        let n = self.check_square("a solution of a system")?;
+        let rhs_cols = if rhs.is_vector() { 1 } else { rhs.cols() };
+        if rhs.len() / rhs_cols != n {
+            return Err(shape_error(format!("Can not solve a system of shape {} with the right side of shape {}",
+                               self.shape(), rhs.shape())));
+        }
+        let mut rows: Vec<Vec<Number>> = self.row_vecs().into_iter().enumerate()
+            .map(|(i, mut row)| {
+                row.extend_from_slice(&rhs.data[i * rhs_cols..(i + 1) * rhs_cols]);
+                row
+            })
+            .collect();
+        let tolerance = epsilon * self.max_abs();
+        for col in 0..n {
+            let pivot = pivot_row(&rows, col, tolerance).ok_or_else(|| ValComputeError::new(
+                    "Matrix is singular".to_string(), ValComputeErrorType::Domain))?;
+            rows.swap(pivot, col);
+            let pivot = rows[col][col].clone();
+            for x in rows[col].iter_mut() {
+                *x = x.div(&pivot)?;
+            }
+            eliminate(&mut rows, col, (0..n).filter(|row| *row != col))?;
+        }
+        let data = rows.into_iter().flat_map(|row| row.into_iter().skip(n)).collect();
+        Ok(Array::from_parts(data, rhs.rows))
+    }
+
+    /// Largest absolute value of the elements
+    fn max_abs(&self) -> f64 {
+        self.data.iter().map(|x| x.abs().to_f64()).fold(0., f64::max)
+    }
+
+    fn row_vecs(&self) -> Vec<Vec<Number>> {
+        self.data.chunks(self.cols()).map(<[Number]>::to_vec).collect()
+    }
+}
+
+fn shape_error(desc: String) -> ValComputeError {
+    ValComputeError::new(desc, ValComputeErrorType::Shape)
+}
+
+fn exact_zero() -> Number {
+    Number::rational(BigRational::zero())
+}
+
+fn exact_one() -> Number {
+    Number::rational(BigRational::from_integer(1.into()))
+}
+
+fn sum(numbers: impl Iterator<Item = Number>) -> Number {
+    numbers.fold(exact_zero(), |acc, x| acc.add(&x))
+}
+
+/// Row at or below `col` with the largest element in that column, `None` if they are all zero.
+/// Inexact elements count as zero if they are within `tolerance` of it, exact ones only if they are zero
+fn pivot_row(rows: &[Vec<Number>], col: usize, tolerance: f64) -> Option<usize> {
+    (col..rows.len())
+        .filter(|row| !rows[*row][col].approx_eq(&exact_zero(), tolerance))
+        .max_by(|a, b| rows[*a][col].abs().to_f64().total_cmp(&rows[*b][col].abs().to_f64()))
+}
+
+/// Subtracts multiples of the pivot row `col` from `targets`, zeroing their elements in that column
+fn eliminate(rows: &mut [Vec<Number>], col: usize, targets: impl Iterator<Item = usize>) -> Result<(), ValComputeError> {
+    for row in targets {
+        let factor = rows[row][col].div(&rows[col][col])?;
+        if factor.is_zero() {
+            continue;
+        }
+        for k in col..rows[row].len() {
+            rows[row][k] = rows[row][k].sub(&factor.mul(&rows[col][k]));
+        }
+    }
+    Ok(())
+}
+
+impl Array {
+    /// Writes the array as `[a, b]` or `[[a, b], [c, d]]` with elements written by `fmt`
+    pub fn fmt_with(&self, fmt: impl Fn(&Number) -> String) -> String {
+        let fmt_row = |row: &[Number]| format!("[{}]", row.iter().map(&fmt).collect::<Vec<_>>().join(", "));
+        match self.rows {
+            None => fmt_row(&self.data),
+            Some(_) => format!("[{}]", self.data.chunks(self.cols()).map(fmt_row).collect::<Vec<_>>().join(", ")),
+        }
+    }
+}
+
+impl Display for Array {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.fmt_with(Number::to_string))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vector(xs: &[i64]) -> Array {
+        Array::vector(xs.iter().map(|x| Number::rational(BigRational::from_integer((*x).into()))).collect())
+    }
+
+    fn matrix(rows: &[&[i64]]) -> Array {
+        Array::matrix(rows.iter().map(|row| vector(row).data).collect())
+    }
+
+    const EPS: f64 = 1e-6;
+
+    #[test]
+    fn linear_algebra() {
+        let a = matrix(&[&[2, 1], &[1, 3]]);
+        assert_eq!(a.to_string(), "[[2, 1], [1, 3]]");
+        assert_eq!(a.transpose().to_string(), "[[2, 1], [1, 3]]");
+        assert_eq!(matrix(&[&[1, 2, 3]]).transpose().shape(), "3x1");
+        assert_eq!(a.det(EPS).unwrap().to_string(), "5");
+        assert_eq!(a.inv(EPS).unwrap().to_string(), "[[0.6, -0.2], [-0.2, 0.4]]");
+        assert_eq!(a.solve(&vector(&[3, 5]), EPS).unwrap().to_string(), "[0.8, 1.4]");
+        assert_eq!(a.matmul(&vector(&[1, 1])).unwrap().to_string(), "[3, 4]");
+        assert_eq!(vector(&[1, 0, 0]).cross(&vector(&[0, 1, 0])).unwrap().to_string(), "[0, 0, 1]");
+        assert_eq!(vector(&[1, 2, 3]).dot(&vector(&[4, 5, 6])).unwrap().to_string(), "32");
+        assert!(matrix(&[&[1, 2], &[2, 4]]).inv(EPS).is_err());
+        assert_eq!(matrix(&[&[1, 2], &[2, 4]]).det(EPS).unwrap().to_string(), "0");
+        assert!(vector(&[1, 2]).dot(&vector(&[1, 2, 3])).is_err());
+        assert_eq!(matrix(&[&[0, 1, 2], &[1, 0, 3], &[4, -3, 8]]).det(EPS).unwrap().to_string(), "-2");
+    }
+
+    #[test]
+    fn rounded_pivots() {
+        let floats = |rows: &[&[f64]]| Array::matrix(rows.iter().map(|row| row.iter().map(|x| Number::Float(*x)).collect()).collect());
+        // singular, but rounding leaves a pivot of about 1e-16
+        let a = floats(&[&[0.1, 0.2, 0.3], &[0.4, 0.5, 0.6], &[0.7, 0.8, 0.9]]);
+        assert!(a.inv(EPS).is_err());
+        assert_eq!(a.det(EPS).unwrap().to_f64(), 0.);
+        // small, but not singular
+        let b = floats(&[&[1e-9, 0.], &[0., 1e-9]]);
+        assert!((b.inv(EPS).unwrap().elements()[0].to_f64() - 1e9).abs() < 1e-3);
+    }
+}
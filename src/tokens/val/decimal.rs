@@ -0,0 +1,347 @@
+//! Functions of decimals computed to any number of digits.
+//! Series are summed in fixed point: integers that count units of `10^-places`,
+//! with a few more places than asked for, so that the rounding errors of the terms
+//! do not reach the digits that are kept
+
+use num_bigint::BigInt;
+use num_integer::Integer;
+use num_rational::BigRational;
+use num_traits::{One, Signed, ToPrimitive, Zero};
+
+/// Places computed beyond the asked for digits
+const GUARD: u32 = 10;
+/// Largest power of two results of `exp` are scaled by, larger ones are left to floats
+const MAX_SHIFT: i32 = 1 << 20;
+
+/// Numbers with `places` places after the point
+struct Fixed {
+    one: BigInt,
+}
+
+impl Fixed {
+    fn new(places: u32) -> Self {
+        Fixed { one: BigInt::from(10).pow(places) }
+    }
+
+    fn from(&self, x: &BigRational) -> BigInt {
+        (x * BigRational::from_integer(self.one.clone())).round().to_integer()
+    }
+
+    fn to_rational(&self, x: BigInt) -> BigRational {
+        BigRational::new(x, self.one.clone())
+    }
+
+    fn mul(&self, a: &BigInt, b: &BigInt) -> BigInt {
+        a * b / &self.one
+    }
+
+    fn div(&self, a: &BigInt, b: &BigInt) -> BigInt {
+        a * &self.one / b
+    }
+
+    fn sqrt(&self, a: &BigInt) -> BigInt {
+        (a * &self.one).sqrt()
+    }
+
+    /// `atan(1/n)`, for the formula of pi
+    fn atan_inv(&self, n: u32) -> BigInt {
+        let n2 = BigInt::from(n) * n;
+        let mut power = &self.one / n;
+        let mut sum = power.clone();
+        let mut k = 1u32;
+        while !power.is_zero() {
+            power /= &n2;
+            let term = &power / (2 * k + 1);
+            if k.is_odd() { sum -= term } else { sum += term }
+            k += 1;
+        }
+        sum
+    }
+
+    /// Machin's formula, `pi = 16 atan(1/5) - 4 atan(1/239)`
+    fn pi(&self) -> BigInt {
+        self.atan_inv(5) * 16u32 - self.atan_inv(239) * 4u32
+    }
+
+    /// `atan(x)` for `|x| <= 1`. The argument is halved a few times first,
+    /// as `atan(x) = 2 atan(x / (1 + sqrt(1 + x^2)))`, for the series to converge fast
+    fn atan(&self, x: &BigInt) -> BigInt {
+        let mut x = x.clone();
+        let mut halvings = 0;
+        while x.abs() * 10 > self.one {
+            let root = self.sqrt(&(&self.one + self.mul(&x, &x)));
+            x = self.div(&x, &(&self.one + root));
+            halvings += 1;
+        }
+        let x2 = self.mul(&x, &x);
+        let mut power = x.clone();
+        let mut sum = x;
+        let mut k = 1u32;
+        while !power.is_zero() {
+            power = -self.mul(&power, &x2);
+            sum += &power / (2 * k + 1);
+            k += 1;
+        }
+        sum << halvings
+    }
+
+    /// `atanh(x) = (x + x^3/3 + x^5/5 + ...)` for `|x| <= 1/3`
+    fn atanh(&self, x: &BigInt) -> BigInt {
+        let x2 = self.mul(x, x);
+        let mut power = x.clone();
+        let mut sum = x.clone();
+        let mut k = 1u32;
+        while !power.is_zero() {
+            power = self.mul(&power, &x2);
+            sum += &power / (2 * k + 1);
+            k += 1;
+        }
+        sum
+    }
+
+    fn ln2(&self) -> BigInt {
+        self.atanh(&(&self.one / 3u32)) * 2
+    }
+
+    /// `exp(x)` for `|x| <= 1`
+    fn exp(&self, x: &BigInt) -> BigInt {
+        let mut term = self.one.clone();
+        let mut sum = self.one.clone();
+        let mut k = 1u32;
+        while !term.is_zero() {
+            term = self.mul(&term, x) / k;
+            sum += &term;
+            k += 1;
+        }
+        sum
+    }
+
+    /// `(sin(x), cos(x))` for `|x| <= pi/4`
+    fn sin_cos(&self, x: &BigInt) -> (BigInt, BigInt) {
+        let x2 = self.mul(x, x);
+        let (mut sin_term, mut cos_term) = (x.clone(), self.one.clone());
+        let (mut sin, mut cos) = (sin_term.clone(), cos_term.clone());
+        let mut k = 1u32;
+        while !sin_term.is_zero() || !cos_term.is_zero() {
+            cos_term = -self.mul(&cos_term, &x2) / ((2 * k - 1) * 2 * k);
+            sin_term = -self.mul(&sin_term, &x2) / (2 * k * (2 * k + 1));
+            sin += &sin_term;
+            cos += &cos_term;
+            k += 1;
+        }
+        (sin, cos)
+    }
+}
+
+/// Power of ten `|x|` is about, so that small results get places for their digits
+fn exponent(x: &BigRational) -> i64 {
+    if x.is_zero() {
+        return 0;
+    }
+    ((x.numer().bits() as f64 - x.denom().bits() as f64) * std::f64::consts::LOG10_2).floor() as i64
+}
+
+/// Places for a result of `digits` significant digits that is about as large as `x`
+fn places(digits: u32, x: &BigRational) -> u32 {
+    digits + GUARD + (-exponent(x)).max(0) as u32
+}
+
+/// `2^k`, for any sign of `k`
+fn pow2(k: i64) -> BigRational {
+    BigRational::from_integer(BigInt::from(2)).pow(k as i32)
+}
+
+fn half_pi(digits: u32) -> BigRational {
+    pi(digits) / BigInt::from(2)
+}
+
+pub fn pi(digits: u32) -> BigRational {
+    let fixed = Fixed::new(digits + GUARD);
+    fixed.to_rational(fixed.pi())
+}
+
+/// Natural logarithm of a positive `x`, as `k ln(2) + ln(y)` with `y = x / 2^k` near 1,
+/// and `ln(y)` written as `2 atanh((y - 1) / (y + 1))`
+pub fn ln(x: &BigRational, digits: u32) -> Option<BigRational> {
+    if !x.is_positive() {
+        return None;
+    }
+    let mut k = x.numer().bits() as i64 - x.denom().bits() as i64;
+    let mut y = x / pow2(k);
+    // between 2/3 and 4/3, so that k is 0 for every x near 1 and its digits are not cancelled
+    let third = BigRational::new(1.into(), 3.into());
+    while y > BigRational::one() + &third {
+        y /= BigInt::from(2);
+        k += 1;
+    }
+    while y < BigRational::one() - &third {
+        y *= BigInt::from(2);
+        k -= 1;
+    }
+    let z = (&y - BigRational::one()) / (&y + BigRational::one());
+    // `k ln(2)` brings the error of ln(2) up by the digits of k
+    let fixed = Fixed::new(places(digits, &z) + k.unsigned_abs().to_string().len() as u32);
+    let ret = fixed.ln2() * k + fixed.atanh(&fixed.from(&z)) * 2u32;
+    Some(fixed.to_rational(ret))
+}
+
+/// `exp(x)` as `2^n exp(x - n ln(2))`, `None` if `2^n` is beyond `MAX_SHIFT`
+pub fn exp(x: &BigRational, digits: u32) -> Option<BigRational> {
+    let fixed = Fixed::new(GUARD);
+    let n = (fixed.from(x) / fixed.ln2()).to_i32().filter(|n| n.abs() <= MAX_SHIFT)?;
+    // the error of `n ln(2)` grows with the digits of n
+    let fixed = Fixed::new(digits + GUARD + n.unsigned_abs().to_string().len() as u32);
+    let r = fixed.from(x) - fixed.ln2() * n;
+    Some(fixed.to_rational(fixed.exp(&r)) * pow2(n as i64))
+}
+
+/// `x^p` for a positive `x`, as `exp(p ln(x))`. The error of `p ln(x)` becomes a relative one,
+/// so the logarithm gets as many more digits as the exponent has before the point
+pub fn pow(x: &BigRational, p: &BigRational, digits: u32) -> Option<BigRational> {
+    let int_digits = exponent(&(p * ln(x, GUARD)?)).max(0) as u32;
+    exp(&(p * ln(x, digits + GUARD + int_digits)?), digits)
+}
+
+/// `x` less the multiple of pi/2 nearest to it, and which multiple it was modulo 4
+fn reduce(x: &BigRational, fixed: &Fixed) -> (BigInt, u32) {
+    let half_pi: BigInt = fixed.pi() / 2u32;
+    let x = fixed.from(x);
+    let n = (&x * 2u32 + &half_pi).div_floor(&(&half_pi * 2u32));
+    let r = x - &n * &half_pi;
+    let quadrant = n.mod_floor(&BigInt::from(4)).to_u32().unwrap();
+    (r, quadrant)
+}
+
+/// Sine and cosine, the multiple of pi/2 is taken from `x` with as many more places
+/// as `x` has digits before the point. Results near zero lose their digits to it,
+/// so they are computed again with as many more places as they have zeros
+fn sin_cos(x: &BigRational, digits: u32) -> (BigRational, BigRational) {
+    let mut extra = 0;
+    loop {
+        let fixed = Fixed::new(places(digits, x) + exponent(x).max(0) as u32 + extra);
+        let (r, quadrant) = reduce(x, &fixed);
+        let (sin, cos) = fixed.sin_cos(&r);
+        let (sin, cos) = match quadrant {
+            0 => (sin, cos),
+            1 => (cos, -sin),
+            2 => (-sin, -cos),
+            _ => (-cos, sin),
+        };
+        let (sin, cos) = (fixed.to_rational(sin), fixed.to_rational(cos));
+        let zeros = (-exponent(&sin).min(exponent(&cos))).max(0) as u32;
+        if zeros <= extra {
+            return (sin, cos);
+        }
+        extra = zeros;
+    }
+}
+
+pub fn sin(x: &BigRational, digits: u32) -> Option<BigRational> {
+    Some(sin_cos(x, digits).0)
+}
+
+pub fn cos(x: &BigRational, digits: u32) -> Option<BigRational> {
+    Some(sin_cos(x, digits).1)
+}
+
+/// `None` at the poles, where the value is not a number
+pub fn tan(x: &BigRational, digits: u32) -> Option<BigRational> {
+    let (sin, cos) = sin_cos(x, digits + GUARD);
+    (!cos.is_zero()).then(|| sin / cos)
+}
+
+pub fn cot(x: &BigRational, digits: u32) -> Option<BigRational> {
+    let (sin, cos) = sin_cos(x, digits + GUARD);
+    (!sin.is_zero()).then(|| cos / sin)
+}
+
+/// Arguments larger than 1 are turned around, as `atan(x) = pi/2 - atan(1/x)`
+pub fn atan(x: &BigRational, digits: u32) -> Option<BigRational> {
+    if x.abs() > BigRational::one() {
+        let ret = half_pi(digits) - atan(&x.recip(), digits)?;
+        return Some(if x.is_negative() { ret - pi(digits) } else { ret });
+    }
+    let fixed = Fixed::new(places(digits, x));
+    Some(fixed.to_rational(fixed.atan(&fixed.from(x))))
+}
+
+/// `pi/2 - atan(x)`, taken as `atan(1/x)` for positive `x` so that large ones keep their digits
+pub fn acot(x: &BigRational, digits: u32) -> Option<BigRational> {
+    match x.is_zero() {
+        true => Some(half_pi(digits)),
+        false if x.is_positive() => atan(&x.recip(), digits),
+        false => Some(pi(digits) + atan(&x.recip(), digits)?),
+    }
+}
+
+/// `None` outside of [-1, 1], where the result is complex
+pub fn asin(x: &BigRational, digits: u32) -> Option<BigRational> {
+    let one = BigRational::one();
+    match x.abs().cmp(&one) {
+        std::cmp::Ordering::Greater => None,
+        std::cmp::Ordering::Equal => Some(half_pi(digits) * x),
+        std::cmp::Ordering::Less => {
+            let fixed = Fixed::new(places(digits, x));
+            let root = fixed.sqrt(&fixed.from(&(one - x * x)));
+            atan(&(x / fixed.to_rational(root)), digits)
+        }
+    }
+}
+
+/// `2 atan(sqrt((1 - x) / (1 + x)))`, which is exact near 1 unlike `pi/2 - asin(x)`
+pub fn acos(x: &BigRational, digits: u32) -> Option<BigRational> {
+    let one = BigRational::one();
+    if x.abs() > one {
+        return None;
+    }
+    if *x == -&one {
+        return Some(pi(digits));
+    }
+    let ratio = (&one - x) / (&one + x);
+    let fixed = Fixed::new(places(digits, &ratio) + GUARD);
+    let root = fixed.to_rational(fixed.sqrt(&fixed.from(&ratio)));
+    Some(atan(&root, digits + GUARD)? * BigInt::from(2))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tokens::val::number::{fmt_exact, parse_literal};
+
+    /// `x` to `digits` places after the point
+    fn places_of(x: &BigRational, digits: u32) -> String {
+        let fixed = Fixed::new(digits);
+        fmt_exact(&fixed.to_rational(fixed.from(x)))
+    }
+
+    fn exact(s: &str) -> BigRational {
+        parse_literal(s).unwrap()
+    }
+
+    #[test]
+    fn constants() {
+        assert_eq!(places_of(&pi(60), 50), "3.14159265358979323846264338327950288419716939937511");
+        assert_eq!(places_of(&ln(&exact("2"), 40).unwrap(), 40), "0.6931471805599453094172321214581765680755");
+        assert_eq!(places_of(&exp(&exact("1"), 40).unwrap(), 40), "2.7182818284590452353602874713526624977572");
+    }
+
+    #[test]
+    fn functions() {
+        assert_eq!(places_of(&sin(&exact("1"), 40).unwrap(), 40), "0.8414709848078965066525023216302989996226");
+        assert_eq!(places_of(&cos(&exact("100"), 40).unwrap(), 40), "0.8623188722876839341019385139508425355101");
+        assert_eq!(places_of(&atan(&exact("-3"), 40).unwrap(), 40), "-1.2490457723982544258299170772810901230778");
+        assert_eq!(places_of(&acos(&exact("0.5"), 40).unwrap(), 40), places_of(&(pi(50) / BigInt::from(3)), 40));
+        assert_eq!(places_of(&asin(&exact("-1"), 40).unwrap(), 40), places_of(&-half_pi(50), 40));
+        assert_eq!(places_of(&acot(&exact("-1"), 40).unwrap(), 40), places_of(&(pi(50) * BigRational::new(3.into(), 4.into())), 40));
+        assert_eq!(places_of(&pow(&exact("2"), &pi(50), 45).unwrap(), 45), "8.824977827076287623856429604208001581704410815");
+        // small results keep their significant digits
+        assert_eq!(places_of(&sin(&exact("1e-20"), 45).unwrap(), 65), "0.00000000000000000000999999999999999999999999999999999999999983333");
+        assert_eq!(places_of(&ln(&exact("1e-100"), 35).unwrap(), 30), "-230.258509299404568401799145468436");
+        assert_eq!(places_of(&exp(&exact("-100"), 40).unwrap(), 80),
+                   "0.00000000000000000000000000000000000000000003720075976020835962959695803863118337");
+        assert!(ln(&exact("0"), 30).is_none());
+        assert!(asin(&exact("1.0000001"), 30).is_none());
+        assert!(cot(&exact("0"), 30).is_none());
+        assert!(exp(&exact("1e10"), 30).is_none());
+    }
+}
@@ -13,6 +13,12 @@ impl ValComputeError{
     pub fn new(desc: String, err_type: ErrorType) -> Self{
         ValComputeError{desc, err_type}
     }
+    pub fn get_desc(&self) -> &str{
+        &self.desc
+    }
+    pub fn get_type(&self) -> ErrorType{
+        self.err_type
+    }
 }
 
 impl Display for ValComputeError{
@@ -22,10 +28,16 @@ impl Display for ValComputeError{
 }
 impl Error for ValComputeError{}
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ErrorType{
     DivisionByZero,
     IncompatibleUnits,
+    /// Argument is outside of the function's domain
+    Domain,
+    /// Arrays of shapes that do not fit the operation, e. g. adding a 2-vector to a 3-vector
+    Shape,
+    /// Boolean where a number is expected, or the other way around
+    Type,
     Other,
 }
 impl Display for ErrorType{
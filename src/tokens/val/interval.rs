@@ -0,0 +1,434 @@
+//! Intervals that are guaranteed to contain the exact result.
+//! Every operation rounds the bounds outwards, so the rounding errors of floats
+//! can only make an interval wider
+
+use std::f64::consts::{FRAC_PI_2, PI};
+use std::fmt::Display;
+
+use num_rational::BigRational;
+use num_traits::ToPrimitive;
+
+/// Number of ulps the results of functions from the standard library are widened by,
+/// as they are not always correctly rounded
+const LIBM_ULPS: u32 = 4;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Interval {
+    lo: f64,
+    hi: f64,
+}
+
+impl Interval {
+    /// Interval between the bounds, `None` if `lo` is greater than `hi`
+    pub fn new(lo: f64, hi: f64) -> Option<Self> {
+        (lo <= hi).then_some(Interval { lo, hi })
+    }
+
+    fn raw(lo: f64, hi: f64) -> Self {
+        Interval { lo, hi }
+    }
+
+    /// Interval that contains only `x`
+    pub fn point(x: f64) -> Self {
+        Interval { lo: x, hi: x }
+    }
+
+    pub fn entire() -> Self {
+        Interval { lo: f64::NEG_INFINITY, hi: f64::INFINITY }
+    }
+
+    /// Interval without any values, the result of a function outside of its real domain
+    pub fn empty() -> Self {
+        Interval { lo: f64::NAN, hi: f64::NAN }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lo.is_nan() || self.hi.is_nan()
+    }
+
+    /// Tightest interval of floats containing an exact number
+    pub fn enclose(r: &BigRational) -> Self {
+        let x = r.to_f64().unwrap_or(f64::NAN);
+        let (mut lo, mut hi) = (x, x);
+        while lo.is_finite() && BigRational::from_float(lo).is_some_and(|lo| &lo > r) {
+            lo = lo.next_down();
+        }
+        while hi.is_finite() && BigRational::from_float(hi).is_some_and(|hi| &hi < r) {
+            hi = hi.next_up();
+        }
+        // numbers too large for a float are still above or below all the finite ones
+        Interval { lo: lo.min(f64::MAX), hi: hi.max(f64::MIN) }
+    }
+
+    /// Interval around a float that may already be rounded, such as a constant
+    pub fn around(x: f64) -> Self {
+        Interval::point(x).widen(1)
+    }
+
+    pub fn lo(&self) -> f64 {
+        self.lo
+    }
+
+    pub fn hi(&self) -> f64 {
+        self.hi
+    }
+
+    pub fn mid(&self) -> f64 {
+        if self.lo.is_infinite() || self.hi.is_infinite() {
+            return if self.lo == -self.hi { 0. } else { self.lo + self.hi };
+        }
+        self.lo / 2. + self.hi / 2.
+    }
+
+    pub fn contains(&self, x: f64) -> bool {
+        self.lo <= x && x <= self.hi
+    }
+
+    /// Smallest interval containing both
+    pub fn hull(&self, other: &Interval) -> Self {
+        Interval { lo: self.lo.min(other.lo), hi: self.hi.max(other.hi) }
+    }
+
+    /// Moves the bounds outwards by `ulps` units in the last place
+    fn widen(self, ulps: u32) -> Self {
+        let (mut lo, mut hi) = (self.lo, self.hi);
+        for _ in 0..ulps {
+            lo = lo.next_down();
+            hi = hi.next_up();
+        }
+        Interval { lo, hi }
+    }
+
+    /// Interval of the values of an exactly computed function monotonic on `self`, like `floor`
+    pub fn map_exact(&self, f: impl Fn(f64) -> f64) -> Self {
+        let (a, b) = (f(self.lo), f(self.hi));
+        Interval { lo: a.min(b), hi: a.max(b) }
+    }
+
+    /// Interval of the values of a function that is monotonic on `self`
+    pub fn monotonic(&self, f: impl Fn(f64) -> f64) -> Self {
+        self.map_exact(f).widen(LIBM_ULPS)
+    }
+
+    /// Interval of the values of `f` at all combinations of the bounds.
+    /// `f` rounds down if its last argument is false and up if it is true
+    fn corners(&self, rhs: &Interval, f: impl Fn(f64, f64, bool) -> f64) -> Self {
+        let pairs = [(self.lo, rhs.lo), (self.lo, rhs.hi), (self.hi, rhs.lo), (self.hi, rhs.hi)];
+        let lo = pairs.iter().map(|(a, b)| f(*a, *b, false)).fold(f64::INFINITY, f64::min);
+        let hi = pairs.iter().map(|(a, b)| f(*a, *b, true)).fold(f64::NEG_INFINITY, f64::max);
+        if lo.is_nan() || hi.is_nan() {
+            return Interval::entire();
+        }
+        Interval { lo, hi }
+    }
+
+    pub fn add(&self, rhs: &Interval) -> Self {
+        Interval::raw(add_rounded(self.lo, rhs.lo, false), add_rounded(self.hi, rhs.hi, true))
+    }
+
+    pub fn sub(&self, rhs: &Interval) -> Self {
+        Interval::raw(add_rounded(self.lo, -rhs.hi, false), add_rounded(self.hi, -rhs.lo, true))
+    }
+
+    pub fn mul(&self, rhs: &Interval) -> Self {
+        // 0 * inf is taken as 0, as the bound is only approached
+        self.corners(rhs, |a, b, up| if a == 0. || b == 0. { 0. } else { mul_rounded(a, b, up) })
+    }
+
+    /// Division by an interval containing zero gives all of the real numbers
+    pub fn div(&self, rhs: &Interval) -> Self {
+        if rhs.contains(0.) {
+            return Interval::entire();
+        }
+        self.corners(rhs, div_rounded)
+    }
+
+    pub fn neg(&self) -> Self {
+        Interval::raw(-self.hi, -self.lo)
+    }
+
+    pub fn abs(&self) -> Self {
+        match self.contains(0.) {
+            true => Interval::raw(0., self.hi.max(-self.lo)),
+            false => self.map_exact(f64::abs),
+        }
+    }
+
+    /// Fractional part with the sign of the number
+    pub fn fract(&self) -> Self {
+        if self.lo.trunc() == self.hi.trunc() && self.lo.is_finite() && self.hi.is_finite() {
+            return self.map_exact(f64::fract);
+        }
+        Interval::raw(if self.lo < 0. { -1. } else { 0. }, if self.hi > 0. { 1. } else { 0. })
+    }
+
+    /// Remainder of division rounded towards zero, has the sign of `self`
+    pub fn rem(&self, rhs: &Interval) -> Self {
+        let q = self.div(rhs);
+        if q.lo.trunc() == q.hi.trunc() && q.lo.is_finite() && !rhs.contains(0.) && rhs.lo == rhs.hi {
+            return self.sub(&rhs.mul(&Interval::point(q.lo.trunc())));
+        }
+        let max = rhs.lo.abs().max(rhs.hi.abs());
+        Interval::raw(if self.lo < 0. { -max } else { 0. }, if self.hi > 0. { max } else { 0. })
+    }
+
+    /// Remainder of division rounded down, has the sign of `rhs`
+    pub fn modulo(&self, rhs: &Interval) -> Self {
+        let q = self.div(rhs);
+        if q.lo.floor() == q.hi.floor() && q.lo.is_finite() && !rhs.contains(0.) && rhs.lo == rhs.hi {
+            return self.sub(&rhs.mul(&Interval::point(q.lo.floor())));
+        }
+        Interval::raw(rhs.lo.min(0.), rhs.hi.max(0.))
+    }
+
+    pub fn pow(&self, p: &Interval) -> Self {
+        if p.lo == p.hi && p.lo.fract() == 0. && p.lo.abs() <= i32::MAX as f64 {
+            return self.powi(p.lo as i32);
+        }
+        // non-integer powers are only defined for non-negative numbers
+        let Some(base) = Interval::new(self.lo.max(0.), self.hi) else {
+            return Interval::empty();
+        };
+        base.corners(p, |a, b, _| a.powf(b)).widen(LIBM_ULPS)
+    }
+
+    fn powi(&self, n: i32) -> Self {
+        if n < 0 {
+            return Interval::point(1.).div(&self.powi_unsigned(n.unsigned_abs()));
+        }
+        self.powi_unsigned(n as u32)
+    }
+
+    fn powi_unsigned(&self, n: u32) -> Self {
+        if n.is_multiple_of(2) {
+            return self.abs().powi_non_negative(n);
+        }
+        // odd powers keep the sign and are increasing
+        let power = |x: f64| match x < 0. {
+            true => Interval::point(-x).powi_non_negative(n).neg(),
+            false => Interval::point(x).powi_non_negative(n),
+        };
+        Interval::raw(power(self.lo).lo, power(self.hi).hi)
+    }
+
+    /// Power of an interval of non-negative numbers by squaring, which is tight for them
+    fn powi_non_negative(&self, mut n: u32) -> Self {
+        let (mut ret, mut base) = (Interval::point(1.), *self);
+        while n > 0 {
+            if n & 1 == 1 {
+                ret = ret.mul(&base);
+            }
+            base = base.mul(&base);
+            n >>= 1;
+        }
+        ret
+    }
+
+    /// Clamps both bounds to `[min, max]`, for results that can not be outside of it
+    fn clamp(self, min: f64, max: f64) -> Self {
+        Interval { lo: self.lo.clamp(min, max), hi: self.hi.clamp(min, max) }
+    }
+
+    /// Whether `self` may contain a point `offset + k * period` for an integer `k`.
+    /// Errs on the side of containing it, as the points themselves are rounded
+    fn may_contain_periodic(&self, offset: f64, period: f64) -> bool {
+        let slack = 1e-9;
+        let k = ((self.lo - offset) / period - slack).ceil();
+        offset + k * period <= self.hi + slack * self.hi.abs().max(1.)
+    }
+
+    fn is_wide(&self) -> bool {
+        !self.lo.is_finite() || !self.hi.is_finite() || self.hi - self.lo >= 2. * PI
+    }
+
+    pub fn sin(&self) -> Self {
+        if self.is_wide() {
+            return Interval::raw(-1., 1.);
+        }
+        let mut ret = self.map_exact(f64::sin).widen(LIBM_ULPS);
+        if self.may_contain_periodic(FRAC_PI_2, 2. * PI) {
+            ret.hi = 1.;
+        }
+        if self.may_contain_periodic(-FRAC_PI_2, 2. * PI) {
+            ret.lo = -1.;
+        }
+        ret.clamp(-1., 1.)
+    }
+
+    pub fn cos(&self) -> Self {
+        if self.is_wide() {
+            return Interval::raw(-1., 1.);
+        }
+        let mut ret = self.map_exact(f64::cos).widen(LIBM_ULPS);
+        if self.may_contain_periodic(0., 2. * PI) {
+            ret.hi = 1.;
+        }
+        if self.may_contain_periodic(PI, 2. * PI) {
+            ret.lo = -1.;
+        }
+        ret.clamp(-1., 1.)
+    }
+
+    /// Intervals around a pole give all of the real numbers
+    pub fn tan(&self) -> Self {
+        if self.is_wide() || self.may_contain_periodic(FRAC_PI_2, PI) {
+            return Interval::entire();
+        }
+        self.monotonic(f64::tan)
+    }
+
+    pub fn cot(&self) -> Self {
+        if self.is_wide() || self.may_contain_periodic(0., PI) {
+            return Interval::entire();
+        }
+        self.monotonic(|x| x.tan().recip())
+    }
+
+    /// Parts of `self` outside of [-1, 1] are left out, the result is empty if nothing is left
+    pub fn asin(&self) -> Self {
+        match Interval::new(self.lo.max(-1.), self.hi.min(1.)) {
+            Some(x) => x.monotonic(f64::asin).clamp(-FRAC_PI_2.next_up(), FRAC_PI_2.next_up()),
+            None => Interval::empty(),
+        }
+    }
+
+    pub fn acos(&self) -> Self {
+        match Interval::new(self.lo.max(-1.), self.hi.min(1.)) {
+            Some(x) => x.monotonic(f64::acos).clamp(0., PI.next_up()),
+            None => Interval::empty(),
+        }
+    }
+
+    pub fn atan(&self) -> Self {
+        self.monotonic(f64::atan)
+    }
+
+    pub fn acot(&self) -> Self {
+        Interval::around(FRAC_PI_2).sub(&self.atan())
+    }
+
+    /// Parts of `self` below zero are left out, the result is empty if nothing is left
+    pub fn ln(&self) -> Self {
+        match Interval::new(self.lo.max(0.), self.hi) {
+            Some(x) => x.monotonic(f64::ln),
+            None => Interval::empty(),
+        }
+    }
+
+    /// Angle in the complex plane: 0 for positive numbers and pi for negative ones
+    pub fn arg(&self) -> Self {
+        match (self.lo >= 0., self.hi < 0.) {
+            (true, _) => Interval::point(0.),
+            (_, true) => Interval::around(PI),
+            _ => Interval::raw(0., PI.next_up()),
+        }
+    }
+}
+
+/// Replaces a sum or a product that overflowed with the largest float when rounding towards zero
+fn overflowed(x: f64, up: bool) -> f64 {
+    match (x > 0., up) {
+        (true, false) => f64::MAX,
+        (false, true) => f64::MIN,
+        _ => x,
+    }
+}
+
+/// `a + b` rounded down or up, using the exact error of the sum
+fn add_rounded(a: f64, b: f64, up: bool) -> f64 {
+    let sum = a + b;
+    if sum.is_infinite() && a.is_finite() && b.is_finite() {
+        return overflowed(sum, up);
+    }
+    if !sum.is_finite() {
+        return sum;
+    }
+    let b_part = sum - a;
+    let err = (a - (sum - b_part)) + (b - b_part);
+    round_towards(sum, err, up)
+}
+
+/// `a * b` rounded down or up, using the exact error of the product given by a fused multiply-add
+fn mul_rounded(a: f64, b: f64, up: bool) -> f64 {
+    let product = a * b;
+    if product.is_infinite() && a.is_finite() && b.is_finite() {
+        return overflowed(product, up);
+    }
+    if !product.is_finite() {
+        return product;
+    }
+    // the error is not exact for tiny products
+    if product.abs() < f64::MIN_POSITIVE * 2f64.powi(54) {
+        return if up { product.next_up() } else { product.next_down() };
+    }
+    round_towards(product, a.mul_add(b, -product), up)
+}
+
+/// `a / b` rounded down or up, using the exact remainder of the division
+fn div_rounded(a: f64, b: f64, up: bool) -> f64 {
+    let quotient = a / b;
+    if quotient.is_infinite() && a.is_finite() {
+        return overflowed(quotient, up);
+    }
+    if !quotient.is_finite() {
+        return quotient;
+    }
+    if quotient.abs() < f64::MIN_POSITIVE * 2f64.powi(54) || a.abs() < f64::MIN_POSITIVE * 2f64.powi(54) {
+        return if up { quotient.next_up() } else { quotient.next_down() };
+    }
+    // the exact quotient is `quotient + rest / b`
+    let rest = (-quotient).mul_add(b, a);
+    round_towards(quotient, rest * b.signum(), up)
+}
+
+/// Moves a rounded result by one ulp if the exact one, `x + err`, is on the other side of it
+fn round_towards(x: f64, err: f64, up: bool) -> f64 {
+    match (up, err) {
+        (true, err) if err > 0. => x.next_up(),
+        (false, err) if err < 0. => x.next_down(),
+        _ => x,
+    }
+}
+
+impl Display for Interval {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // not `[lo, hi]`, which would look like a vector
+        write!(f, "[{} .. {}]", self.lo, self.hi)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn exact(s: &str) -> Interval {
+        Interval::enclose(&super::super::number::parse_literal(s).unwrap())
+    }
+
+    #[test]
+    fn enclosures() {
+        let tenth = exact("0.1");
+        assert!(tenth.lo < tenth.hi);
+        assert_eq!(exact("0.5"), Interval::point(0.5));
+        let sum = (0..10).fold(Interval::point(0.), |acc, _| acc.add(&tenth));
+        assert!(sum.contains(1.) && sum.hi - sum.lo < 1e-14);
+        let x = Interval::new(-1., 2.).unwrap();
+        assert_eq!(x.powi(2), Interval::raw(0., 4.));
+        assert_eq!(x.powi(3), Interval::raw(-1., 8.));
+        assert_eq!(Interval::point(2.).add(&Interval::point(3.)), Interval::point(5.));
+        let third = Interval::point(1.).div(&Interval::point(3.));
+        assert_eq!(third.hi, third.lo.next_up());
+        assert!(third.mul(&Interval::point(3.)).contains(1.));
+        assert_eq!(x.sin().hi, 1.);
+        assert!(x.sin().lo <= (-1f64).sin() && x.sin().lo > -1.);
+        assert_eq!(Interval::new(1., 2.).unwrap().sin().hi, 1.);
+        assert_eq!(Interval::new(1., 2.).unwrap().div(&x), Interval::entire());
+        assert_eq!(Interval::new(1., 2.).unwrap().tan(), Interval::entire());
+        assert!(Interval::new(0.5, 0.6).unwrap().mul(&Interval::new(-2., 3.).unwrap()).contains(-1.2));
+        assert_eq!(Interval::new(-0.5, 1.5).unwrap().fract(), Interval::raw(-1., 1.));
+        let negative = Interval::new(-2., -1.).unwrap();
+        assert!(negative.ln().is_empty() && negative.pow(&Interval::point(0.5)).is_empty());
+        assert!(Interval::point(2.).asin().is_empty() && Interval::point(-2.).acos().is_empty());
+        assert_eq!(Interval::new(-1., 1.).unwrap().ln().lo, f64::NEG_INFINITY);
+    }
+}
@@ -1,12 +1,26 @@
 pub mod unit;
+pub mod number;
+pub mod array;
+pub mod uncertain;
+pub mod interval;
+pub mod decimal;
 mod errors;
 
 pub use unit::base_units;
+pub use unit::derived_units;
 pub use unit::Unit;
+pub use unit::Ratio;
+pub use unit::SI_PREFIXES;
+pub use number::{Backend, Number};
+pub use array::Array;
+pub use uncertain::Uncertain;
+pub use interval::Interval;
+use number::parse_literal;
 pub use errors::ValComputeError;
 pub use errors::ErrorType as ValComputeErrorType;
 use super::associations::UnitAlias;
 use super::associations::ValAlias;
+use super::suggest::did_you_mean;
 
 
 use unit::base_units::*;
@@ -16,158 +30,536 @@ use unit::base_units::*;
 pub struct ValOpts{
     cmp_epsilon: f64,
     unit_alias: UnitAlias,
+    backend: Backend,
+    decimal_digits: u32,
 }
 
 impl ValOpts{
     pub fn new() -> Self{
         ValOpts{..Default::default()}
     }
+    /// Default options behind the handle values share them through.
+    /// The handle is only shared within a single thread
+    #[allow(clippy::arc_with_non_send_sync)]
+    pub fn shared() -> Arc<RefCell<Self>>{
+        Arc::new(RefCell::new(ValOpts::default()))
+    }
     pub fn set_cmp_epsilon(&mut self, cmp_epsilon: f64) -> &Self{
         self.cmp_epsilon = cmp_epsilon;
         self
     }
-
+    pub fn get_cmp_epsilon(&self) -> f64{
+        self.cmp_epsilon
+    }
+    /// Sets the kind of numbers that number literals become
+    pub fn set_backend(&mut self, backend: Backend) -> &Self{
+        self.backend = backend;
+        self
+    }
+    pub fn get_backend(&self) -> Backend{
+        self.backend
+    }
+    /// Sets the number of significant digits of the decimal backend
+    pub fn set_decimal_digits(&mut self, digits: u32) -> &Self{
+        self.decimal_digits = digits.max(1);
+        self
+    }
+    pub fn get_decimal_digits(&self) -> u32{
+        self.decimal_digits
+    }
+    /// Turns an exact number into a number of the current backend
+    pub fn number(&self, r: BigRational) -> Number{
+        Number::from_exact(r, self.backend, self.decimal_digits)
+    }
 }
 
 impl Default for ValOpts{ 
     fn default() -> Self {
-        ValOpts{cmp_epsilon: 0.000001, unit_alias: UnitAlias::default()}
+        ValOpts{cmp_epsilon: 0.000001, unit_alias: UnitAlias::default(), backend: Backend::Float, decimal_digits: 50}
     }
 }
 
+/// Unit a value was explicitly converted to, used only for printing
+#[derive(Clone, Debug)]
+pub struct DisplayUnit{
+    pub name: String,
+    /// Size of the unit in base units
+    pub scale: Number,
+    /// Zero point of the unit in base units; not 0 only for temperature scales
+    pub offset: Number,
+    pub unit: Unit,
+}
+
 /// Struct that represents a mathematical value with unit
 #[derive(Clone, Debug)]
 pub struct Val {
     unit: Unit,
-    magn: f64,
+    magn: Number,
     options: Arc<RefCell<ValOpts>>, 
+    /// Boxed, as values are moved around a lot and this is rarely set
+    display: Option<Box<DisplayUnit>>,
+    /// A point on a temperature scale, like `20 degC`, rather than a difference of temperatures.
+    /// It can only be shifted by a difference or subtracted from another absolute temperature
+    absolute: bool,
 }
 
 impl Val {
     pub fn new (magn:f64, unit: Unit, options: Arc<RefCell<ValOpts>>) -> Self{
-        Val{magn, unit, options}
+        Self::from_number(Number::Float(magn), unit, options)
+    }
+
+    pub fn from_number (magn: Number, unit: Unit, options: Arc<RefCell<ValOpts>>) -> Self{
+        Val{magn, unit, options, display: None, absolute: false}
+    }
+
+    /// Marks the value as an absolute temperature
+    pub fn absolute(mut self) -> Self{
+        self.absolute = true;
+        self
+    }
+
+    pub fn is_absolute(&self) -> bool{
+        self.absolute
+    }
+
+    /// Fails for absolute temperatures, which can not be scaled or passed to functions.
+    /// `what` names the place of the value in messages, like `Operand of *`
+    pub fn check_relative(&self, what: &str) -> Result<(), ValComputeError>{
+        match self.absolute {
+            true => Err(ValComputeError::new(
+                    format!("{} can not be an absolute temperature; use delta_degC or delta_degF for temperature differences", what),
+                    ValComputeErrorType::IncompatibleUnits)),
+            false => Ok(()),
+        }
+    }
+
+    pub fn boolean (b: bool, options: Arc<RefCell<ValOpts>>) -> Self{
+        Self::from_number(Number::Bool(b), D, options)
+    }
+
+    /// The value of a boolean, `None` for numbers
+    pub fn as_bool(&self) -> Option<bool>{
+        self.magn.as_bool()
+    }
+
+    /// Fails for booleans, which can not be used in arithmetic.
+    /// `what` names the place of the value in messages, like `Operand of +`
+    pub fn check_number(&self, what: &str) -> Result<(), ValComputeError>{
+        match self.as_bool() {
+            Some(_) => Err(ValComputeError::new(
+                    format!("{} should be a number, not a boolean", what),
+                    ValComputeErrorType::Type)),
+            None => Ok(()),
+        }
+    }
+
+    fn get_bool(&self, what: &str) -> Result<bool, ValComputeError>{
+        self.as_bool().ok_or_else(|| ValComputeError::new(
+                format!("{} should be a boolean, not {}", what, self),
+                ValComputeErrorType::Type))
+    }
+
+    /// Combines booleans with a logical operator called `op_name`
+    pub fn logical(&self, rhs: &Val, op_name: &str, f: impl Fn(bool, bool) -> bool) -> Result<Self, ValComputeError>{
+        let what = format!("Operand of {}", op_name);
+        Ok(Val::boolean(f(self.get_bool(&what)?, rhs.get_bool(&what)?), self.options.clone()))
+    }
+
+    pub fn not(&self) -> Result<Self, ValComputeError>{
+        Ok(Val::boolean(!self.get_bool("Operand of !")?, self.options.clone()))
+    }
+
+    /// Orders numbers of the same unit. Fails for unordered ones: arrays, complex numbers,
+    /// NaN and overlapping intervals, as the order of the values in them is not known
+    pub fn compare(&self, rhs: &Val) -> Result<cmp::Ordering, ValComputeError>{
+        self.check_comparable(rhs)?;
+        if let Some(ordering) = self.partial_cmp(rhs) {
+            return Ok(ordering);
+        }
+        let (a, b) = (&self.magn, &rhs.magn);
+        let (reason, kind) = match () {
+            _ if a.as_array().is_some() || b.as_array().is_some() => ("arrays are not ordered", ValComputeErrorType::Type),
+            _ if !a.is_real() || !b.is_real() => ("complex numbers are not ordered", ValComputeErrorType::Type),
+            _ if a.is_interval() || b.is_interval() => ("the intervals overlap", ValComputeErrorType::Domain),
+            _ => ("NaN is not ordered", ValComputeErrorType::Domain),
+        };
+        Err(ValComputeError::new(format!("Can not compare {} to {}, as {}", self, rhs, reason), kind))
+    }
+
+    fn check_comparable(&self, rhs: &Val) -> Result<(), ValComputeError>{
+        self.check_number("Compared value")?;
+        rhs.check_number("Compared value")?;
+        if !self.same_unit(rhs) {
+            return Err(ValComputeError::new(
+                    "Can not compare values of different dimensions".to_string(),
+                    ValComputeErrorType::IncompatibleUnits));
+        }
+        Ok(())
+    }
+
+    /// Whether values are equal, numbers up to `ValOpts::cmp_epsilon`.
+    /// Numbers can only be compared to numbers of the same dimension and booleans to booleans
+    pub fn equals(&self, rhs: &Val) -> Result<bool, ValComputeError>{
+        match (self.as_bool(), rhs.as_bool()) {
+            (Some(a), Some(b)) => Ok(a == b),
+            (None, None) if self.magn.is_interval() || rhs.magn.is_interval() => {
+                Ok(self.compare(rhs)? == cmp::Ordering::Equal)
+            }
+            (None, None) => {
+                self.check_comparable(rhs)?;
+                Ok(self == rhs)
+            }
+            _ => Err(ValComputeError::new(
+                    "Can not compare a boolean to a number".to_string(),
+                    ValComputeErrorType::Type)),
+        }
+    }
+
+    /// Makes a vector of plain values or a matrix of vectors of the same length.
+    /// All elements should have the same unit, which becomes the unit of the array
+    pub fn array(elements: Vec<Val>, options: Arc<RefCell<ValOpts>>) -> Result<Self, ValComputeError>{
+        let Some(unit) = elements.first().map(Val::get_unit) else {
+            return Err(ValComputeError::new("Arrays can not be empty".to_string(), ValComputeErrorType::Shape));
+        };
+        for e in &elements {
+            e.check_number("Element of an array")?;
+        }
+        if elements.iter().any(|e| !e.unit.same_unit(&unit)) {
+            return Err(ValComputeError::new(
+                    "All elements of an array should have the same unit".to_string(),
+                    ValComputeErrorType::IncompatibleUnits));
+        }
+        let absolute = elements[0].absolute;
+        if elements.iter().any(|e| e.absolute != absolute) {
+            return Err(ValComputeError::new(
+                    "Elements of an array should all be absolute temperatures or all differences".to_string(),
+                    ValComputeErrorType::IncompatibleUnits));
+        }
+        let rows: Option<Vec<&Array>> = elements.iter().map(|e| e.magn.as_array()).collect();
+        let array = match rows {
+            None if elements.iter().all(|e| e.magn.as_array().is_none()) => {
+                Array::vector(elements.iter().map(|e| e.magn.clone()).collect())
+            }
+            Some(rows) if rows.iter().all(|row| row.is_vector() && row.len() == rows[0].len()) => {
+                Array::matrix(rows.iter().map(|row| row.elements().to_vec()).collect())
+            }
+            _ => return Err(ValComputeError::new(
+                    "Rows of a matrix should be vectors of the same length".to_string(),
+                    ValComputeErrorType::Shape)),
+        };
+        Ok(Val{absolute, ..Val::from_number(Number::array(array), unit, options)})
     }
 
+    /// Returns the value set to be printed in `target` units, which are called `name`
+    /// and have their zero at `offset`. Fails if `target` is of another dimension.
+    /// Values converted to a temperature scale, whose `target` is absolute, become absolute temperatures
+    pub fn convert_to(&self, target: &Val, offset: Number, name: &str) -> Result<Self, ValComputeError>{
+        self.check_number("Converted value")?;
+        target.check_number("Unit")?;
+        if !self.same_unit(target) {
+            return Err(ValComputeError::new(
+                format!("Can not convert to {}: units have different dimensions", name),
+                ValComputeErrorType::IncompatibleUnits));
+        }
+        // `delta_` units are temperature differences, see `ValAlias::insert_default`
+        if self.absolute && name.starts_with("delta_") {
+            return Err(ValComputeError::new(
+                format!("Can not convert an absolute temperature to {}; subtract another temperature to get a difference", name),
+                ValComputeErrorType::IncompatibleUnits));
+        }
+        if target.magn.is_zero() || !target.magn.to_f64().is_finite() {
+            return Err(ValComputeError::new(
+                format!("Can not convert to {}: it is not a valid unit", name),
+                ValComputeErrorType::Domain));
+        }
+        let mut ret = self.clone();
+        ret.absolute |= target.absolute;
+        ret.display = Some(Box::new(DisplayUnit{name: name.to_string(), scale: target.magn.clone(), offset, unit: target.unit}));
+        Ok(ret)
+    }
+
+    pub fn get_display_unit(&self) -> Option<&DisplayUnit>{
+        self.display.as_deref()
+    }
+
+    /// Writes the value as an expression that gives it back, see [`Number::to_source`].
+    /// The unit is written as a separate factor, as `2m^2` is `(2m)^2`;
+    /// the unit the value was converted to is not kept.
+    /// Absolute temperatures are shifted from the zero of the Rankine scale, which is 0 K
+    pub fn to_source(&self) -> String{
+        let source = match self.format_unit() {
+            unit if unit.is_empty() => self.magn.to_source(),
+            unit => format!("{} * 1{}", self.magn.to_source(), unit),
+        };
+        match self.absolute {
+            true => format!("0 degR + {}", source),
+            false => source,
+        }
+    }
+    /// Unit of the value written the way it is printed, empty for dimensionless values
+    pub fn format_unit(&self) -> String{
+        self.options.borrow().unit_alias.format(&self.unit)
+    }
     pub fn get_opts(&self) -> Arc<RefCell<ValOpts>>{
         self.options.clone()
     }
 
     pub fn set_magnetude(&mut self, magn: f64){
+        self.magn = Number::Float(magn);
+    }
+
+    pub fn get_number(&self) -> &Number{
+        &self.magn
+    }
+
+    pub fn set_number(&mut self, magn: Number){
         self.magn = magn;
     }
 
-    pub fn pow(&self, p:f64) -> Self{
+    /// Applies a function to the magnitude, keeping the unit
+    pub fn map_number(&self, f: impl Fn(&Number) -> Number) -> Self{
         let mut ret = self.clone();
-        ret.unit = ret.unit.pow(p);
-        ret.magn = ret.magn.powf(p);
+        ret.magn = f(&self.magn);
         ret
     }
 
-    pub fn pow_val(&self, p: &Val) -> Result<Self, ValComputeError>{
+    /// Applies a fallible function to the magnitude, keeping the unit
+    pub fn try_map_number(&self, f: impl Fn(&Number) -> Result<Number, ValComputeError>) -> Result<Self, ValComputeError>{
         let mut ret = self.clone();
-        if !p.get_unit().same_unit( &base_units::D, self.options.borrow().cmp_epsilon){
+        ret.magn = f(&self.magn)?;
+        Ok(ret)
+    }
+
+    /// Raises the value to a power. A value with a unit can only be raised
+    /// to a simple fraction (`m^(1/3)`), as powers of units are kept exact
+    pub fn pow(&self, p:f64) -> Result<Self, ValComputeError>{
+        self.pow_number(&Number::Float(p))
+    }
+
+    /// Square root, halving the powers of the unit. Negative numbers give imaginary ones
+    pub fn sqrt(&self) -> Result<Self, ValComputeError>{
+        self.pow_number(&Number::rational(BigRational::new(1.into(), 2.into())))
+    }
+
+    fn pow_number(&self, p: &Number) -> Result<Self, ValComputeError>{
+        let mut ret = self.clone();
+        if !self.unit.is_dimensionless() {
+            let exact = match p.as_exact() {
+                Some(r) => r.numer().to_i64().zip(r.denom().to_i64())
+                    .filter(|(_, den)| *den <= 1000)
+                    .and_then(|(num, den)| Ratio::checked_new(num, den)),
+                None => Ratio::from_f64(p.to_f64()),
+            };
+            let Some(exact) = exact else {
+                return Err(ValComputeError::new(
+                        format!("Can not rise a value with a unit to the power of {}", p),
+                        ValComputeErrorType::IncompatibleUnits));
+            };
+            ret.unit = ret.unit.checked_pow(exact).ok_or_else(unit_overflow)?;
+        }
+        ret.magn = ret.magn.checked_pow(p)?;
+        Ok(ret)
+    }
+
+    /// Interval from `self` to `hi`, they should have the same unit
+    pub fn interval(&self, hi: &Val) -> Result<Self, ValComputeError>{
+        if !self.same_unit(hi) {
+            return Err(ValComputeError::new(
+                "Bounds of an interval should have the same unit".to_string(),
+                ValComputeErrorType::IncompatibleUnits));
+        }
+        if self.absolute != hi.absolute {
+            return Err(ValComputeError::new(
+                "Bounds of an interval should both be absolute temperatures or both differences".to_string(),
+                ValComputeErrorType::IncompatibleUnits));
+        }
+        let ret = Val::from_number(Number::interval(&self.magn, &hi.magn)?, self.unit, self.options.clone());
+        Ok(Val{absolute: self.absolute, ..ret})
+    }
+
+    /// Value with a standard uncertainty of `rhs`
+    pub fn plus_minus(&self, rhs: &Val) -> Result<Self, ValComputeError>{
+        if !self.same_unit(rhs) {
+            return Err(ValComputeError::new(
+                "Units of a value and its uncertainty should be the same".to_string(),
+                ValComputeErrorType::IncompatibleUnits));
+        }
+        self.check_shapes(rhs, "uncertainty")?;
+        rhs.check_relative("Uncertainty")?;
+        Ok(self.map_number(|magn| magn.plus_minus(&rhs.magn)))
+    }
+
+    pub fn pow_val(&self, p: &Val) -> Result<Self, ValComputeError>{
+        self.check_shapes(p, "power")?;
+        if !p.get_unit().is_dimensionless(){
             return Err(ValComputeError::new(
                     "Can not rise to a power with a unit".to_string(),
                     ValComputeErrorType::IncompatibleUnits));
         }
-        let p = p.magn;
-        ret.unit = ret.unit.pow(p);
-        ret.magn = ret.magn.powf(p);
-        Ok(ret)
+        self.pow_number(&p.magn)
+    }
+
+
+    /// Remainder of division rounded towards zero.
+    /// Has the sign of `self`, like `%` in Rust or C
+    pub fn rem(&self, rhs: &Val) -> Result<Self, ValComputeError>{
+        self.check_shapes(rhs, "remainder")?;
+        self.check_divisor(rhs, "remainder")?;
+        self.try_map_number(|magn| magn.rem(&rhs.magn))
+    }
+
+    /// Remainder of division rounded down.
+    /// Has the sign of `rhs`, so `-7 mod 3` is 2
+    pub fn modulo(&self, rhs: &Val) -> Result<Self, ValComputeError>{
+        self.check_shapes(rhs, "modulo")?;
+        self.check_divisor(rhs, "modulo")?;
+        self.try_map_number(|magn| magn.modulo(&rhs.magn))
+    }
+
+    /// Division rounded down, so `-7 div 2` is -4
+    pub fn floor_div(&self, rhs: &Val) -> Result<Self, ValComputeError>{
+        if rhs.magn.is_zero() {
+            return Err(ValComputeError::new(
+                    "Integer division by zero".to_string(),
+                    ValComputeErrorType::DivisionByZero));
+        }
+        let magn = self.magn.floor_div(&rhs.magn)?;
+        Ok(Val{magn, ..(self.clone() / rhs.clone())?})
+    }
+
+    fn check_divisor(&self, rhs: &Val, op_name: &str) -> Result<(), ValComputeError>{
+        if self.unit != rhs.unit {
+            return Err(ValComputeError::new(
+                    format!("Units should be the same for {}", op_name),
+                    ValComputeErrorType::IncompatibleUnits));
+        }
+        if rhs.magn.is_zero() {
+            return Err(ValComputeError::new(
+                    format!("Can not take {} of division by zero", op_name),
+                    ValComputeErrorType::DivisionByZero));
+        }
+        Ok(())
+    }
+
+    /// Arrays can only be combined element by element if they have the same shape
+    fn check_shapes(&self, rhs: &Val, op_name: &str) -> Result<(), ValComputeError>{
+        match (self.magn.as_array(), rhs.magn.as_array()) {
+            (Some(a), Some(b)) if !a.same_shape(b) => Err(ValComputeError::new(
+                    format!("Can not take {} of arrays of shapes {} and {}", op_name, a.shape(), b.shape()),
+                    ValComputeErrorType::Shape)),
+            _ => Ok(()),
+        }
+    }
+
+    fn get_array(&self, fn_name: &str) -> Result<&Array, ValComputeError>{
+        self.magn.as_array().ok_or_else(|| ValComputeError::new(
+                format!("{} takes a vector or a matrix", fn_name),
+                ValComputeErrorType::Shape))
+    }
+
+    /// Dot product of vectors, its unit is the product of their units
+    pub fn dot(&self, rhs: &Val) -> Result<Self, ValComputeError>{
+        let magn = self.get_array("dot")?.dot(rhs.get_array("dot")?)?;
+        Ok(Val::from_number(magn, self.unit.checked_mul(rhs.unit).ok_or_else(unit_overflow)?, self.options.clone()))
+    }
+
+    pub fn cross(&self, rhs: &Val) -> Result<Self, ValComputeError>{
+        let magn = self.get_array("cross")?.cross(rhs.get_array("cross")?)?;
+        Ok(Val::from_number(Number::array(magn), self.unit.checked_mul(rhs.unit).ok_or_else(unit_overflow)?, self.options.clone()))
     }
 
+    pub fn matmul(&self, rhs: &Val) -> Result<Self, ValComputeError>{
+        let magn = self.get_array("matmul")?.matmul(rhs.get_array("matmul")?)?;
+        Ok(Val::from_number(Number::array(magn), self.unit.checked_mul(rhs.unit).ok_or_else(unit_overflow)?, self.options.clone()))
+    }
+
+    pub fn transpose(&self) -> Result<Self, ValComputeError>{
+        let magn = self.get_array("transpose")?.transpose();
+        Ok(Val::from_number(Number::array(magn), self.unit, self.options.clone()))
+    }
+
+    /// Determinant of a square matrix, its unit is the unit of the matrix to the power of its size
+    pub fn det(&self) -> Result<Self, ValComputeError>{
+        let array = self.get_array("det")?;
+        let magn = array.det(self.options.borrow().get_cmp_epsilon())?;
+        let unit = i32::try_from(array.rows()).ok()
+            .and_then(|rows| self.unit.checked_pow(Ratio::int(rows)))
+            .ok_or_else(unit_overflow)?;
+        Ok(Val::from_number(magn, unit, self.options.clone()))
+    }
+
+    pub fn inv(&self) -> Result<Self, ValComputeError>{
+        let magn = self.get_array("inv")?.inv(self.options.borrow().get_cmp_epsilon())?;
+        Ok(Val::from_number(Number::array(magn), self.unit.checked_pow(-Ratio::ONE).ok_or_else(unit_overflow)?, self.options.clone()))
+    }
+
+    /// Solves the linear system `self * x = rhs` for `x`
+    pub fn solve(&self, rhs: &Val) -> Result<Self, ValComputeError>{
+        let magn = self.get_array("solve")?.solve(rhs.get_array("solve")?, self.options.borrow().get_cmp_epsilon())?;
+        Ok(Val::from_number(Number::array(magn), rhs.unit.checked_div(self.unit).ok_or_else(unit_overflow)?, self.options.clone()))
+    }
+
+    /// Euclidean length of a vector, or the Frobenius norm of a matrix
+    pub fn norm(&self) -> Result<Self, ValComputeError>{
+        let array = self.get_array("norm")?;
+        let two = Number::rational(BigRational::from_integer(2.into()));
+        let magn = array.elements().iter()
+            .fold(Number::rational(BigRational::from_integer(0.into())), |acc, x| acc.add(&x.abs().pow(&two)))
+            .pow(&Number::rational(BigRational::new(1.into(), 2.into())));
+        Ok(Val::from_number(magn, self.unit, self.options.clone()))
+    }
 
     pub fn same_unit(&self, other: &Val) -> bool{
-        let precisionf = self.options.borrow().cmp_epsilon;
-        self.unit.same_unit(&other.unit, precisionf)
+        self.unit.same_unit(&other.unit)
     }
 
     pub fn get_unit(&self) -> Unit{
         self.unit
     }
 
+    /// Magnitude as a float, whatever kind of number it is; NaN for arrays
     pub fn get_magnetude(&self) -> f64{
-        self.magn
+        self.magn.to_f64()
     }
     pub fn from_str  (s: &str, al: &ValAlias, options: Arc<RefCell<ValOpts>>) -> Result<Self, String> {
         use regex::Regex;
         let reg = 
-            r"^(?<val>(?<neg>-)?(?<base>0[xbo])?(?<int>\d+)(\.(?<fract>\d+))?([Ee](?<exp>-?\d+))?)?\s*(?<unit>\w+)?";
+            r"^(?<val>-?(0[xbo])?[\d_]+(\.[\d_]+)?([Ee][+-]?\d+)?)?\s*(?<unit>\w+)?";
         let regex_val = Regex::new(reg).unwrap();
         let Some(caps) = regex_val.captures(s) else {return Err("Wrong value format!".to_string())};
-        if caps.name("val").is_some(){
-            let base:u32 = match caps.name("base"){
-                Some(s) => {
-                    match s.as_str(){
-                        "0b" => 2,
-                        "0o" => 8,
-                        "0x" => 16,
-                        _ => unreachable!(),
-                    }
-                },
-                None => 10,
-            };
-            let to_u64_base = |s:&str, base: u32|{
-                let len = s.len() as u32;
-                let mut ret:u64 = 0;
-                for i in 0..len{
-                    ret += (base as u64).pow(len-1-i)*
-                        s.chars().nth(i as usize).unwrap().to_string().parse::<u64>().unwrap();
-                }
-                ret 
-            };
-            let int_part: u64 = match caps.name("int") {
-                Some(s) => to_u64_base(s.as_str(), base),
-                None => unreachable!(),
-            };
-            let fract_part: f64 = match caps.name("fract"){
-                Some(s) => to_u64_base(s.as_str(), base) as f64,
-                None => 0.,
-            };
-            let fract_part_len: i32 = match caps.name("fract"){
-                Some(s) => s.as_str().len() as i32,
-                None => 0
-            };
-            let exponent_part: i32 = match caps.name("exp"){
-                Some(s) => {
-                    s.as_str().replace('-', "").parse::<i32>().unwrap() *
-                        (if  s.as_str().contains('-') {-1} else {1})
-                },
-                None => 0,
-            };
-            let neg: f64 = match caps.name("neg"){
-                Some(s) => if s.as_str().contains('-') {-1.} else {1.},
-                None => 1.,
-            };
-            
-            let mut magn = 0.;
-            magn += neg*int_part as f64;
-            magn +=  neg*(fract_part as f64) /
-                ((base as f64).powi(fract_part_len));
-            magn *= (base as f64).powi(exponent_part);
-
-            let mut ret = Val::new(magn, D, options.clone());
-            ret *= match caps.name("unit"){
-                Some(s) => {match al.get_val(s.as_str()){
-                    Some(v) => v,
-                    None => return Err(format!("No {} found", s.as_str())),
-                }},
-                None => Self::new(1., D, options.clone()),
-            };
-            Ok(ret)
-        }else{
-            Err("No number found in the string".to_string())
+        let Some(val) = caps.name("val") else {return Err("No number found in the string".to_string())};
+        let magn = options.borrow().number(parse_literal(val.as_str())?);
+        let ret = Val::from_number(magn, D, options.clone());
+        match caps.name("unit"){
+            Some(s) => match al.get_val(s.as_str()){
+                Some(v) => (ret * v).map_err(|e| e.get_desc().to_string()),
+                None => Err(format!("No {} found{}", s.as_str(), did_you_mean(&al.suggest(s.as_str())))),
+            },
+            None => Ok(ret),
         }
     }
 }
 
+/// Error for a unit whose power does not fit, like `m^2000000000 * m^2000000000`
+fn unit_overflow() -> ValComputeError{
+    ValComputeError::new("Power of the unit is too large".to_string(), ValComputeErrorType::Domain)
+}
+
 impl Display for Val {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let unit = &self.unit;
-        let prec = self.options.borrow().cmp_epsilon;
+        // arithmetic may have changed the unit since the conversion
+        if let Some(display) = self.display.as_ref().filter(|d| d.unit.same_unit(unit)) {
+            if let Ok(magn) = self.magn.via_exact(|magn| magn.sub(&display.offset).div(&display.scale)) {
+                // `10 Hz to 1/s` would read as `101/s`
+                return match display.name.starts_with(|c: char| c.is_alphabetic() || c == '°') {
+                    true => write!(f, "{}{}", magn, display.name),
+                    false => write!(f, "{} ({})", magn, display.name),
+                };
+            }
+        }
         let ual = &self.options.borrow().unit_alias;
-        let unit_mame = ual.get_name(unit, prec).unwrap_or("".to_string());
-        write!(f, "{}{}", self.magn, unit_mame)
+        write!(f, "{}{}", self.magn, ual.format(unit))
     }
 }
 
@@ -176,84 +568,79 @@ use std::fmt::Display;
 use std::sync::Arc;
 use std::ops;
 
+use num_rational::BigRational;
+use num_traits::ToPrimitive;
+
 
 impl ops::Add for Val{
     type Output = Result<Self, ValComputeError>;
     fn add(self, rhs: Self) -> Self::Output {
-        let mut ret = self;
-
-        if ret.unit == rhs.unit {
-            ret.magn += rhs.magn;
-        }else{
+        if self.absolute && rhs.absolute {
+            return Err(ValComputeError::new(
+                "Can not add two absolute temperatures; use delta_degC or delta_degF for temperature differences".to_string(),
+                ValComputeErrorType::IncompatibleUnits));
+        }
+        if self.unit != rhs.unit {
             return Err(ValComputeError::new(
                 "Units should be the same for addition".to_string(),
                 ValComputeErrorType::IncompatibleUnits
             ));
         }
-
-        Ok(ret)
+        self.check_shapes(&rhs, "sum")?;
+        let absolute = self.absolute || rhs.absolute;
+        Ok(Val{absolute, ..self.map_number(|magn| magn.add(&rhs.magn))})
     }
 }
 
 impl ops::Sub for Val{
     type Output = Result<Self, ValComputeError>;
     fn sub(self, rhs: Self) -> Self::Output {
-        let mut ret = self;
-
-        if ret.unit == rhs.unit {
-            ret.magn -= rhs.magn;
-        }else{
+        // the difference of two absolute temperatures is a temperature difference
+        let absolute = match (self.absolute, rhs.absolute) {
+            (false, true) => return Err(ValComputeError::new(
+                "Can only subtract an absolute temperature from another absolute temperature".to_string(),
+                ValComputeErrorType::IncompatibleUnits)),
+            (lhs, rhs) => lhs && !rhs,
+        };
+        if self.unit != rhs.unit {
             return Err(ValComputeError::new(
-                "Units should be the same for addition".to_string(),
+                "Units should be the same for subtraction".to_string(),
                 ValComputeErrorType::IncompatibleUnits
                     ));
         }
-        Ok(ret)
+        self.check_shapes(&rhs, "difference")?;
+        Ok(Val{absolute, ..self.map_number(|magn| magn.sub(&rhs.magn))})
     }
 }
 
 impl ops::Neg for Val{
     type Output = Self;
     fn neg(self) -> Self::Output {
-        let mut ret = self;
-        ret.magn = -ret.magn;
-        ret
+        self.map_number(Number::neg)
     }
 }
 
 impl ops::Mul for Val {
-    type Output = Self;
+    type Output = Result<Self, ValComputeError>;
     fn mul(self, rhs: Self) -> Self::Output {
+        self.check_shapes(&rhs, "product")?;
         let mut ret = self;
 
-        ret.magn *= rhs.magn;
-        ret.unit *= rhs.unit;
-        ret
-    }
-}
-
-impl ops::MulAssign for Val  {
-    fn mul_assign(&mut self, rhs: Self) {
-        self.magn *= rhs.magn;
-        self.unit *= rhs.unit;
+        ret.magn = ret.magn.mul(&rhs.magn);
+        ret.unit = ret.unit.checked_mul(rhs.unit).ok_or_else(unit_overflow)?;
+        Ok(ret)
     }
 }
 
 impl ops::Div for Val  {
-    type Output = Self;
+    type Output = Result<Self, ValComputeError>;
     fn div(self, rhs: Self) -> Self::Output {
+        self.check_shapes(&rhs, "quotient")?;
         let mut ret = self;
 
-        ret.magn /= rhs.magn;
-        ret.unit /= rhs.unit;
-        ret
-    }
-}
-
-impl ops::DivAssign for Val  {
-    fn div_assign(&mut self, rhs: Self) {
-        self.magn /= rhs.magn;
-        self.unit /= rhs.unit;
+        ret.magn = ret.magn.div(&rhs.magn)?;
+        ret.unit = ret.unit.checked_div(rhs.unit).ok_or_else(unit_overflow)?;
+        Ok(ret)
     }
 }
 
@@ -261,15 +648,14 @@ use std::cmp;
 
 impl cmp::PartialEq for Val  {
     fn eq(&self, other: &Self) -> bool {
-        return (self.magn.abs()-other.magn.abs()).abs() < self.options.borrow().cmp_epsilon 
-            && self.same_unit(other);
+        self.same_unit(other) && self.magn.approx_eq(&other.magn, self.options.borrow().cmp_epsilon)
     }
 }
 
 impl cmp::PartialOrd for Val  {
     fn partial_cmp(&self, other: &Self) -> Option<cmp::Ordering> {
         if self.same_unit(other) {
-            Some(self.magn.partial_cmp(&other.magn).unwrap())
+            self.magn.partial_cmp(&other.magn)
         }else{
             None
         }
@@ -279,13 +665,33 @@ impl cmp::PartialOrd for Val  {
 #[cfg(test)]
 mod tests {
     use super::*;
-    
+
     #[test]
     fn test_basic_math() {
-        let opts = Arc::new(RefCell::new(ValOpts::default()));
+        let opts = ValOpts::shared();
         let a = Val::new(1., D, opts.clone());
         let b = Val::new(1., KG, opts.clone());
         assert_eq!((a.clone()+a.clone()).unwrap(), Val::new(2., D, opts.clone()));
-        assert_eq!(b.clone().pow(2.), Val::new(1., KG.pow(2.), opts.clone()));
+        assert_eq!(b.clone().pow(2.).unwrap(), Val::new(1., KG.powi(2), opts.clone()));
+        assert_eq!(b.pow(1./3.).unwrap().pow(3.).unwrap().get_unit(), KG);
+        assert!(b.pow(std::f64::consts::PI).is_err());
+        assert!(a.pow(std::f64::consts::PI).is_ok());
+    }
+
+    #[test]
+    fn test_remainders() {
+        let opts = ValOpts::shared();
+        let v = |x: f64| Val::new(x, M, opts.clone());
+        let magn = |x: Result<Val, ValComputeError>| x.unwrap().get_magnetude();
+        assert_eq!(magn(v(7.).rem(&v(2.))), 1.);
+        assert_eq!(magn(v(-7.).rem(&v(2.))), -1.);
+        assert_eq!(magn(v(-7.).modulo(&v(3.))), 2.);
+        assert_eq!(magn(v(7.).modulo(&v(-3.))), -2.);
+        assert_eq!(magn(v(5.5).rem(&v(2.))), 1.5);
+        assert_eq!(magn(v(-7.).floor_div(&v(2.))), -4.);
+        assert_eq!(v(7.).floor_div(&v(2.)).unwrap().get_unit(), D);
+        assert!(v(7.).rem(&Val::new(2., S, opts.clone())).is_err());
+        assert!(v(7.).modulo(&v(0.)).is_err());
+        assert!(v(7.).floor_div(&v(0.)).is_err());
     }
 }
@@ -0,0 +1,864 @@
+//! Magnitudes of values: floats, exact rationals, decimals of a set precision, complex numbers,
+//! numbers with an uncertainty, intervals and arrays of them
+
+use std::cmp::Ordering;
+use std::fmt::Display;
+use std::str::FromStr;
+
+use num_bigint::BigInt;
+use num_complex::Complex64;
+use num_integer::Integer;
+use num_rational::BigRational;
+use num_traits::{One, Signed, ToPrimitive, Zero};
+
+use super::array::Array;
+use super::decimal;
+use super::interval::Interval;
+use super::uncertain::Uncertain;
+use super::{ValComputeError, ValComputeErrorType};
+
+/// Kind of numbers that number literals are turned into
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Backend {
+    #[default]
+    Float,
+    /// Exact fractions of any size, so `0.1 + 0.2 == 0.3`
+    Rational,
+    /// Decimals rounded to `ValOpts::decimal_digits` significant digits
+    Decimal,
+    /// Intervals that surely contain the exact value, literals become the tightest ones
+    Interval,
+}
+
+impl FromStr for Backend {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "float" => Ok(Backend::Float),
+            "rational" => Ok(Backend::Rational),
+            "decimal" => Ok(Backend::Decimal),
+            "interval" => Ok(Backend::Interval),
+            _ => Err(format!("No such backend: '{s}', expected float, rational, decimal or interval")),
+        }
+    }
+}
+
+impl Display for Backend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Backend::Float => "float",
+            Backend::Rational => "rational",
+            Backend::Decimal => "decimal",
+            Backend::Interval => "interval",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Magnitude of a value.
+/// Operations on numbers of different kinds give the kind with the higher rank:
+/// rational, then float, then decimal, then uncertain, then interval, then complex
+/// (which lose the uncertainty, or the width of the interval). Floats become intervals
+/// one ulp wide on both sides, as they may already be rounded. Functions without an exact version
+/// (`sin`, `ln`, non-integer powers) compute rationals as floats.
+/// Operations on arrays are done element by element, a plain number is paired with every element
+#[derive(Clone, Debug)]
+pub enum Number {
+    Float(f64),
+    /// Boxed to keep values small, as they are moved around a lot
+    Rational(Box<BigRational>),
+    /// Number already rounded to the given count of significant digits
+    Decimal(Box<BigRational>, u32),
+    /// Complex number with float parts
+    Complex(Complex64),
+    /// Float with a standard uncertainty
+    Uncertain(Box<Uncertain>),
+    /// Interval of floats containing the exact value
+    Interval(Interval),
+    /// Vector or matrix, its elements are never arrays
+    Array(Box<Array>),
+    /// Result of a comparison, not a number: values checks that it is not used in arithmetic
+    Bool(bool),
+}
+
+/// Largest exponent an exact number can be raised to, to keep it from eating all the memory
+const MAX_EXACT_POW: i32 = 100_000;
+/// Largest root of a decimal found to full precision, higher ones are computed as floats
+const MAX_EXACT_ROOT: u32 = 100;
+
+impl Number {
+    pub fn rational(r: BigRational) -> Number {
+        Number::Rational(Box::new(r))
+    }
+
+    pub fn decimal(r: BigRational, digits: u32) -> Number {
+        Number::Decimal(Box::new(round_to_digits(&r, digits)), digits)
+    }
+
+    /// Creates a number of the given backend from an exact value
+    pub fn from_exact(r: BigRational, backend: Backend, digits: u32) -> Number {
+        match backend {
+            Backend::Float => Number::Float(r.to_f64().unwrap_or(f64::NAN)),
+            Backend::Rational => Number::rational(r),
+            Backend::Decimal => Number::decimal(r, digits),
+            Backend::Interval => Number::Interval(Interval::enclose(&r)),
+        }
+    }
+
+    /// Pi as a number of the given backend, decimals get all of their digits
+    pub fn pi(backend: Backend, digits: u32) -> Number {
+        match backend {
+            Backend::Decimal => Number::decimal(decimal::pi(digits), digits),
+            _ => Number::Float(std::f64::consts::PI),
+        }
+    }
+
+    pub fn complex(re: f64, im: f64) -> Number {
+        Number::Complex(Complex64::new(re, im))
+    }
+
+    pub fn uncertain(u: Uncertain) -> Number {
+        Number::Uncertain(Box::new(u))
+    }
+
+    /// Standard uncertainty, 0 for numbers that are not uncertain
+    pub fn uncertainty(&self) -> Number {
+        match self {
+            Number::Uncertain(u) => Number::Float(u.uncertainty()),
+            Number::Array(a) => Number::array(a.map(Number::uncertainty)),
+            _ => self.map_exact(|_| BigRational::zero(), |_| 0.),
+        }
+    }
+
+    /// The value without its uncertainty
+    pub fn nominal(&self) -> Number {
+        match self {
+            Number::Uncertain(u) => Number::Float(u.value()),
+            Number::Array(a) => Number::array(a.map(Number::nominal)),
+            _ => self.clone(),
+        }
+    }
+
+    fn to_uncertain(&self) -> Uncertain {
+        match self {
+            Number::Uncertain(u) => (**u).clone(),
+            _ => Uncertain::exact(self.to_f64()),
+        }
+    }
+
+    /// Interval between two real numbers, fails if `lo` is greater than `hi`
+    pub fn interval(lo: &Number, hi: &Number) -> Result<Number, ValComputeError> {
+        if !lo.is_real() || !hi.is_real() || lo.as_array().is_some() || hi.as_array().is_some() {
+            return Err(ValComputeError::new("Bounds of an interval should be real numbers".to_string(),
+                                            ValComputeErrorType::Domain));
+        }
+        if lo.partial_cmp(hi) == Some(Ordering::Greater) {
+            return Err(ValComputeError::new(format!("Interval can not start at {} and end at {}", lo, hi),
+                                            ValComputeErrorType::Domain));
+        }
+        Ok(Number::Interval(lo.to_interval().hull(&hi.to_interval())))
+    }
+
+    fn to_interval(&self) -> Interval {
+        match self {
+            Number::Interval(i) => *i,
+            Number::Rational(r) | Number::Decimal(r, _) => Interval::enclose(r),
+            _ => Interval::around(self.to_f64()),
+        }
+    }
+
+    pub fn array(a: Array) -> Number {
+        Number::Array(Box::new(a))
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Number::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    pub fn is_interval(&self) -> bool {
+        matches!(self, Number::Interval(_))
+    }
+
+    pub fn as_array(&self) -> Option<&Array> {
+        match self {
+            Number::Array(a) => Some(a),
+            _ => None,
+        }
+    }
+
+    /// Turns a float into an exact number using its shortest decimal form,
+    /// so that `0.001` becomes exactly one thousandth. Infinities and NaN stay floats
+    pub fn exact_from_f64(x: f64) -> Number {
+        match parse_literal(&format!("{:e}", x)) {
+            Ok(r) if x.is_finite() => Number::rational(r),
+            _ => Number::Float(x),
+        }
+    }
+
+    /// Applies `f` to a float as the decimal it is written as, and gives back a float.
+    /// Temperature scales are shifted this way, as the float sum of their decimal zero points
+    /// is off in the last digits (`0 degC to degF` would be 31.999999999999936)
+    pub fn via_exact(&self, f: impl FnOnce(&Number) -> Result<Number, ValComputeError>) -> Result<Number, ValComputeError> {
+        match (self, Number::exact_from_f64(self.to_f64())) {
+            (Number::Float(_), exact @ Number::Rational(_)) => f(&exact).map(|ret| match ret {
+                Number::Rational(r) => Number::Float(r.to_f64().unwrap_or(f64::NAN)),
+                other => other,
+            }),
+            _ => f(self),
+        }
+    }
+
+    /// The number as a float, NaN for complex numbers with an imaginary part, arrays and booleans
+    pub fn to_f64(&self) -> f64 {
+        match self {
+            Number::Float(x) => *x,
+            Number::Rational(r) | Number::Decimal(r, _) => r.to_f64().unwrap_or(f64::NAN),
+            Number::Complex(z) if z.im == 0. => z.re,
+            Number::Uncertain(u) => u.value(),
+            Number::Interval(i) => i.mid(),
+            Number::Complex(_) | Number::Array(_) | Number::Bool(_) => f64::NAN,
+        }
+    }
+
+    pub fn to_complex(&self) -> Complex64 {
+        match self {
+            Number::Complex(z) => *z,
+            _ => Complex64::new(self.to_f64(), 0.),
+        }
+    }
+
+    /// The exact value, if the number is not a float
+    pub fn as_exact(&self) -> Option<&BigRational> {
+        match self {
+            Number::Float(_) | Number::Complex(_) | Number::Uncertain(_) | Number::Interval(_) | Number::Array(_)
+            | Number::Bool(_) => None,
+            Number::Rational(r) | Number::Decimal(r, _) => Some(r),
+        }
+    }
+
+    pub fn is_zero(&self) -> bool {
+        match self {
+            Number::Float(x) => *x == 0.,
+            Number::Rational(r) | Number::Decimal(r, _) => r.is_zero(),
+            Number::Complex(z) => z.is_zero(),
+            Number::Uncertain(u) => u.value() == 0.,
+            Number::Interval(i) => *i == Interval::point(0.),
+            Number::Array(_) | Number::Bool(_) => false,
+        }
+    }
+
+    /// Whether the number has no imaginary part
+    pub fn is_real(&self) -> bool {
+        match self {
+            Number::Complex(z) => z.im == 0.,
+            Number::Array(a) => a.elements().iter().all(Number::is_real),
+            _ => true,
+        }
+    }
+
+    /// Brings both numbers to the same kind
+    fn unify(&self, rhs: &Number) -> (Number, Number) {
+        use Number::*;
+        match (self, rhs) {
+            (Complex(_), _) | (_, Complex(_)) => (Complex(self.to_complex()), Complex(rhs.to_complex())),
+            (Interval(_), _) | (_, Interval(_)) => (Interval(self.to_interval()), Interval(rhs.to_interval())),
+            (Uncertain(_), _) | (_, Uncertain(_)) => {
+                (Number::uncertain(self.to_uncertain()), Number::uncertain(rhs.to_uncertain()))
+            }
+            (Decimal(_, d), other) | (other, Decimal(_, d)) if !matches!(other, Decimal(..)) => {
+                (self.to_decimal(*d), rhs.to_decimal(*d))
+            }
+            (Decimal(a, d1), Decimal(b, d2)) => {
+                let d = *d1.max(d2);
+                (Decimal(a.clone(), d), Decimal(b.clone(), d))
+            }
+            (Float(_), Rational(_)) | (Rational(_), Float(_)) => (Float(self.to_f64()), Float(rhs.to_f64())),
+            _ => (self.clone(), rhs.clone()),
+        }
+    }
+
+    fn to_decimal(&self, digits: u32) -> Number {
+        match self {
+            Number::Float(x) => match Number::exact_from_f64(*x) {
+                Number::Rational(r) => Number::decimal(*r, digits),
+                float => float,
+            },
+            Number::Rational(r) | Number::Decimal(r, _) => Number::decimal((**r).clone(), digits),
+            Number::Complex(_) | Number::Uncertain(_) | Number::Interval(_) | Number::Bool(_) => self.clone(),
+            Number::Array(a) => Number::array(a.map(|n| n.to_decimal(digits))),
+        }
+    }
+
+    /// Applies `f` to every element if either number is an array
+    fn broadcast(&self, rhs: &Number, f: impl Fn(&Number, &Number) -> Number) -> Option<Number> {
+        let ret = match (self, rhs) {
+            (Number::Array(a), Number::Array(b)) => a.zip(b, f),
+            (Number::Array(a), x) => a.map(|n| f(n, x)),
+            (x, Number::Array(b)) => b.map(|n| f(x, n)),
+            _ => return None,
+        };
+        Some(Number::array(ret))
+    }
+
+    /// Same as [`Number::broadcast`] for operations that can fail
+    fn try_broadcast(&self, rhs: &Number, f: impl Fn(&Number, &Number) -> Result<Number, ValComputeError>)
+                     -> Option<Result<Number, ValComputeError>> {
+        let ret = match (self, rhs) {
+            (Number::Array(a), Number::Array(b)) => a.try_zip(b, f),
+            (Number::Array(a), x) => a.try_map(|n| f(n, x)),
+            (x, Number::Array(b)) => b.try_map(|n| f(x, n)),
+            _ => return None,
+        };
+        Some(ret.map(Number::array))
+    }
+
+    /// Rounded division is only defined for real numbers, as complex numbers are not ordered
+    fn check_real(&self, rhs: &Number, op_name: &str) -> Result<(), ValComputeError> {
+        match self.is_real() && rhs.is_real() {
+            true => Ok(()),
+            false => Err(ValComputeError::new(format!("Can not take {} of complex numbers", op_name),
+                                              ValComputeErrorType::Type)),
+        }
+    }
+
+    /// Applies an exact operation or a float one, depending on the kind of the numbers
+    /// `derivatives` gives the partial derivatives of `float`, used for uncertain numbers
+    fn combine(&self, rhs: &Number, exact: impl Fn(&BigRational, &BigRational) -> BigRational,
+               float: impl Fn(f64, f64) -> f64, complex: impl Fn(Complex64, Complex64) -> Complex64,
+               derivatives: impl Fn(f64, f64) -> (f64, f64), interval: impl Fn(&Interval, &Interval) -> Interval) -> Number {
+        match self.unify(rhs) {
+            (Number::Interval(a), Number::Interval(b)) => Number::Interval(interval(&a, &b)),
+            (Number::Uncertain(a), Number::Uncertain(b)) => {
+                let (x, y) = (a.value(), b.value());
+                Number::uncertain(a.combine(&b, float(x, y), derivatives(x, y)))
+            }
+            (Number::Rational(a), Number::Rational(b)) => Number::rational(exact(&a, &b)),
+            (Number::Decimal(a, d), Number::Decimal(b, _)) => Number::decimal(exact(&a, &b), d),
+            (Number::Complex(a), Number::Complex(b)) => Number::Complex(complex(a, b)),
+            (a, b) => Number::Float(float(a.to_f64(), b.to_f64())),
+        }
+    }
+
+    /// Result of `exact` keeps the kind of `self`, `float` is used for floats
+    /// and for both parts of complex numbers
+    fn map_exact(&self, exact: impl Fn(&BigRational) -> BigRational, float: impl Fn(f64) -> f64) -> Number {
+        match self {
+            Number::Float(x) => Number::Float(float(*x)),
+            Number::Complex(z) => Number::complex(float(z.re), float(z.im)),
+            Number::Uncertain(u) => Number::uncertain(u.map_f64(float)),
+            Number::Interval(i) => Number::Interval(i.map_exact(float)),
+            Number::Array(a) => {
+                let exact: &dyn Fn(&BigRational) -> BigRational = &exact;
+                let float: &dyn Fn(f64) -> f64 = &float;
+                Number::array(a.map(|n| n.map_exact(exact, float)))
+            }
+            Number::Rational(r) => Number::rational(exact(r)),
+            Number::Decimal(r, d) => Number::decimal(exact(r), *d),
+            Number::Bool(_) => self.clone(),
+        }
+    }
+
+    /// Applies a function that has no exact version.
+    /// Decimals stay decimals, but only have the precision of a float.
+    /// Intervals go through `interval`, as the bounds of the result are not always the values at the bounds
+    pub fn map_f64(&self, f: impl Fn(f64) -> f64, interval: impl Fn(&Interval) -> Interval) -> Number {
+        match self {
+            Number::Decimal(_, d) => Number::Float(f(self.to_f64())).to_decimal(*d),
+            Number::Uncertain(u) => Number::uncertain(u.map_f64(f)),
+            Number::Interval(i) => Number::Interval(interval(i)),
+            Number::Array(a) => {
+                let f: &dyn Fn(f64) -> f64 = &f;
+                let interval: &dyn Fn(&Interval) -> Interval = &interval;
+                Number::array(a.map(|n| n.map_f64(f, interval)))
+            }
+            _ => Number::Float(f(self.to_f64())),
+        }
+    }
+
+    /// Applies a function that is defined on complex numbers too.
+    /// Real numbers go through `real`, unless it is not defined for them,
+    /// like `ln` of a negative number, and `complex` is used instead.
+    /// Intervals go through `interval`, which should give an enclosure of all values,
+    /// and decimals through `decimal`, which computes the function to the given digits.
+    /// Fails for uncertain numbers outside of the real domain, as complex numbers carry no uncertainty,
+    /// and for intervals that have no values in it
+    pub fn map_analytic(&self, real: impl Fn(f64) -> f64, complex: impl Fn(Complex64) -> Complex64,
+                        interval: impl Fn(&Interval) -> Interval,
+                        decimal: impl Fn(&BigRational, u32) -> Option<BigRational>) -> Result<Number, ValComputeError> {
+        let x = self.to_f64();
+        match self {
+            Number::Complex(z) => Ok(Number::Complex(complex(*z))),
+            Number::Interval(i) => match interval(i) {
+                ret if ret.is_empty() => Err(no_real_values(self)),
+                ret => Ok(Number::Interval(ret)),
+            },
+            Number::Array(a) => {
+                let real: &dyn Fn(f64) -> f64 = &real;
+                let complex: &dyn Fn(Complex64) -> Complex64 = &complex;
+                let interval: &dyn Fn(&Interval) -> Interval = &interval;
+                let decimal: &dyn Fn(&BigRational, u32) -> Option<BigRational> = &decimal;
+                Ok(Number::array(a.try_map(|n| n.map_analytic(real, complex, interval, decimal))?))
+            }
+            Number::Uncertain(_) if real(x).is_nan() && !x.is_nan() => Err(complex_uncertain(self)),
+            _ if real(x).is_nan() && !x.is_nan() => Ok(Number::Complex(complex(Complex64::new(x, 0.)))),
+            // decimals too close to a pole or to the edge of the domain for a float to see are left to it
+            Number::Decimal(r, d) => Ok(decimal(r, *d)
+                .map(|ret| Number::decimal(ret, *d))
+                .unwrap_or_else(|| self.map_f64(real, interval))),
+            _ => Ok(self.map_f64(real, interval)),
+        }
+    }
+
+    pub fn re(&self) -> Number {
+        match self {
+            Number::Complex(z) => Number::Float(z.re),
+            Number::Array(a) => Number::array(a.map(Number::re)),
+            _ => self.clone(),
+        }
+    }
+
+    pub fn im(&self) -> Number {
+        match self {
+            Number::Complex(z) => Number::Float(z.im),
+            Number::Array(a) => Number::array(a.map(Number::im)),
+            _ => self.map_exact(|_| BigRational::zero(), |_| 0.),
+        }
+    }
+
+    /// Angle of the number in the complex plane
+    pub fn arg(&self) -> Number {
+        match self {
+            Number::Array(a) => Number::array(a.map(Number::arg)),
+            Number::Interval(i) => Number::Interval(i.arg()),
+            Number::Decimal(r, d) if r.is_negative() => Number::pi(Backend::Decimal, *d),
+            Number::Decimal(_, d) => Number::decimal(BigRational::zero(), *d),
+            _ => Number::Float(self.to_complex().arg()),
+        }
+    }
+
+    pub fn conj(&self) -> Number {
+        match self {
+            Number::Complex(z) => Number::Complex(z.conj()),
+            Number::Array(a) => Number::array(a.map(Number::conj)),
+            _ => self.clone(),
+        }
+    }
+
+    pub fn add(&self, rhs: &Number) -> Number {
+        self.broadcast(rhs, Number::add)
+            .unwrap_or_else(|| self.combine(rhs, |a, b| a + b, |a, b| a + b, |a, b| a + b, |_, _| (1., 1.), Interval::add))
+    }
+
+    pub fn sub(&self, rhs: &Number) -> Number {
+        self.broadcast(rhs, Number::sub)
+            .unwrap_or_else(|| self.combine(rhs, |a, b| a - b, |a, b| a - b, |a, b| a - b, |_, _| (1., -1.), Interval::sub))
+    }
+
+    pub fn mul(&self, rhs: &Number) -> Number {
+        self.broadcast(rhs, Number::mul)
+            .unwrap_or_else(|| self.combine(rhs, |a, b| a * b, |a, b| a * b, |a, b| a * b, |a, b| (b, a), Interval::mul))
+    }
+
+    /// Division by an exact zero is an error, floats follow IEEE 754
+    pub fn div(&self, rhs: &Number) -> Result<Number, ValComputeError> {
+        match (self, rhs) {
+            (Number::Array(a), Number::Array(b)) => return Ok(Number::array(a.try_zip(b, Number::div)?)),
+            (Number::Array(a), x) => return Ok(Number::array(a.try_map(|n| n.div(x))?)),
+            (x, Number::Array(b)) => return Ok(Number::array(b.try_map(|n| x.div(n))?)),
+            _ => {}
+        }
+        if rhs.as_exact().is_some_and(Zero::is_zero) && !matches!(self, Number::Float(_)) {
+            return Err(ValComputeError::new("Division by zero".to_string(), ValComputeErrorType::DivisionByZero));
+        }
+        Ok(self.combine(rhs, |a, b| a / b, |a, b| a / b, |a, b| a / b, |a, b| (1. / b, -a / (b * b)),
+                        Interval::div))
+    }
+
+    /// Adds an independent standard uncertainty to the number.
+    /// For intervals gives the interval from `self - uncertainty` to `self + uncertainty`
+    pub fn plus_minus(&self, uncertainty: &Number) -> Number {
+        if let Some(ret) = self.broadcast(uncertainty, Number::plus_minus) {
+            return ret;
+        }
+        if let (Number::Interval(x), Number::Interval(u)) = self.unify(uncertainty) {
+            let u = u.abs();
+            return Number::Interval(x.sub(&u).hull(&x.add(&u)));
+        }
+        self.add(&Number::uncertain(Uncertain::new(0., uncertainty.to_f64())))
+    }
+
+    pub fn neg(&self) -> Number {
+        self.map_exact(|r| -r, |x| -x)
+    }
+
+    /// Absolute value, the modulus for complex numbers
+    pub fn abs(&self) -> Number {
+        match self {
+            Number::Complex(z) => Number::Float(z.norm()),
+            Number::Array(a) => Number::array(a.map(Number::abs)),
+            Number::Interval(i) => Number::Interval(i.abs()),
+            _ => self.map_exact(|r| r.abs(), f64::abs),
+        }
+    }
+
+    /// Rounds down
+    fn floor(&self) -> Number {
+        self.map_exact(|r| r.floor(), f64::floor)
+    }
+
+    /// Fractional part with the sign of the number, like `f64::fract`
+    pub fn fract(&self) -> Number {
+        match self {
+            Number::Interval(i) => Number::Interval(i.fract()),
+            _ => self.map_exact(|r| r.fract(), f64::fract),
+        }
+    }
+
+    /// Like [`Number::pow`], but fails for fractional powers of negative uncertain numbers,
+    /// as the complex results carry no uncertainty, and of intervals of negative numbers
+    pub fn checked_pow(&self, p: &Number) -> Result<Number, ValComputeError> {
+        match (self, p) {
+            (Number::Array(a), Number::Array(b)) => Ok(Number::array(a.try_zip(b, Number::checked_pow)?)),
+            (Number::Array(a), p) => Ok(Number::array(a.try_map(|n| n.checked_pow(p))?)),
+            (x, Number::Array(b)) => Ok(Number::array(b.try_map(|n| x.checked_pow(n))?)),
+            (Number::Uncertain(_), _) | (_, Number::Uncertain(_)) if self.to_f64() < 0. && p.to_f64().fract() != 0. => {
+                Err(complex_uncertain(self))
+            }
+            _ => match self.pow(p) {
+                Number::Interval(i) if i.is_empty() => Err(no_real_values(self)),
+                ret => Ok(ret),
+            },
+        }
+    }
+
+    /// Exact numbers raised to integer powers stay exact
+    pub fn pow(&self, p: &Number) -> Number {
+        if let Some(ret) = self.broadcast(p, Number::pow) {
+            return ret;
+        }
+        let int_pow = p.as_exact()
+            .filter(|p| p.is_integer())
+            .and_then(|p| p.to_integer().to_i32())
+            .filter(|p| p.abs() <= MAX_EXACT_POW);
+        // roots of decimals are found to full precision
+        let root = p.as_exact()
+            .filter(|p| p.denom() <= &BigInt::from(MAX_EXACT_ROOT))
+            .and_then(|p| Some((p.numer().to_i32()?, p.denom().to_u32()?)))
+            .filter(|(num, _)| num.abs() <= MAX_EXACT_POW);
+        match (self, int_pow, root) {
+            (Number::Rational(r), Some(p), _) if !(r.is_zero() && p < 0) => Number::rational(r.pow(p)),
+            (Number::Decimal(r, d), Some(p), _) if !(r.is_zero() && p < 0) => Number::decimal(r.pow(p), *d),
+            (Number::Decimal(r, d), _, Some((num, den))) if r.is_positive() => {
+                Number::decimal(nth_root(&r.pow(num), den, *d), *d)
+            }
+            _ => match self.unify(p) {
+                (Number::Complex(base), Number::Complex(p)) => Number::Complex(complex_pow(base, p)),
+                (Number::Interval(base), Number::Interval(p)) => Number::Interval(base.pow(&p)),
+                (Number::Uncertain(base), Number::Uncertain(p)) => {
+                    let (x, p_value) = (base.value(), p.value());
+                    let value = x.powf(p_value);
+                    // the derivative by the exponent is only needed if it is uncertain
+                    let d_p = if p.uncertainty() == 0. { 0. } else { value * x.ln() };
+                    Number::uncertain(base.combine(&p, value, (p_value * x.powf(p_value - 1.), d_p)))
+                }
+                (Number::Decimal(base, d), Number::Decimal(p, _)) if base.is_positive() => match decimal::pow(&base, &p, d) {
+                    Some(ret) => Number::decimal(ret, d),
+                    None => Number::Float(base.to_f64().unwrap_or(f64::NAN).powf(p.to_f64().unwrap_or(f64::NAN))),
+                },
+                // roots of negative numbers are complex
+                (base, p) if base.to_f64() < 0. && p.to_f64().fract() != 0. => {
+                    Number::Complex(complex_pow(base.to_complex(), p.to_complex()))
+                }
+                (base, p) => base.map_f64(|x| x.powf(p.to_f64()), |i| i.pow(&p.to_interval())),
+            },
+        }
+    }
+
+    /// Remainder of division rounded towards zero. Fails for complex numbers
+    pub fn rem(&self, rhs: &Number) -> Result<Number, ValComputeError> {
+        if let Some(ret) = self.try_broadcast(rhs, Number::rem) {
+            return ret;
+        }
+        self.check_real(rhs, "the remainder")?;
+        // complex numbers here have no imaginary part
+        Ok(self.combine(rhs, |a, b| a - b * (a / b).trunc(), |a, b| a % b, |a, b| Complex64::from(a.re % b.re),
+                        |a, b| (1., -(a / b).trunc()), Interval::rem))
+    }
+
+    /// Remainder of division rounded down. Fails for complex numbers
+    pub fn modulo(&self, rhs: &Number) -> Result<Number, ValComputeError> {
+        if let Some(ret) = self.try_broadcast(rhs, Number::modulo) {
+            return ret;
+        }
+        self.check_real(rhs, "modulo")?;
+        let float = |a: f64, b: f64| a - b * (a / b).floor();
+        Ok(self.combine(rhs, |a, b| a - b * (a / b).floor(), float, |a, b| Complex64::from(float(a.re, b.re)),
+                        |a, b| (1., -(a / b).floor()), Interval::modulo))
+    }
+
+    /// Division rounded down. Fails for complex numbers
+    pub fn floor_div(&self, rhs: &Number) -> Result<Number, ValComputeError> {
+        if let Some(ret) = self.try_broadcast(rhs, Number::floor_div) {
+            return ret;
+        }
+        self.check_real(rhs, "integer division")?;
+        Ok(self.div(rhs)?.floor())
+    }
+
+    /// Compares numbers; exact numbers are equal only if they are exactly equal,
+    /// floats if they differ by less than `epsilon`
+    pub fn approx_eq(&self, rhs: &Number, epsilon: f64) -> bool {
+        match (self, rhs) {
+            (Number::Array(a), Number::Array(b)) => return a.all_eq(b, |x, y| x.approx_eq(y, epsilon)),
+            (Number::Array(_), _) | (_, Number::Array(_)) => return false,
+            (Number::Bool(a), Number::Bool(b)) => return a == b,
+            (Number::Bool(_), _) | (_, Number::Bool(_)) => return false,
+            _ => {}
+        }
+        match self.unify(rhs) {
+            (Number::Float(a), Number::Float(b)) => (a - b).abs() < epsilon,
+            (Number::Complex(a), Number::Complex(b)) => (a - b).norm() < epsilon,
+            (Number::Uncertain(a), Number::Uncertain(b)) => (a.value() - b.value()).abs() < epsilon,
+            (Number::Interval(a), Number::Interval(b)) => {
+                (a.lo() - b.lo()).abs() < epsilon && (a.hi() - b.hi()).abs() < epsilon
+            }
+            (a, b) => a.as_exact() == b.as_exact(),
+        }
+    }
+
+    /// Complex numbers are only ordered if they have no imaginary part, arrays are not ordered.
+    /// Intervals are ordered if they do not overlap, and equal only if both are the same point
+    pub fn partial_cmp(&self, rhs: &Number) -> Option<Ordering> {
+        if matches!(self, Number::Array(_) | Number::Bool(_)) || matches!(rhs, Number::Array(_) | Number::Bool(_)) {
+            return None;
+        }
+        match self.unify(rhs) {
+            (Number::Float(a), Number::Float(b)) => a.partial_cmp(&b),
+            (Number::Complex(a), Number::Complex(b)) if a.im == 0. && b.im == 0. => a.re.partial_cmp(&b.re),
+            (Number::Complex(_), Number::Complex(_)) => None,
+            (Number::Uncertain(a), Number::Uncertain(b)) => a.value().partial_cmp(&b.value()),
+            (Number::Interval(a), Number::Interval(b)) => match () {
+                _ if a.hi() < b.lo() => Some(Ordering::Less),
+                _ if a.lo() > b.hi() => Some(Ordering::Greater),
+                _ if a == b && a.lo() == a.hi() => Some(Ordering::Equal),
+                _ => None,
+            },
+            (a, b) => a.as_exact().partial_cmp(&b.as_exact()),
+        }
+    }
+}
+
+impl From<f64> for Number {
+    fn from(x: f64) -> Self {
+        Number::Float(x)
+    }
+}
+
+impl Number {
+    /// Writes the number as an expression that gives it back when parsed with the same backend.
+    /// Unlike `Display`, uncertainties are not rounded and intervals are written as `interval(lo, hi)`.
+    /// Infinite and undefined floats are written as the constants `inf` and `NaN`,
+    /// which complex numbers can not be built from
+    pub fn to_source(&self) -> String {
+        match self {
+            Number::Uncertain(u) => format!("({}±{})", u.value(), u.uncertainty()),
+            Number::Interval(i) => format!("interval({}, {})", i.lo(), i.hi()),
+            Number::Array(a) => a.fmt_with(Number::to_source),
+            _ => self.to_string(),
+        }
+    }
+}
+
+impl Display for Number {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Number::Float(x) => write!(f, "{}", x),
+            Number::Rational(r) | Number::Decimal(r, _) => write!(f, "{}", fmt_exact(r)),
+            Number::Complex(z) if z.im == 0. => write!(f, "{}", z.re),
+            // in parentheses like fractions, so that a unit after it is not read as a part of it
+            Number::Complex(z) if z.re == 0. => write!(f, "({}i)", z.im),
+            Number::Complex(z) if z.im < 0. => write!(f, "({}-{}i)", z.re, -z.im),
+            Number::Complex(z) => write!(f, "({}+{}i)", z.re, z.im),
+            Number::Uncertain(u) => write!(f, "{}", u),
+            Number::Interval(i) => write!(f, "{}", i),
+            Number::Array(a) => write!(f, "{}", a),
+            Number::Bool(b) => write!(f, "{}", b),
+        }
+    }
+}
+
+/// Integer powers and square roots are computed separately, as they are more precise
+fn complex_pow(base: Complex64, p: Complex64) -> Complex64 {
+    match (p.re, p.im) {
+        (re, 0.) if re.fract() == 0. && re.abs() <= MAX_EXACT_POW as f64 => base.powi(re as i32),
+        (0.5, 0.) => base.sqrt(),
+        _ => base.powc(p),
+    }
+}
+
+/// Rounds to `digits` significant digits, halves away from zero
+fn round_to_digits(r: &BigRational, digits: u32) -> BigRational {
+    if r.is_zero() {
+        return r.clone();
+    }
+    let ten = BigRational::from_integer(BigInt::from(10));
+    // number of digits before the point: |r| is in [10^(exp-1), 10^exp)
+    let mut exp = r.numer().abs().to_string().len() as i64 - r.denom().to_string().len() as i64;
+    while r.abs() >= ten.pow(exp as i32) {
+        exp += 1;
+    }
+    while r.abs() < ten.pow(exp as i32 - 1) {
+        exp -= 1;
+    }
+    let shift = digits as i64 - exp;
+    let scale = ten.pow(shift as i32);
+    (r * &scale).round() / scale
+}
+
+/// `n`-th root of a positive `x` to `digits` significant digits, by Newton's method
+fn nth_root(x: &BigRational, n: u32, digits: u32) -> BigRational {
+    let n_big = BigRational::from_integer(BigInt::from(n));
+    let mut y = x.to_f64()
+        .map(|x| x.powf(1. / n as f64))
+        .filter(|y| y.is_normal())
+        .and_then(BigRational::from_float)
+        .unwrap_or_else(BigRational::one);
+    // each step doubles the correct digits, starting from those of a float
+    for _ in 0..64 {
+        let next = ((&n_big - BigRational::one()) * &y + x / y.pow(n as i32 - 1)) / &n_big;
+        let next = round_to_digits(&next, digits + 5);
+        if next == y {
+            break;
+        }
+        y = next;
+    }
+    round_to_digits(&y, digits)
+}
+
+/// Writes an exact number as a decimal if it has a finite one, as a fraction in parentheses otherwise
+pub fn fmt_exact(r: &BigRational) -> String {
+    if r.is_integer() {
+        return r.to_integer().to_string();
+    }
+    let (mut den, mut places) = (r.denom().clone(), 0usize);
+    for factor in [2, 5] {
+        let factor = BigInt::from(factor);
+        while den.is_multiple_of(&factor) {
+            den /= &factor;
+            places += 1;
+        }
+    }
+    if !den.is_one() {
+        return format!("({})", r);
+    }
+    // enough places for both 2s and 5s, trailing zeros are cut below
+    let digits = (r.abs() * BigRational::from_integer(BigInt::from(10).pow(places as u32)))
+        .to_integer()
+        .to_string();
+    let digits = format!("{:0>width$}", digits, width = places + 1);
+    let (int, fract) = digits.split_at(digits.len() - places);
+    let sign = if r.is_negative() { "-" } else { "" };
+    format!("{}{}.{}", sign, int, fract.trim_end_matches('0'))
+}
+
+fn complex_uncertain(x: &Number) -> ValComputeError {
+    ValComputeError::new(format!("Result for {} would be complex, which can not carry an uncertainty", x),
+                         ValComputeErrorType::Domain)
+}
+
+fn no_real_values(x: &Number) -> ValComputeError {
+    ValComputeError::new(format!("Result for {} has no real values", x), ValComputeErrorType::Domain)
+}
+
+/// Largest power of the base a literal is scaled by, either way.
+/// Exact numbers grow with it, and `1e999999` would take minutes to compute
+pub const MAX_LITERAL_EXPONENT: i32 = 10_000;
+
+/// Parses a number literal like `12.5e-3` or `0b101.1` exactly
+pub fn parse_literal(s: &str) -> Result<BigRational, String> {
+    let s = s.replace('_', "");
+    let (neg, s) = match s.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, s.as_str()),
+    };
+    let (base, s) = match s.get(..2) {
+        Some("0b") => (2, &s[2..]),
+        Some("0o") => (8, &s[2..]),
+        Some("0x") => (16, &s[2..]),
+        _ => (10, s),
+    };
+    let (mantissa, exp) = match s.find(['e', 'E']).filter(|_| base != 16) {
+        Some(i) => (&s[..i], s[i + 1..].trim_start_matches('+').parse::<i32>().map_err(|e| e.to_string())?),
+        None => (s, 0),
+    };
+    let (int, fract) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+    let digits = BigInt::parse_bytes(format!("{}{}", int, fract).as_bytes(), base)
+        .ok_or_else(|| format!("Wrong number format: {}", s))?;
+    let exp = i32::try_from(fract.len()).ok()
+        .and_then(|places| exp.checked_sub(places))
+        .filter(|exp| exp.abs() <= MAX_LITERAL_EXPONENT)
+        .ok_or_else(|| format!("Exponent of {} is too large, the limit is {}", s, MAX_LITERAL_EXPONENT))?;
+    let base = BigRational::from_integer(BigInt::from(base));
+    let mut ret = BigRational::from_integer(digits) * base.pow(exp);
+    if neg {
+        ret = -ret;
+    }
+    Ok(ret)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn exact(s: &str) -> Number {
+        Number::rational(parse_literal(s).unwrap())
+    }
+
+    #[test]
+    fn literals() {
+        assert_eq!(parse_literal("12.5e-1").unwrap(), BigRational::new(5.into(), 4.into()));
+        assert_eq!(parse_literal("0b101.1").unwrap(), BigRational::new(11.into(), 2.into()));
+        assert_eq!(parse_literal("1_000").unwrap(), BigRational::from_integer(1000.into()));
+        assert_eq!(fmt_exact(&parse_literal("-0.0625").unwrap()), "-0.0625");
+        assert_eq!(fmt_exact(&parse_literal("1.5e30").unwrap()), "1500000000000000000000000000000");
+        assert_eq!(fmt_exact(&BigRational::new(1.into(), 3.into())), "(1/3)");
+        assert!(parse_literal("1.0e999999").is_err());
+        assert!(parse_literal("1e-10001").is_err());
+        assert_eq!(Number::from_exact(parse_literal("1e400").unwrap(), Backend::Float, 50).to_f64(), f64::INFINITY);
+    }
+
+    #[test]
+    fn exact_arithmetic() {
+        assert!(exact("0.1").add(&exact("0.2")).approx_eq(&exact("0.3"), 0.));
+        assert!(!Number::Float(0.1 + 0.2).approx_eq(&Number::Float(0.3), 0.));
+        assert_eq!(exact("2").pow(&exact("100")).to_string(), "1267650600228229401496703205376");
+        assert!(exact("1").div(&exact("0")).is_err());
+        assert_eq!(exact("-7").modulo(&exact("3")).unwrap().to_string(), "2");
+        assert_eq!(exact("1").div(&exact("3")).unwrap().to_string(), "(1/3)");
+        assert_eq!(exact("1").add(&Number::Float(0.5)).to_string(), "1.5");
+    }
+
+    #[test]
+    fn decimals() {
+        let third = Number::decimal(BigRational::new(1.into(), 3.into()), 30);
+        assert_eq!(third.to_string(), format!("0.{}", "3".repeat(30)));
+        let two = Number::decimal(BigRational::from_integer(2.into()), 40);
+        let sqrt2 = two.pow(&exact("0.5"));
+        assert_eq!(sqrt2.to_string(), "1.41421356237309504880168872420969807857");
+        assert_eq!(Number::decimal(parse_literal("123456").unwrap(), 3).to_string(), "123000");
+        assert_eq!(Number::decimal(parse_literal("0.00123456").unwrap(), 2).to_string(), "0.0012");
+    }
+
+    #[test]
+    fn complex() {
+        let i = Number::complex(0., 1.);
+        assert_eq!(i.mul(&i).to_string(), "-1");
+        assert_eq!(exact("-9").pow(&exact("0.5")).to_string(), "(3i)");
+        assert_eq!(Number::complex(1., -1.).add(&exact("0.5")).to_string(), "(1.5-1i)");
+        assert!(i.partial_cmp(&exact("1")).is_none());
+        assert!(Number::Float(-1.).map_analytic(f64::ln, Complex64::ln, Interval::ln, decimal::ln).unwrap().approx_eq(&Number::complex(0., std::f64::consts::PI), 1e-12));
+    }
+}
@@ -0,0 +1,124 @@
+//! Numbers with a standard uncertainty, propagated to first order.
+//! Every number written with `±` is an independent source of uncertainty,
+//! and results remember how much each source contributes to them,
+//! so a value that is used twice is correlated with itself (`x - x` is exactly 0)
+
+use std::fmt::Display;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Gives every measured value its own source of uncertainty
+static NEXT_SOURCE: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Uncertain {
+    value: f64,
+    /// Source and its contribution to the uncertainty: the derivative of the value
+    /// by the source times the source's standard uncertainty. Sorted by source
+    parts: Vec<(u64, f64)>,
+}
+
+impl Uncertain {
+    /// Measured value, independent of all the others
+    pub fn new(value: f64, uncertainty: f64) -> Self {
+        let source = NEXT_SOURCE.fetch_add(1, Ordering::Relaxed);
+        Uncertain { value, parts: vec![(source, uncertainty.abs())] }
+    }
+
+    /// Value known exactly
+    pub fn exact(value: f64) -> Self {
+        Uncertain { value, parts: Vec::new() }
+    }
+
+    pub fn value(&self) -> f64 {
+        self.value
+    }
+
+    /// Standard uncertainty, the contributions of the sources added in quadrature
+    pub fn uncertainty(&self) -> f64 {
+        self.parts.iter().map(|(_, part)| part * part).sum::<f64>().sqrt()
+    }
+
+    /// Result of a function of one variable with the given value and derivative
+    pub fn map(&self, value: f64, derivative: f64) -> Self {
+        let parts = self.parts.iter().map(|(source, part)| (*source, part * derivative)).collect();
+        Uncertain { value, parts }
+    }
+
+    /// Applies a function of one variable, finding its derivative numerically
+    pub fn map_f64(&self, f: impl Fn(f64) -> f64) -> Self {
+        let x = self.value;
+        let h = f64::EPSILON.sqrt() * x.abs().max(1.);
+        let derivative = if self.parts.is_empty() { 0. } else { (f(x + h) - f(x - h)) / (2. * h) };
+        self.map(f(x), derivative)
+    }
+
+    /// Result of a function of two variables with the given value and partial derivatives
+    pub fn combine(&self, rhs: &Uncertain, value: f64, (d_lhs, d_rhs): (f64, f64)) -> Self {
+        let mut parts: Vec<(u64, f64)> = Vec::with_capacity(self.parts.len() + rhs.parts.len());
+        let (mut i, mut j) = (0, 0);
+        while i < self.parts.len() || j < rhs.parts.len() {
+            let lhs_part = self.parts.get(i).copied();
+            let rhs_part = rhs.parts.get(j).copied();
+            let part = match (lhs_part, rhs_part) {
+                (Some((a, x)), Some((b, y))) if a == b => {
+                    i += 1;
+                    j += 1;
+                    (a, x * d_lhs + y * d_rhs)
+                }
+                (Some((a, x)), Some((b, _))) if a < b => {
+                    i += 1;
+                    (a, x * d_lhs)
+                }
+                (Some((a, x)), None) => {
+                    i += 1;
+                    (a, x * d_lhs)
+                }
+                (_, Some((b, y))) => {
+                    j += 1;
+                    (b, y * d_rhs)
+                }
+                (None, None) => unreachable!(),
+            };
+            parts.push(part);
+        }
+        Uncertain { value, parts }
+    }
+}
+
+impl Display for Uncertain {
+    /// Rounds the uncertainty to two significant digits and the value to the same place
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let sigma = self.uncertainty();
+        if sigma == 0. || !sigma.is_finite() || !self.value.is_finite() {
+            return write!(f, "({}±{})", self.value, sigma);
+        }
+        let exp = sigma.log10().floor() as i32;
+        if exp <= 1 {
+            let places = (1 - exp) as usize;
+            write!(f, "({:.*}±{:.*})", places, self.value, places, sigma)
+        } else {
+            let step = 10f64.powi(exp - 1);
+            write!(f, "({}±{})", (self.value / step).round() * step, (sigma / step).round() * step)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn propagation() {
+        let x = Uncertain::new(2., 0.1);
+        let y = Uncertain::new(3., 0.2);
+        let sum = x.combine(&y, 5., (1., 1.));
+        assert!((sum.uncertainty() - (0.01f64 + 0.04).sqrt()).abs() < 1e-12);
+        // x - x does not depend on x
+        assert_eq!(x.combine(&x, 0., (1., -1.)).uncertainty(), 0.);
+        let square = x.map_f64(|x| x * x);
+        assert!((square.uncertainty() - 0.4).abs() < 1e-6);
+        assert_eq!(Uncertain::new(9.81, 0.0223).to_string(), "(9.810±0.022)");
+        assert_eq!(Uncertain::new(1234.4, 56.).to_string(), "(1234±56)");
+        assert_eq!(Uncertain::new(12345., 560.).to_string(), "(12350±560)");
+    }
+}
@@ -1,51 +1,268 @@
+use std::ops;
+
 pub mod base_units{
     use super::Unit;
-    pub const D  :Unit = Unit{dim:[0., 0., 0., 0., 0., 0., 0.]};
-    pub const S  :Unit = Unit{dim:[1., 0., 0., 0., 0., 0., 0.]};
-    pub const M  :Unit = Unit{dim:[0., 1., 0., 0., 0., 0., 0.]};
-    pub const KG :Unit = Unit{dim:[0., 0., 1., 0., 0., 0., 0.]};
-    pub const A  :Unit = Unit{dim:[0., 0., 0., 1., 0., 0., 0.]};
-    pub const K  :Unit = Unit{dim:[0., 0., 0., 0., 1., 0., 0.]};
-    pub const MOL:Unit = Unit{dim:[0., 0., 0., 0., 0., 1., 0.]};
-    pub const CD :Unit = Unit{dim:[0., 0., 0., 0., 0., 0., 1.]};
+    pub const D  :Unit = Unit::from_ints([ 0,  0,  0,  0,  0,  0,  0]);
+    pub const S  :Unit = Unit::from_ints([ 1,  0,  0,  0,  0,  0,  0]);
+    pub const M  :Unit = Unit::from_ints([ 0,  1,  0,  0,  0,  0,  0]);
+    pub const KG :Unit = Unit::from_ints([ 0,  0,  1,  0,  0,  0,  0]);
+    pub const A  :Unit = Unit::from_ints([ 0,  0,  0,  1,  0,  0,  0]);
+    pub const K  :Unit = Unit::from_ints([ 0,  0,  0,  0,  1,  0,  0]);
+    pub const MOL:Unit = Unit::from_ints([ 0,  0,  0,  0,  0,  1,  0]);
+    pub const CD :Unit = Unit::from_ints([ 0,  0,  0,  0,  0,  0,  1]);
+}
+
+/// Coherent SI derived units. Radian and steradian are dimensionless and not listed
+pub mod derived_units{
+    use super::Unit;
+    pub const HZ :Unit = Unit::from_ints([-1,  0,  0,  0,  0,  0,  0]);
+    pub const N  :Unit = Unit::from_ints([-2,  1,  1,  0,  0,  0,  0]);
+    pub const PA :Unit = Unit::from_ints([-2, -1,  1,  0,  0,  0,  0]);
+    pub const J  :Unit = Unit::from_ints([-2,  2,  1,  0,  0,  0,  0]);
+    pub const W  :Unit = Unit::from_ints([-3,  2,  1,  0,  0,  0,  0]);
+    pub const C  :Unit = Unit::from_ints([ 1,  0,  0,  1,  0,  0,  0]);
+    pub const V  :Unit = Unit::from_ints([-3,  2,  1, -1,  0,  0,  0]);
+    pub const F  :Unit = Unit::from_ints([ 4, -2, -1,  2,  0,  0,  0]);
+    pub const OHM:Unit = Unit::from_ints([-3,  2,  1, -2,  0,  0,  0]);
+    pub const SIEMENS:Unit = Unit::from_ints([ 3, -2, -1,  2,  0,  0,  0]);
+    pub const WB :Unit = Unit::from_ints([-2,  2,  1, -1,  0,  0,  0]);
+    pub const T  :Unit = Unit::from_ints([-2,  0,  1, -1,  0,  0,  0]);
+    pub const H  :Unit = Unit::from_ints([-2,  2,  1, -2,  0,  0,  0]);
+    pub const LM :Unit = Unit::from_ints([ 0,  0,  0,  0,  0,  0,  1]);
+    pub const LX :Unit = Unit::from_ints([ 0, -2,  0,  0,  0,  0,  1]);
+    pub const BQ :Unit = Unit::from_ints([-1,  0,  0,  0,  0,  0,  0]);
+    pub const GY :Unit = Unit::from_ints([-2,  2,  0,  0,  0,  0,  0]);
+    pub const SV :Unit = Unit::from_ints([-2,  2,  0,  0,  0,  0,  0]);
+    pub const KAT:Unit = Unit::from_ints([-1,  0,  0,  0,  0,  1,  0]);
 }
 
+/// SI prefixes with their factors, from quecto to quetta.
+/// Micro can be written as the micro sign, the greek letter mu or `u`
+pub const SI_PREFIXES: [(&str, f64); 26] = [
+    ("q", 1e-30), ("r", 1e-27), ("y", 1e-24), ("z", 1e-21), ("a", 1e-18), ("f", 1e-15),
+    ("p", 1e-12), ("n", 1e-9), ("\u{b5}", 1e-6), ("\u{3bc}", 1e-6), ("u", 1e-6), ("m", 1e-3),
+    ("c", 1e-2), ("d", 1e-1), ("da", 1e1), ("h", 1e2), ("k", 1e3), ("M", 1e6),
+    ("G", 1e9), ("T", 1e12), ("P", 1e15), ("E", 1e18), ("Z", 1e21), ("Y", 1e24),
+    ("R", 1e27), ("Q", 1e30),
+];
+
+/// Exact fraction used for powers of units, always kept in lowest terms
+/// with a positive denominator
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Ratio{
+    num: i32,
+    den: i32,
+}
+
+impl Ratio {
+    pub const ZERO: Ratio = Ratio::int(0);
+    pub const ONE: Ratio = Ratio::int(1);
+    /// Largest denominator `from_f64` looks for
+    const MAX_DEN: i64 = 1000;
+
+    pub const fn int(n: i32) -> Ratio{
+        Ratio{num: n, den: 1}
+    }
+
+    /// # Panics
+    /// If `den` is 0 or the fraction does not fit in `i32` in lowest terms
+    pub fn new(num: i64, den: i64) -> Ratio{
+        assert!(den != 0, "denominator of a ratio can not be 0");
+        Self::checked_new(num, den).expect("unit power is too large")
+    }
+
+    /// Like [`Ratio::new`], but returns `None` instead of panicking
+    pub fn checked_new(num: i64, den: i64) -> Option<Ratio>{
+        if den == 0 {
+            return None;
+        }
+        let g = gcd(num, den) * den.signum();
+        Some(Ratio{num: i32::try_from(num / g).ok()?, den: i32::try_from(den / g).ok()?})
+    }
+
+    /// Sum, or `None` if it does not fit
+    pub fn checked_add(self, rhs: Ratio) -> Option<Ratio>{
+        let (a, b, c, d) = (self.num as i64, self.den as i64, rhs.num as i64, rhs.den as i64);
+        Self::checked_new((a * d).checked_add(c * b)?, b * d)
+    }
+
+    /// Difference, or `None` if it does not fit
+    pub fn checked_sub(self, rhs: Ratio) -> Option<Ratio>{
+        let (a, b, c, d) = (self.num as i64, self.den as i64, rhs.num as i64, rhs.den as i64);
+        Self::checked_new((a * d).checked_sub(c * b)?, b * d)
+    }
+
+    /// Product, or `None` if it does not fit
+    pub fn checked_mul(self, rhs: Ratio) -> Option<Ratio>{
+        Self::checked_new(self.num as i64 * rhs.num as i64, self.den as i64 * rhs.den as i64)
+    }
+
+    /// Finds a fraction with a small denominator equal to `x`,
+    /// so that `1/3` computed in floats becomes exactly one third
+    pub fn from_f64(x: f64) -> Option<Ratio>{
+        if !x.is_finite() || x.abs() > i32::MAX as f64 {
+            return None;
+        }
+        // continued fraction expansion, stopped once close enough
+        let (mut h0, mut h1, mut k0, mut k1) = (0i64, 1i64, 1i64, 0i64);
+        let mut rest = x;
+        loop {
+            let a = rest.floor();
+            let (h, k) = (a as i64 * h1 + h0, a as i64 * k1 + k0);
+            if k > Self::MAX_DEN {
+                return None;
+            }
+            if (h as f64 / k as f64 - x).abs() <= 1e-9 * x.abs().max(1.) {
+                return Ratio::checked_new(h, k);
+            }
+            (h0, h1, k0, k1) = (h1, h, k1, k);
+            rest = 1. / (rest - a);
+        }
+    }
+
+    pub fn numer(&self) -> i32{
+        self.num
+    }
+    pub fn denom(&self) -> i32{
+        self.den
+    }
+    pub fn to_f64(&self) -> f64{
+        self.num as f64 / self.den as f64
+    }
+    pub fn is_zero(&self) -> bool{
+        self.num == 0
+    }
+    pub fn is_integer(&self) -> bool{
+        self.den == 1
+    }
+    pub fn abs(&self) -> Ratio{
+        Ratio{num: self.num.abs(), den: self.den}
+    }
+}
+
+fn gcd(a: i64, b: i64) -> i64{
+    let (mut a, mut b) = (a.abs(), b.abs());
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a.max(1)
+}
+
+impl std::fmt::Display for Ratio {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.is_integer() {
+            write!(f, "{}", self.num)
+        }else{
+            write!(f, "{}/{}", self.num, self.den)
+        }
+    }
+}
+
+/// # Panics
+/// If the sum does not fit, see [`Ratio::checked_add`]
+impl ops::Add for Ratio {
+    type Output = Ratio;
+    fn add(self, rhs: Self) -> Self::Output {
+        self.checked_add(rhs).expect("unit power is too large")
+    }
+}
+
+impl ops::Sub for Ratio {
+    type Output = Ratio;
+    fn sub(self, rhs: Self) -> Self::Output {
+        self.checked_sub(rhs).expect("unit power is too large")
+    }
+}
+
+/// # Panics
+/// If the product does not fit, see [`Ratio::checked_mul`]
+impl ops::Mul for Ratio {
+    type Output = Ratio;
+    fn mul(self, rhs: Self) -> Self::Output {
+        self.checked_mul(rhs).expect("unit power is too large")
+    }
+}
+
+impl ops::Neg for Ratio {
+    type Output = Ratio;
+    fn neg(self) -> Self::Output {
+        Ratio{num: self.num.checked_neg().expect("unit power is too large"), den: self.den}
+    }
+}
 
 /// # Struct that represents number's Unit
-/// Essentially, it's a vector with 7 floats,
+/// Essentially, it's a vector with 7 exact fractions,
 /// each representing power of one of the base units
+/// (second, meter, kilogram, ampere, kelvin, mole, candela)
 /// ## E. g. mol/m^3 is 
 /// ```rust
 /// use syntcalc::tokens::val::unit::Unit;
-/// Unit::new([0., 0., -3., 0., 0., 1., 0.]);
+/// Unit::from_ints([0, -3, 0, 0, 0, 1, 0]);
 /// ```
 #[derive(Clone, Copy, Debug)]
 pub struct Unit{
-    pub dim: [f64;7],
+    pub dim: [Ratio;7],
 }
 
 impl Unit {
-    pub fn new(dim:[f64;7]) -> Unit{
+    pub fn new(dim:[Ratio;7]) -> Unit{
         Unit{dim}
     }
 
-    pub fn pow(self, p:f64) -> Self{
+    pub const fn from_ints(dim: [i32;7]) -> Unit{
+        let mut ret = [Ratio::ZERO; 7];
+        let mut i = 0;
+        while i < 7 {
+            ret[i] = Ratio::int(dim[i]);
+            i += 1;
+        }
+        Unit{dim: ret}
+    }
+
+    /// # Panics
+    /// If a power of the result does not fit, see [`Unit::checked_pow`]
+    pub fn pow(self, p: Ratio) -> Self{
+        self.checked_pow(p).expect("unit power is too large")
+    }
+
+    /// Raises the unit to a power, `None` if a power of the result does not fit
+    pub fn checked_pow(self, p: Ratio) -> Option<Self>{
         let mut ret = self;
         for i in 0..7{
-            ret.dim[i] *= p;
+            ret.dim[i] = ret.dim[i].checked_mul(p)?;
         }
-        ret
+        Some(ret)
+    }
+
+    /// Product of units, `None` if a power of the result does not fit
+    pub fn checked_mul(self, rhs: Unit) -> Option<Self>{
+        let mut ret = self;
+        for i in 0..7{
+            ret.dim[i] = ret.dim[i].checked_add(rhs.dim[i])?;
+        }
+        Some(ret)
     }
 
-    pub fn same_unit(self, other: &Unit, precision: f64) -> bool{
-        let mut flag = true;
-        for i in 0..7 {
-            flag = flag && self.dim[i] - precision/2. < other.dim[i] &&
-                self.dim[i] + precision/2. > other.dim[i];  
+    /// Quotient of units, `None` if a power of the result does not fit
+    pub fn checked_div(self, rhs: Unit) -> Option<Self>{
+        let mut ret = self;
+        for i in 0..7{
+            ret.dim[i] = ret.dim[i].checked_sub(rhs.dim[i])?;
         }
-        flag
+        Some(ret)
+    }
+
+    pub fn powi(self, p: i32) -> Self{
+        self.pow(Ratio::int(p))
+    }
+
+    pub fn same_unit(self, other: &Unit) -> bool{
+        self.dim == other.dim
+    }
+
+    pub fn is_dimensionless(&self) -> bool{
+        self.dim.iter().all(Ratio::is_zero)
     }
-    
 }
 
 
@@ -55,8 +272,6 @@ impl std::cmp::PartialEq for Unit {
     }
 }
 
-use std::ops;
-
 impl ops::Add for Unit{
     type Output = Option<Unit>;
     fn add(self, rhs: Self) -> Self::Output {
@@ -81,39 +296,78 @@ impl ops::Sub for Unit{
 
 impl ops::Mul for Unit{
     type Output = Unit;
+    // multiplying units adds up their powers
+    #[allow(clippy::suspicious_arithmetic_impl)]
     fn mul(self, rhs: Self) -> Self::Output {
         let mut ret = self;
         for i in 0..7{
-            ret.dim[i] += rhs.dim[i];
+            ret.dim[i] = ret.dim[i] + rhs.dim[i];
         };
         ret
     }
 }
 
 impl ops::MulAssign for Unit {
+    #[allow(clippy::suspicious_op_assign_impl)]
     fn mul_assign(&mut self, rhs: Self) {
         for i in 0..7{
-            self.dim[i] += rhs.dim[i];
+            self.dim[i] = self.dim[i] + rhs.dim[i];
         }
     }
 }
 
 impl ops::Div for Unit{
     type Output = Unit;
+    #[allow(clippy::suspicious_arithmetic_impl)]
     fn div(self, rhs: Self) -> Self::Output {
         let mut ret = self;
         for i in 0..7{
-            ret.dim[i] -= rhs.dim[i];
+            ret.dim[i] = ret.dim[i] - rhs.dim[i];
         };
         ret
     }
 }
 
 impl ops::DivAssign for Unit {
+    #[allow(clippy::suspicious_op_assign_impl)]
     fn div_assign(&mut self, rhs: Self) {
         for i in 0..7{
-            self.dim[i] -= rhs.dim[i];
+            self.dim[i] = self.dim[i] - rhs.dim[i];
         }
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::base_units::*;
+
+    #[test]
+    fn ratios(){
+        assert_eq!(Ratio::new(2, -4), Ratio::new(-1, 2));
+        assert_eq!(Ratio::from_f64(1./3.), Some(Ratio::new(1, 3)));
+        assert_eq!(Ratio::from_f64(-2.5), Some(Ratio::new(-5, 2)));
+        assert_eq!(Ratio::from_f64(3.), Some(Ratio::int(3)));
+        assert_eq!(Ratio::from_f64(std::f64::consts::PI), None);
+        assert_eq!(Ratio::new(1, 3) * Ratio::int(3), Ratio::ONE);
+        assert_eq!(Ratio::new(1, 2).to_string(), "1/2");
+    }
+
+    #[test]
+    fn exact_powers(){
+        let third = Ratio::from_f64(1./3.).unwrap();
+        assert_eq!(M.pow(third).powi(3), M);
+        assert_eq!(M.powi(2).pow(Ratio::new(1, 2)), M);
+        assert!((M / M).is_dimensionless());
+    }
+
+    #[test]
+    fn overflowing_powers(){
+        let huge = M.powi(2_000_000_000);
+        assert_eq!(huge.checked_mul(huge), None);
+        assert_eq!(huge.checked_pow(Ratio::int(2)), None);
+        assert_eq!(huge.checked_div(M.powi(-2_000_000_000)), None);
+        assert_eq!(huge.checked_div(huge), Some(D));
+        assert_eq!(Ratio::checked_new(i64::MAX, 1), None);
+    }
+}